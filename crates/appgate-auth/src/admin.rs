@@ -0,0 +1,73 @@
+use appgate_ipc::pdp::{session_admin_server::SessionAdmin, ExplainRequest, ExplainResponse, RevokeRequest, RevokeResponse};
+use appgate_policy::{engine::PolicyEngine, Context};
+use chrono::Utc;
+use std::{collections::HashMap, sync::Arc};
+use tonic::{Request, Response, Status};
+
+use crate::{
+    pdp::{parse_peer, respond, rule_trace, PdpSvc},
+    store::SessionStore,
+};
+
+/// Operator-facing session administration (revocation, explanations), served on its own socket
+pub struct AdminSvc {
+    pub store: Arc<SessionStore>,
+    /// The PDP whose decisions `Explain` traces
+    pub pdp: Arc<PdpSvc>,
+}
+
+#[tonic::async_trait]
+impl SessionAdmin for AdminSvc {
+    async fn revoke(&self, req: Request<RevokeRequest>) -> Result<Response<RevokeResponse>, Status> {
+        let r = req.into_inner();
+        let revoked = match (r.sid.is_empty(), r.sub.is_empty()) {
+            (false, true) => self.store.revoke(&r.sid).map(usize::from),
+            (true, false) => self.store.revoke_sub(&r.sub),
+            _ => return Err(Status::invalid_argument("set exactly one of sid or sub")),
+        }
+        .map_err(|e| Status::internal(e.to_string()))?;
+        tracing::info!(sid = %r.sid, sub = %r.sub, revoked, "admin revocation");
+        Ok(Response::new(RevokeResponse { revoked: revoked as u32 }))
+    }
+
+    async fn explain(&self, req: Request<ExplainRequest>) -> Result<Response<ExplainResponse>, Status> {
+        // served here rather than on the PDP socket: a `sub` selects anyone's session
+        let pdp = &self.pdp;
+        let e = req.into_inner();
+        let r = e.request.unwrap_or_default();
+        // whose groups: the token's session, the subject's newest live session, or the given groups
+        let rec = if !r.session_token.is_empty() {
+            match pdp.session(&r) {
+                Ok(rec) => Some(rec),
+                Err(reason) => {
+                    let decision = Some(pdp.unauthenticated(&r, &reason));
+                    let identity = "no usable session".to_string();
+                    return Ok(Response::new(ExplainResponse { decision, identity, ..Default::default() }));
+                }
+            }
+        } else if !e.sub.is_empty() {
+            let live = pdp.store.live_sessions_of(&e.sub).map_err(|err| Status::unavailable(err.to_string()))?;
+            let rec = live.into_iter().next().ok_or_else(|| Status::not_found(format!("no live session for sub {}", e.sub)))?;
+            Some(rec)
+        } else {
+            None
+        };
+        let (identity, groups, claims, expires_at) = match rec {
+            Some(rec) => (format!("sub {} (session {})", rec.sub, rec.sid), rec.groups, rec.claims, Some(rec.expires_at)),
+            None => ("given groups".to_string(), e.groups, HashMap::new(), None),
+        };
+
+        let no_attributes = HashMap::new();
+        let attributes = r.attributes.as_ref().map_or(&no_attributes, |a| &a.kv);
+        let peer = parse_peer(&r.peer);
+        let cx = Context { protocol: &r.protocol, resource: &r.resource, groups: &groups, claims: &claims, attributes, peer, now: Utc::now() };
+        let x = pdp.policy.load().explain(&cx);
+        let decision = respond(x.decision, claims, &groups, expires_at);
+        Ok(Response::new(ExplainResponse {
+            decision: Some(decision),
+            identity,
+            groups,
+            rules: x.rules.into_iter().map(rule_trace).collect(),
+        }))
+    }
+}
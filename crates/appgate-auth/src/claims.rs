@@ -0,0 +1,85 @@
+use appgate_ctrl::ClaimMapping;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+/// Extracts groups and identity claims from verified ID token claims per `[auth.claims]`
+#[derive(Debug, Clone)]
+pub struct ClaimMapper {
+    group_paths: Vec<String>,
+    strip_prefix: Vec<String>,
+    rename: HashMap<String, String>,
+    project: Vec<(String, String)>,
+}
+
+impl Default for ClaimMapper {
+    fn default() -> Self {
+        Self::new(&ClaimMapping::default(), "")
+    }
+}
+
+impl ClaimMapper {
+    /// Build a mapper, expanding `{client_id}` in every path
+    pub fn new(cfg: &ClaimMapping, client_id: &str) -> Self {
+        // client ids are a single pointer segment: escape per RFC 6901
+        let seg = client_id.replace('~', "~0").replace('/', "~1");
+        let expand = |p: &String| p.replace("{client_id}", &seg);
+        let mut project: Vec<_> = cfg.claims.iter().map(|(k, p)| (k.clone(), expand(p))).collect();
+        project.sort();
+        Self {
+            group_paths: cfg.groups.iter().map(expand).collect(),
+            strip_prefix: cfg.strip_prefix.clone(),
+            rename: cfg.rename.clone(),
+            project,
+        }
+    }
+
+    /// Groups found at the configured paths, prefix-stripped, renamed and de-duplicated
+    pub fn groups(&self, claims: &Map<String, Value>) -> Vec<String> {
+        let mut out: Vec<String> = Vec::new();
+        for path in &self.group_paths {
+            let raw: Vec<&str> = match lookup(claims, path) {
+                Some(Value::String(s)) => vec![s.as_str()],
+                Some(Value::Array(a)) => a.iter().filter_map(Value::as_str).collect(),
+                _ => continue,
+            };
+            for g in raw {
+                let g = self.strip_prefix.iter().find_map(|p| g.strip_prefix(p.as_str())).unwrap_or(g);
+                let g = self.rename.get(g).map(String::as_str).unwrap_or(g);
+                if !g.is_empty() && !out.iter().any(|o| o == g) {
+                    out.push(g.to_string());
+                }
+            }
+        }
+        out
+    }
+
+    /// Scalar claims projected into `DecisionResponse.claims`
+    pub fn project(&self, claims: &Map<String, Value>) -> HashMap<String, String> {
+        self.project
+            .iter()
+            .filter_map(|(name, path)| {
+                let v = match lookup(claims, path)? {
+                    Value::String(s) => s.clone(),
+                    v @ (Value::Number(_) | Value::Bool(_)) => v.to_string(),
+                    _ => return None,
+                };
+                Some((name.clone(), v))
+            })
+            .collect()
+    }
+}
+
+/// Resolve a JSON pointer against a claims object
+fn lookup<'a>(claims: &'a Map<String, Value>, path: &str) -> Option<&'a Value> {
+    let rest = path.strip_prefix('/')?;
+    let (first, tail) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, ""),
+    };
+    let v = claims.get(&first.replace("~1", "/").replace("~0", "~"))?;
+    if tail.is_empty() {
+        Some(v)
+    } else {
+        v.pointer(tail)
+    }
+}
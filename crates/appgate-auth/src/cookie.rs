@@ -0,0 +1,176 @@
+use anyhow::{bail, Context, Result};
+use appgate_ctrl::SessionConfig;
+use base64::{
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+    Engine,
+};
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    XChaCha20Poly1305, XNonce,
+};
+use rand::RngCore;
+use std::collections::HashMap;
+use thiserror::Error;
+
+use crate::session::Session;
+
+/// Format marker at the start of every sealed cookie
+const VERSION: &str = "v1";
+const NONCE_LEN: usize = 24;
+const TAG_LEN: usize = 16;
+
+/// Why a session cookie could not be opened; the message doubles as the PDP deny reason
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum UnsealError {
+    #[error("no session")]
+    Empty,
+    #[error("malformed session cookie")]
+    Malformed,
+    #[error("session sealed with unknown key {0}")]
+    UnknownKey(String),
+    #[error("session cookie failed authentication")]
+    Tampered,
+    #[error("session expired")]
+    Expired,
+}
+
+/// Seals sessions into cookie values (and secrets for storage) with XChaCha20-Poly1305.
+///
+/// Cookies look like `v1.<kid>.<base64url(nonce || ciphertext)>`; the version and key id are
+/// bound in as associated data so they cannot be swapped without failing authentication.
+pub struct CookieSealer {
+    keys: HashMap<String, XChaCha20Poly1305>,
+    current: String,
+}
+
+impl CookieSealer {
+    /// Build a sealer from raw 32-byte keys, sealing new cookies with `current`
+    pub fn new(keys: HashMap<String, [u8; 32]>, current: &str) -> Result<Self> {
+        if !keys.contains_key(current) {
+            bail!("current session key {current} is not configured");
+        }
+        if let Some(bad) = keys.keys().find(|k| k.is_empty() || !k.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')) {
+            bail!("session key id {bad:?} must be non-empty [A-Za-z0-9_-]");
+        }
+        let keys = keys.into_iter().map(|(id, k)| (id, XChaCha20Poly1305::new(&k.into()))).collect();
+        Ok(Self { keys, current: current.to_string() })
+    }
+
+    /// Build a sealer from `[auth.session]`, or a random single-process key if none are configured
+    pub fn from_config(cfg: &SessionConfig) -> Result<Self> {
+        if cfg.keys.is_empty() {
+            tracing::warn!("no [auth.session] keys configured; using an ephemeral key, sessions will not survive a restart");
+            return Ok(Self::ephemeral());
+        }
+        let mut keys = HashMap::new();
+        for (id, b64) in cfg.resolve()? {
+            let raw = STANDARD.decode(b64.trim()).with_context(|| format!("session key {id} is not base64"))?;
+            let key: [u8; 32] = raw.try_into().map_err(|_| anyhow::anyhow!("session key {id} must be 32 bytes"))?;
+            keys.insert(id, key);
+        }
+        Self::new(keys, &cfg.current_key)
+    }
+
+    /// Sealer with one random key, for tests and unconfigured single-node setups
+    pub fn ephemeral() -> Self {
+        let mut key = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut key);
+        Self::new([("ephemeral".to_string(), key)].into_iter().collect(), "ephemeral").unwrap()
+    }
+
+    fn aad(purpose: &str, kid: &str) -> Vec<u8> {
+        format!("appgate-{purpose}:{VERSION}:{kid}").into_bytes()
+    }
+
+    pub(crate) fn seal_bytes(&self, purpose: &str, plaintext: &[u8]) -> String {
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let aad = Self::aad(purpose, &self.current);
+        let ct = self.keys[&self.current]
+            .encrypt(XNonce::from_slice(&nonce), Payload { msg: plaintext, aad: &aad })
+            .expect("XChaCha20-Poly1305 encryption is infallible for in-memory buffers");
+        let mut blob = nonce.to_vec();
+        blob.extend_from_slice(&ct);
+        format!("{VERSION}.{}.{}", self.current, URL_SAFE_NO_PAD.encode(blob))
+    }
+
+    pub(crate) fn open_bytes(&self, purpose: &str, sealed: &str) -> Result<Vec<u8>, UnsealError> {
+        if sealed.is_empty() {
+            return Err(UnsealError::Empty);
+        }
+        let mut parts = sealed.splitn(3, '.');
+        let (Some(VERSION), Some(kid), Some(data)) = (parts.next(), parts.next(), parts.next()) else {
+            return Err(UnsealError::Malformed);
+        };
+        let cipher = self.keys.get(kid).ok_or_else(|| UnsealError::UnknownKey(kid.to_string()))?;
+        let blob = URL_SAFE_NO_PAD.decode(data).map_err(|_| UnsealError::Malformed)?;
+        if blob.len() < NONCE_LEN + TAG_LEN {
+            return Err(UnsealError::Malformed);
+        }
+        let (nonce, ct) = blob.split_at(NONCE_LEN);
+        let aad = Self::aad(purpose, kid);
+        cipher
+            .decrypt(XNonce::from_slice(nonce), Payload { msg: ct, aad: &aad })
+            .map_err(|_| UnsealError::Tampered)
+    }
+
+    /// Encrypt and authenticate `s` with the current key
+    pub fn seal(&self, s: &Session) -> String {
+        self.seal_bytes("session", &serde_json::to_vec(s).expect("session serialises"))
+    }
+
+    /// Open a cookie produced by [`CookieSealer::seal`], rejecting tampered or expired sessions
+    pub fn unseal(&self, cookie: &str) -> Result<Session, UnsealError> {
+        let plaintext = self.open_bytes("session", cookie)?;
+        let s: Session = serde_json::from_slice(&plaintext).map_err(|_| UnsealError::Malformed)?;
+        if s.expires_at <= chrono::Utc::now() {
+            return Err(UnsealError::Expired);
+        }
+        Ok(s)
+    }
+
+    /// Encrypt a secret (e.g. a refresh token) for storage at rest
+    pub fn seal_secret(&self, secret: &str) -> String {
+        self.seal_bytes("secret", secret.as_bytes())
+    }
+
+    /// Decrypt a value produced by [`CookieSealer::seal_secret`]
+    pub fn open_secret(&self, sealed: &str) -> Result<String, UnsealError> {
+        String::from_utf8(self.open_bytes("secret", sealed)?).map_err(|_| UnsealError::Malformed)
+    }
+}
+
+/// Attributes of the session cookie handed to browsers
+#[derive(Debug, Clone)]
+pub struct CookieSettings {
+    pub name: String,
+    pub domain: String,
+    /// Validity window of a session, extended by each refresh
+    pub ttl_seconds: u64,
+    /// Hard cap on the lifetime of a refreshable session
+    pub max_lifetime_seconds: u64,
+}
+
+impl CookieSettings {
+    /// `Set-Cookie` value carrying `value` for `max_age` seconds
+    pub fn set_cookie(&self, value: &str, max_age: u64) -> String {
+        format!(
+            "{}={}; Domain={}; Path=/; Max-Age={}; HttpOnly; Secure; SameSite=Lax",
+            self.name, value, self.domain, max_age
+        )
+    }
+
+    /// `Set-Cookie` value that removes the session cookie
+    pub fn clear_cookie(&self) -> String {
+        format!("{}=; Domain={}; Path=/; Max-Age=0; HttpOnly; Secure; SameSite=Lax", self.name, self.domain)
+    }
+
+    /// Our cookie's value within a `Cookie` request header
+    pub fn find<'a>(&self, cookie_header: &'a str) -> Option<&'a str> {
+        cookie_header
+            .split(';')
+            .filter_map(|kv| kv.trim().split_once('='))
+            .find(|(k, _)| *k == self.name)
+            .map(|(_, v)| v)
+    }
+}
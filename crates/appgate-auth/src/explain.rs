@@ -0,0 +1,50 @@
+use anyhow::Result;
+use appgate_ipc::{
+    pdp::{session_admin_client::SessionAdminClient, ExplainRequest, ExplainResponse, Outcome},
+    uds_channel,
+};
+
+/// Ask appgate-auth's operator socket `uds` to explain a request and return the printable report
+pub async fn query(uds: &str, req: ExplainRequest) -> Result<String> {
+    let mut client = SessionAdminClient::new(uds_channel(uds).await?);
+    let resp = client.explain(req).await?.into_inner();
+    Ok(report(&resp))
+}
+
+/// Human-readable report: identity, decision, then one line per rule in policy order with the
+/// deciding rule marked `*` and failed conditions listed under their rule
+pub fn report(x: &ExplainResponse) -> String {
+    let mut out = String::new();
+    out.push_str(&format!("identity: {}\n", x.identity));
+    if !x.identity.starts_with("no usable session") {
+        out.push_str(&format!("groups:   {}\n", if x.groups.is_empty() { "(none)".into() } else { x.groups.join(", ") }));
+    }
+    if let Some(d) = &x.decision {
+        let outcome = match d.outcome() {
+            Outcome::Allow => "ALLOW",
+            Outcome::Unauthenticated => "DENY (unauthenticated)",
+            Outcome::Forbidden | Outcome::Unspecified => "DENY (forbidden)",
+        };
+        out.push_str(&format!("decision: {outcome}: {}\n", d.reason));
+        if !d.expiry.is_empty() {
+            out.push_str(&format!("expiry:   {}\n", d.expiry));
+        }
+    }
+    if x.rules.is_empty() {
+        return out;
+    }
+    let width = x.rules.iter().map(|r| r.rule.len()).max().unwrap_or(0).max(4);
+    // the SOURCE column only when the policy was loaded from files
+    let src = x.rules.iter().map(|r| r.source.len()).max().unwrap_or(0);
+    let column = |s: &str| if src == 0 { String::new() } else { format!("{s:src$}  ", src = src.max(6)) };
+    out.push_str(&format!("\n  {:width$}  {}EFFECT  PRIO  RESULT\n", "RULE", column("SOURCE")));
+    for r in &x.rules {
+        let mark = if r.decisive { '*' } else { ' ' };
+        out.push_str(&format!("{mark} {:width$}  {}{:6}  {:>4}  {}\n", r.rule, column(&r.source), r.effect, r.priority, r.summary));
+        for c in r.conditions.iter().filter(|c| !c.passed) {
+            let value = if c.present { format!("{:?}", c.value) } else { "(absent)".into() };
+            out.push_str(&format!("{:width$}      {} = {value}: failed\n", "", c.attribute));
+        }
+    }
+    out
+}
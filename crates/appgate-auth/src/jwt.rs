@@ -0,0 +1,151 @@
+use anyhow::{anyhow, bail, Context, Result};
+use jsonwebtoken::{
+    jwk::{Jwk, JwkSet},
+    Algorithm, DecodingKey, Validation,
+};
+use serde_json::{Map, Value};
+use std::{
+    sync::RwLock,
+    time::{Duration, Instant},
+};
+
+/// Signature algorithms accepted on tokens from the issuer
+const ALLOWED_ALGS: &[Algorithm] = &[Algorithm::RS256, Algorithm::ES256, Algorithm::EdDSA];
+
+/// `events` member identifying a Back-Channel Logout token
+pub const BACKCHANNEL_LOGOUT_EVENT: &str = "http://schemas.openid.net/event/backchannel-logout";
+
+/// Minimum spacing between JWKS refreshes triggered by unknown `kid`s
+pub const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Cached copy of the issuer's JSON Web Key Set
+pub struct JwksCache {
+    uri: String,
+    http: reqwest::Client,
+    keys: RwLock<JwkSet>,
+    last_fetch: tokio::sync::Mutex<Instant>,
+    min_refresh: Duration,
+}
+
+impl JwksCache {
+    /// Fetch the key set at `uri` and cache it
+    pub async fn fetch(uri: &str, http: reqwest::Client) -> Result<Self> {
+        let keys = download(&http, uri).await?;
+        Ok(Self {
+            uri: uri.to_string(),
+            http,
+            keys: RwLock::new(keys),
+            last_fetch: tokio::sync::Mutex::new(Instant::now()),
+            min_refresh: MIN_REFRESH_INTERVAL,
+        })
+    }
+
+    /// Override the minimum interval between refreshes
+    pub fn with_min_refresh(mut self, d: Duration) -> Self {
+        self.min_refresh = d;
+        self
+    }
+
+    fn cached(&self, kid: &str) -> Option<Jwk> {
+        self.keys.read().unwrap().find(kid).cloned()
+    }
+
+    /// Key with id `kid`; an unknown id triggers one rate-limited refresh of the set
+    pub async fn key(&self, kid: &str) -> Result<Jwk> {
+        if let Some(k) = self.cached(kid) {
+            return Ok(k);
+        }
+        // Serialise refreshes so a burst of tokens with a new kid causes a single fetch.
+        let mut last = self.last_fetch.lock().await;
+        if let Some(k) = self.cached(kid) {
+            return Ok(k);
+        }
+        if last.elapsed() < self.min_refresh {
+            bail!("unknown kid {kid} (JWKS refresh rate-limited)");
+        }
+        *last = Instant::now();
+        let fresh = download(&self.http, &self.uri).await?;
+        tracing::info!(kid, keys = fresh.keys.len(), "JWKS refreshed after unknown kid");
+        *self.keys.write().unwrap() = fresh;
+        self.cached(kid).ok_or_else(|| anyhow!("unknown kid {kid}"))
+    }
+}
+
+async fn download(http: &reqwest::Client, uri: &str) -> Result<JwkSet> {
+    http.get(uri)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .with_context(|| format!("fetching JWKS from {uri}"))?
+        .json()
+        .await
+        .context("parsing JWKS")
+}
+
+/// Verifies signed JWTs from one issuer for one audience
+pub struct TokenVerifier {
+    pub issuer: String,
+    pub audience: String,
+    /// Allowed clock skew in seconds for exp/nbf/iat
+    pub skew: u64,
+    pub jwks: JwksCache,
+}
+
+impl TokenVerifier {
+    /// Check signature, `iss`, `aud`, `exp`, `nbf` and `iat`, returning the claims
+    pub async fn verify(&self, token: &str) -> Result<Map<String, Value>> {
+        let header = jsonwebtoken::decode_header(token).context("malformed JWT header")?;
+        if !ALLOWED_ALGS.contains(&header.alg) {
+            bail!("algorithm {:?} not allowed", header.alg);
+        }
+        let kid = header.kid.ok_or_else(|| anyhow!("token has no kid"))?;
+        let jwk = self.jwks.key(&kid).await?;
+        let key = DecodingKey::from_jwk(&jwk).with_context(|| format!("unusable key {kid}"))?;
+
+        let mut v = Validation::new(header.alg);
+        v.leeway = self.skew;
+        v.validate_nbf = true;
+        v.set_issuer(&[&self.issuer]);
+        v.set_audience(&[&self.audience]);
+        v.set_required_spec_claims(&["exp", "iss", "aud"]);
+        let claims = jsonwebtoken::decode::<Map<String, Value>>(token, &key, &v)
+            .context("token rejected")?
+            .claims;
+
+        let iat = claims.get("iat").and_then(Value::as_i64).ok_or_else(|| anyhow!("missing iat"))?;
+        if iat > chrono::Utc::now().timestamp() + self.skew as i64 {
+            bail!("token issued in the future");
+        }
+        Ok(claims)
+    }
+
+    /// [`TokenVerifier::verify`] plus the `nonce` bound to the login attempt
+    pub async fn verify_id_token(&self, token: &str, nonce: &str) -> Result<Map<String, Value>> {
+        let claims = self.verify(token).await?;
+        if claims.get("nonce").and_then(Value::as_str) != Some(nonce) {
+            bail!("nonce mismatch");
+        }
+        Ok(claims)
+    }
+
+    /// Validate an OIDC Back-Channel Logout token, returning its `(sid, sub)`; at least one is present
+    pub async fn verify_logout_token(&self, token: &str) -> Result<(Option<String>, Option<String>)> {
+        let claims = self.verify(token).await?;
+        let is_logout = claims
+            .get("events")
+            .and_then(Value::as_object)
+            .is_some_and(|e| e.get(BACKCHANNEL_LOGOUT_EVENT).is_some_and(Value::is_object));
+        if !is_logout {
+            bail!("not a back-channel logout token");
+        }
+        if claims.contains_key("nonce") {
+            bail!("logout token must not carry a nonce");
+        }
+        let sid = claims.get("sid").and_then(Value::as_str).map(str::to_string);
+        let sub = claims.get("sub").and_then(Value::as_str).map(str::to_string);
+        if sid.is_none() && sub.is_none() {
+            bail!("logout token names neither sid nor sub");
+        }
+        Ok((sid, sub))
+    }
+}
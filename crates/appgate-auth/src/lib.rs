@@ -0,0 +1,13 @@
+pub mod admin;
+pub mod claims;
+pub mod cookie;
+pub mod explain;
+pub mod jwt;
+pub mod links;
+pub mod oidc;
+pub mod pdp;
+pub mod reload;
+pub mod renew;
+pub mod session;
+pub mod store;
+pub mod web;
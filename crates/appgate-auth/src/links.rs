@@ -0,0 +1,44 @@
+use anyhow::Result;
+use chrono::Utc;
+use std::sync::Arc;
+
+use crate::cookie::CookieSealer;
+
+/// How long a login link minted by the PDP stays usable
+const RETURN_TO_TTL_SECONDS: i64 = 300;
+
+/// Builds `/oidc/login` links whose `rt` parameter is a sealed, short-lived absolute `return_to`.
+///
+/// Plain `return_to` values are limited to local paths; sealing lets the PDP send users back to
+/// the proxied site they came from without turning the login endpoint into an open redirect.
+pub struct LoginLinks {
+    login_url: url::Url,
+    sealer: Arc<CookieSealer>,
+}
+
+impl LoginLinks {
+    /// Login endpoint derived from the OIDC `redirect_uri` (`.../oidc/callback` → `.../oidc/login`)
+    pub fn new(redirect_uri: &str, sealer: Arc<CookieSealer>) -> Result<Self> {
+        Ok(Self { login_url: url::Url::parse(redirect_uri)?.join("login")?, sealer })
+    }
+
+    /// Login link that returns the browser to `return_to` afterwards
+    pub fn login_url(&self, return_to: &str) -> String {
+        let exp = Utc::now().timestamp() + RETURN_TO_TTL_SECONDS;
+        let rt = self.sealer.seal_bytes("return-to", format!("{exp}|{return_to}").as_bytes());
+        let mut url = self.login_url.clone();
+        url.query_pairs_mut().append_pair("rt", &rt);
+        url.into()
+    }
+
+    /// The `return_to` inside a sealed `rt`, if authentic, unexpired and an http(s) URL
+    pub fn open(&self, rt: &str) -> Option<String> {
+        let plain = String::from_utf8(self.sealer.open_bytes("return-to", rt).ok()?).ok()?;
+        let (exp, target) = plain.split_once('|')?;
+        if exp.parse::<i64>().ok()? < Utc::now().timestamp() {
+            return None;
+        }
+        let url = url::Url::parse(target).ok()?;
+        matches!(url.scheme(), "http" | "https").then(|| url.into())
+    }
+}
@@ -1,41 +1,74 @@
 use anyhow::Result;
-use clap::Parser;
-use tracing_subscriber::EnvFilter;
-use tonic::{Request, Response, Status};
-use appgate_ipc::{pdp::{p_d_p_server::{Pdp, PdpServer}, DecisionRequest, DecisionResponse}, uds_server};
-use std::sync::Arc;
+use clap::{Parser, Subcommand};
+use appgate_auth::{
+    admin::AdminSvc,
+    claims::ClaimMapper,
+    cookie::{CookieSealer, CookieSettings},
+    explain,
+    links::LoginLinks,
+    oidc::OidcClient,
+    pdp::PdpSvc,
+    reload::PolicyReloader,
+    renew::{spawn_renewer, Renewer},
+    store::{spawn_gc, SessionStore},
+    web,
+};
+use appgate_ctrl::Config;
+use arc_swap::ArcSwap;
+use axum::{routing::get, Router};
+use prometheus::{Encoder, Registry, TextEncoder};
+use appgate_ipc::{
+    pdp::{pdp_server::PdpServer, session_admin_server::SessionAdminServer, Attributes, DecisionRequest, ExplainRequest},
+    uds_server,
+};
+use std::{net::SocketAddr, sync::Arc, time::Duration};
 
 #[derive(Parser, Debug)]
 struct Args {
     #[arg(long, default_value="/etc/appgate/appgate.toml")]
     config: String,
-    #[arg(long, default_value="/run/appgate/pdp.sock")]
+    #[arg(long, global = true, default_value="/run/appgate/pdp.sock")]
     uds: String,
-    #[arg(long, default_value="config/policy/foundry.toml")]
-    policy: String,
+    /// Operator socket for session administration (revocation, `explain`)
+    #[arg(long, global = true, default_value="/run/appgate/auth-admin.sock")]
+    admin_uds: String,
+    /// Policy file, reloaded when it changes or on SIGHUP [default: `policy.file`, else config/policy/foundry.toml]
+    #[arg(long)]
+    policy: Option<String>,
+    /// Listener for the browser-facing OIDC endpoints (`/oidc/login`, `/oidc/callback`)
+    #[arg(long, default_value="127.0.0.1:8090")]
+    http_bind: String,
+    /// Prometheus `/metrics` listener
+    #[arg(long, default_value="127.0.0.1:9102")]
+    metrics_bind: String,
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
-struct PdpSvc {
-    policy: Arc<appgate_policy::Policy>,
-}
-
-#[tonic::async_trait]
-impl Pdp for PdpSvc {
-    async fn decide(&self, req: Request<DecisionRequest>) -> Result<Response<DecisionResponse>, Status> {
-        let r = req.into_inner();
-        // TODO: validate session_token (OIDC/session cookie verification).
-        // For MVP, treat token presence as authenticated and fake groups:
-        let groups = vec!["foundry-players".to_string(), "foundry-admin".to_string()];
-        let (allow, inject, reason) = self.policy.decide(&r.protocol, &r.resource, &groups);
-        let resp = DecisionResponse {
-            allow,
-            expiry: chrono::Utc::now().checked_add_signed(chrono::Duration::minutes(30)).unwrap().to_rfc3339(),
-            claims: [("sub".to_string(), "demo-sub".to_string())].into_iter().collect(),
-            inject: inject.unwrap_or_default(),
-            reason,
-        };
-        Ok(Response::new(resp))
-    }
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Ask the running appgate-auth (on `--admin-uds`) why a request is allowed or denied
+    Explain {
+        /// Resource as modules send it, e.g. `http://foundry/admin`
+        resource: String,
+        #[arg(long, default_value="http")]
+        protocol: String,
+        /// Explain for this subject's newest live session
+        #[arg(long)]
+        sub: Option<String>,
+        /// Explain for a session cookie value
+        #[arg(long)]
+        token: Option<String>,
+        /// Explain for these groups (when neither `--sub` nor `--token` is given)
+        #[arg(long = "group")]
+        groups: Vec<String>,
+        /// Client address
+        #[arg(long)]
+        peer: Option<String>,
+        /// Request attribute as `name=value`, e.g. `method=POST`
+        #[arg(long = "attr")]
+        attrs: Vec<String>,
+    },
 }
 
 #[tokio::main]
@@ -44,9 +77,96 @@ async fn main() -> Result<()> {
     init_json_logger();
 
     let args = Args::parse();
-    let policy = Arc::new(appgate_policy::Policy::load(&args.policy)?);
+    if let Some(Command::Explain { resource, protocol, sub, token, groups, peer, attrs }) = args.command {
+        let kv = attrs
+            .iter()
+            .map(|a| a.split_once('=').map(|(k, v)| (k.to_string(), v.to_string())))
+            .collect::<Option<_>>()
+            .ok_or_else(|| anyhow::anyhow!("--attr takes name=value"))?;
+        let request = DecisionRequest {
+            session_token: token.unwrap_or_default(),
+            protocol,
+            resource,
+            peer: peer.unwrap_or_default(),
+            attributes: Some(Attributes { kv }),
+        };
+        let req = ExplainRequest { request: Some(request), sub: sub.unwrap_or_default(), groups };
+        print!("{}", explain::query(&args.admin_uds, req).await?);
+        return Ok(());
+    }
+    let cfg: Config = toml::from_str(&std::fs::read_to_string(&args.config)?)?;
+    cfg.validate().map_err(|e| anyhow::anyhow!(e))?;
+    let policy_file = args.policy.or(cfg.policy.file).unwrap_or_else(|| "config/policy/foundry.toml".into());
+    let (policy, warnings) = appgate_policy::engine::Engine::load(cfg.policy.engine, &policy_file)?;
+    for w in warnings {
+        tracing::warn!(policy = %policy_file, "{w}");
+    }
+    let policy = Arc::new(ArcSwap::from_pointee(policy));
+    let registry = Registry::new();
+    let reloader = Arc::new(PolicyReloader::new(&policy_file, policy.clone(), &registry)?);
+    reloader.watch()?;
+    reloader.reload_on_sighup()?;
+    let sealer = Arc::new(CookieSealer::from_config(&cfg.auth.session)?);
+    let store = Arc::new(SessionStore::from_config(&cfg.auth.session, &cfg.global.run_dir)?);
+    spawn_gc(store.clone(), Duration::from_secs(60));
+
+    let oidc = Arc::new(OidcClient::discover(&cfg.auth.oidc).await?);
+    tracing::info!(issuer = %oidc.discovery.issuer, "OIDC discovery complete");
+    let mapper = Arc::new(ClaimMapper::new(&cfg.auth.claims, &cfg.auth.oidc.client_id));
+    let renewer = Renewer::new(oidc.clone(), store.clone(), sealer.clone(), mapper.clone(), cfg.auth.oidc.session_ttl_seconds);
+    let every = renewer.margin.to_std().unwrap_or_default().max(Duration::from_secs(2)) / 2;
+    spawn_renewer(Arc::new(renewer), every);
+    let links = Arc::new(LoginLinks::new(&cfg.auth.oidc.redirect_uri, sealer.clone())?);
+    let web_state = web::WebState {
+        oidc,
+        sealer: sealer.clone(),
+        mapper,
+        store: store.clone(),
+        links: links.clone(),
+        cookie: CookieSettings {
+            name: cfg.auth.oidc.cookie_name.clone(),
+            domain: cfg.auth.oidc.cookie_domain.clone(),
+            ttl_seconds: cfg.auth.oidc.session_ttl_seconds,
+            max_lifetime_seconds: cfg.auth.oidc.session_max_lifetime_seconds,
+        },
+    };
+    let addr: SocketAddr = args.http_bind.parse()?;
+    tracing::info!("OIDC endpoints on {}", addr);
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, web::router(web_state)).await {
+            tracing::error!(error = %e, "OIDC listener failed");
+        }
+    });
+
+    let metrics_addr: SocketAddr = args.metrics_bind.parse()?;
+    let metrics_app = Router::new().route("/metrics", get(move || {
+        let registry = registry.clone();
+        async move {
+            let mut buf = Vec::new();
+            TextEncoder::new().encode(&registry.gather(), &mut buf).unwrap();
+            String::from_utf8(buf).unwrap()
+        }
+    }));
+    tracing::info!("metrics on {}", metrics_addr);
+    let metrics_listener = tokio::net::TcpListener::bind(metrics_addr).await?;
+    tokio::spawn(async move {
+        if let Err(e) = axum::serve(metrics_listener, metrics_app).await {
+            tracing::error!(error = %e, "metrics listener failed");
+        }
+    });
 
-    let svc = PdpServer::new(PdpSvc { policy });
+    let pdp = Arc::new(PdpSvc { policy, sealer, store: store.clone(), login: Some(links) });
+    let admin = SessionAdminServer::new(AdminSvc { store, pdp: pdp.clone() });
+    let admin_uds = args.admin_uds.clone();
+    tracing::info!("session admin listening on {}", admin_uds);
+    tokio::spawn(async move {
+        if let Err(e) = uds_server(admin, &admin_uds).await {
+            tracing::error!(error = %e, "session admin listener failed");
+        }
+    });
+
+    let svc = PdpServer::from_arc(pdp);
     tracing::info!("PDP listening on {}", args.uds);
     uds_server(svc, &args.uds).await?;
     Ok(())
@@ -63,4 +183,4 @@ fn init_json_logger() {
         .with_current_span(true)
         .with_span_list(true)
         .init();
-}
\ No newline at end of file
+}
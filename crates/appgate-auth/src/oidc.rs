@@ -0,0 +1,195 @@
+use anyhow::{bail, Context, Result};
+use appgate_ctrl::Oidc;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use rand::RngCore;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::jwt::{JwksCache, TokenVerifier};
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// How long an authorize redirect may take before its state is forgotten
+const PENDING_TTL: Duration = Duration::from_secs(600);
+
+/// Subset of the OpenID Provider metadata used by AppGate
+#[derive(Debug, Clone, Deserialize)]
+pub struct Discovery {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub jwks_uri: String,
+    #[serde(default)]
+    pub end_session_endpoint: Option<String>,
+}
+
+/// Successful token endpoint response
+#[derive(Debug, Clone, Deserialize)]
+pub struct TokenResponse {
+    #[serde(default)]
+    pub id_token: Option<String>,
+    #[serde(default)]
+    pub access_token: Option<String>,
+    #[serde(default)]
+    pub refresh_token: Option<String>,
+    #[serde(default)]
+    pub expires_in: Option<u64>,
+}
+
+/// Login state kept between the authorize redirect and the callback
+#[derive(Debug, Clone)]
+pub struct PendingLogin {
+    pub verifier: String,
+    pub nonce: String,
+    pub return_to: String,
+    created: Instant,
+}
+
+/// Relying-party side of the Authorization Code + PKCE flow
+pub struct OidcClient {
+    pub discovery: Discovery,
+    client_id: String,
+    client_secret: String,
+    redirect_uri: String,
+    http: reqwest::Client,
+    pub verifier: TokenVerifier,
+    pending: Mutex<HashMap<String, PendingLogin>>,
+}
+
+impl OidcClient {
+    /// Fetch the issuer's discovery document and build a client for it
+    pub async fn discover(cfg: &Oidc) -> Result<Self> {
+        let http = reqwest::Client::new();
+        let url = format!("{}/.well-known/openid-configuration", cfg.issuer.trim_end_matches('/'));
+        let discovery: Discovery = http
+            .get(&url)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .with_context(|| format!("fetching {url}"))?
+            .json()
+            .await
+            .context("parsing discovery document")?;
+        if discovery.issuer.trim_end_matches('/') != cfg.issuer.trim_end_matches('/') {
+            bail!("discovery issuer {} does not match configured {}", discovery.issuer, cfg.issuer);
+        }
+        let verifier = TokenVerifier {
+            issuer: discovery.issuer.clone(),
+            audience: cfg.client_id.clone(),
+            skew: cfg.clock_skew_seconds,
+            jwks: JwksCache::fetch(&discovery.jwks_uri, http.clone()).await?,
+        };
+        Ok(Self {
+            discovery,
+            client_id: cfg.client_id.clone(),
+            client_secret: cfg.resolve_client_secret()?,
+            redirect_uri: cfg.redirect_uri.clone(),
+            http,
+            verifier,
+            pending: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn client_id(&self) -> &str {
+        &self.client_id
+    }
+
+    /// Record a new login attempt and return the authorize URL to redirect the browser to
+    pub fn begin(&self, return_to: &str) -> Result<String> {
+        let state = random_token(32);
+        let nonce = random_token(32);
+        let verifier = random_token(32);
+        let mut url = url::Url::parse(&self.discovery.authorization_endpoint)?;
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &self.client_id)
+            .append_pair("redirect_uri", &self.redirect_uri)
+            .append_pair("scope", "openid profile email")
+            .append_pair("state", &state)
+            .append_pair("nonce", &nonce)
+            .append_pair("code_challenge", &pkce_challenge(&verifier))
+            .append_pair("code_challenge_method", "S256");
+
+        let mut pending = self.pending.lock().unwrap();
+        pending.retain(|_, p| p.created.elapsed() < PENDING_TTL);
+        pending.insert(state, PendingLogin { verifier, nonce, return_to: return_to.to_string(), created: Instant::now() });
+        Ok(url.into())
+    }
+
+    /// Consume the login attempt identified by `state`, if it exists and has not expired
+    pub fn take_pending(&self, state: &str) -> Option<PendingLogin> {
+        let p = self.pending.lock().unwrap().remove(state)?;
+        (p.created.elapsed() < PENDING_TTL).then_some(p)
+    }
+
+    /// RP-initiated logout URL at the IdP's `end_session_endpoint`, returning the browser to
+    /// `return_to` on our origin afterwards; `None` when the IdP does not advertise one
+    pub fn end_session_url(&self, id_token_hint: Option<&str>, return_to: &str) -> Result<Option<String>> {
+        let Some(endpoint) = &self.discovery.end_session_endpoint else {
+            return Ok(None);
+        };
+        let post_logout = url::Url::parse(&self.redirect_uri)?.join(return_to)?;
+        let mut url = url::Url::parse(endpoint)?;
+        {
+            let mut q = url.query_pairs_mut();
+            q.append_pair("client_id", &self.client_id).append_pair("post_logout_redirect_uri", post_logout.as_str());
+            if let Some(hint) = id_token_hint {
+                q.append_pair("id_token_hint", hint);
+            }
+        }
+        Ok(Some(url.into()))
+    }
+
+    /// Exchange an authorization code for tokens at the token endpoint
+    pub async fn exchange(&self, code: &str, verifier: &str) -> Result<TokenResponse> {
+        self.token_request(&[
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", self.redirect_uri.as_str()),
+            ("code_verifier", verifier),
+        ])
+        .await
+    }
+
+    /// Redeem a refresh token for a fresh set of tokens
+    pub async fn refresh(&self, refresh_token: &str) -> Result<TokenResponse> {
+        self.token_request(&[
+            ("grant_type", "refresh_token"),
+            ("refresh_token", refresh_token),
+            ("scope", "openid profile email"),
+        ])
+        .await
+    }
+
+    async fn token_request(&self, form: &[(&str, &str)]) -> Result<TokenResponse> {
+        let resp = self
+            .http
+            .post(&self.discovery.token_endpoint)
+            .basic_auth(&self.client_id, Some(&self.client_secret))
+            .form(form)
+            .send()
+            .await
+            .context("calling token endpoint")?;
+        if !resp.status().is_success() {
+            let status = resp.status();
+            let body = resp.text().await.unwrap_or_default();
+            bail!("token endpoint returned {status}: {body}");
+        }
+        resp.json().await.context("parsing token response")
+    }
+}
+
+/// Random URL-safe token with `bytes` bytes of entropy
+pub fn random_token(bytes: usize) -> String {
+    let mut buf = vec![0u8; bytes];
+    rand::thread_rng().fill_bytes(&mut buf);
+    URL_SAFE_NO_PAD.encode(buf)
+}
+
+/// S256 PKCE code challenge for `verifier`
+pub fn pkce_challenge(verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()))
+}
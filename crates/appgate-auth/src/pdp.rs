@@ -0,0 +1,149 @@
+use appgate_ipc::pdp::{
+    pdp_server::Pdp, ConditionTrace, DecisionRequest, DecisionResponse, Outcome, RuleTrace,
+};
+use appgate_policy::{
+    engine::{Engine, PolicyEngine},
+    explain, template, Context, Decision, Effect,
+};
+use arc_swap::ArcSwap;
+use chrono::{DateTime, Utc};
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
+use tonic::{Request, Response, Status};
+
+use crate::{
+    cookie::CookieSealer,
+    links::LoginLinks,
+    store::{SessionRecord, SessionStore},
+};
+
+/// PDP gRPC service: opens the sealed session behind a token and evaluates policy
+pub struct PdpSvc {
+    /// Live policy, swapped on reload
+    pub policy: Arc<ArcSwap<Engine>>,
+    pub sealer: Arc<CookieSealer>,
+    pub store: Arc<SessionStore>,
+    /// Mints login links for unauthenticated HTTP requests; without it modules only get the outcome
+    pub login: Option<Arc<LoginLinks>>,
+}
+
+impl PdpSvc {
+    /// Deny for lack of a usable session, pointing HTTP clients at login
+    pub(crate) fn unauthenticated(&self, r: &DecisionRequest, reason: &str) -> DecisionResponse {
+        // modules pass the public URL the user asked for; fall back to the resource
+        let url = r.attributes.as_ref().and_then(|a| a.kv.get("url")).unwrap_or(&r.resource);
+        let login_url = match &self.login {
+            Some(links) if r.protocol == "http" => links.login_url(url),
+            _ => String::new(),
+        };
+        DecisionResponse {
+            allow: false,
+            reason: format!("unauthenticated: {reason}"),
+            outcome: Outcome::Unauthenticated.into(),
+            login_url,
+            ..Default::default()
+        }
+    }
+
+    /// The live session behind the request's token, or why there is none
+    pub(crate) fn session(&self, r: &DecisionRequest) -> Result<SessionRecord, String> {
+        let sess = self.sealer.unseal(&r.session_token).map_err(|e| e.to_string())?;
+        // groups and claims come from the store so refreshes take effect without a new cookie
+        match self.store.get(&sess.sid) {
+            Ok(Some(rec)) if rec.revoked => Err("session revoked".into()),
+            Ok(Some(rec)) if rec.expires_at > Utc::now() => Ok(rec),
+            Ok(Some(_)) => Err("session expired".into()),
+            Ok(None) => Err("unknown session".into()),
+            Err(e) => {
+                tracing::error!(error = %e, "session store lookup failed");
+                Err("session store unavailable".into())
+            }
+        }
+    }
+}
+
+/// Modules send `ip` or `ip:port`; anything else (e.g. `"unknown"`) is no address
+pub(crate) fn parse_peer(peer: &str) -> Option<IpAddr> {
+    peer.parse::<IpAddr>().ok().or_else(|| peer.parse::<SocketAddr>().ok().map(|s| s.ip()))
+}
+
+/// The answer for an identified caller; `expires_at` is the session's own end, if there is a session
+pub(crate) fn respond(d: Decision, claims: HashMap<String, String>, groups: &[String], expires_at: Option<DateTime<Utc>>) -> DecisionResponse {
+    // scheduled access ends with its window even if the session lives on
+    let expiry = match (d.until, expires_at) {
+        (Some(until), Some(end)) => Some(until.min(end)),
+        (until, end) => until.or(end),
+    };
+    let inject = d
+        .inject
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(name, value)| (name, template::render(&value, &claims, groups)))
+        .collect();
+    DecisionResponse {
+        allow: d.allow,
+        expiry: expiry.map(|t| t.to_rfc3339()).unwrap_or_default(),
+        claims,
+        inject,
+        reason: d.reason,
+        outcome: if d.allow { Outcome::Allow } else { Outcome::Forbidden }.into(),
+        login_url: String::new(),
+    }
+}
+
+pub(crate) fn rule_trace(t: explain::RuleTrace) -> RuleTrace {
+    RuleTrace {
+        summary: t.summary(),
+        effect: match t.effect {
+            Effect::Allow => "allow",
+            Effect::Deny => "deny",
+        }
+        .into(),
+        rule: t.rule,
+        priority: t.priority,
+        protocol_matched: t.protocol,
+        resource_matched: t.resource,
+        conditions: t
+            .conditions
+            .into_iter()
+            .map(|c| ConditionTrace { attribute: c.attribute, present: c.value.is_some(), value: c.value.unwrap_or_default(), passed: c.passed })
+            .collect(),
+        peer_matched: t.peer,
+        schedule_active: t.schedule,
+        groups_matched: t.groups,
+        decisive: t.decisive,
+        source: t.source.map(|s| s.to_string()).unwrap_or_default(),
+    }
+}
+
+#[tonic::async_trait]
+impl Pdp for PdpSvc {
+    async fn decide(&self, req: Request<DecisionRequest>) -> Result<Response<DecisionResponse>, Status> {
+        let r = req.into_inner();
+        let rec = match self.session(&r) {
+            Ok(rec) => rec,
+            Err(reason) => return Ok(Response::new(self.unauthenticated(&r, &reason))),
+        };
+        let no_attributes = HashMap::new();
+        let attributes = r.attributes.as_ref().map_or(&no_attributes, |a| &a.kv);
+        let peer = parse_peer(&r.peer);
+        let cx = Context {
+            protocol: &r.protocol,
+            resource: &r.resource,
+            groups: &rec.groups,
+            claims: &rec.claims,
+            attributes,
+            peer,
+            now: Utc::now(),
+        };
+        let d = self.policy.load().evaluate(&cx);
+        if let Some(source) = &d.source {
+            tracing::debug!(resource = %r.resource, reason = %d.reason, %source, "decision");
+        }
+        Ok(Response::new(respond(d, rec.claims, &rec.groups, Some(rec.expires_at))))
+    }
+}
+
@@ -0,0 +1,111 @@
+use anyhow::{Context, Result};
+use appgate_policy::engine::Engine;
+use arc_swap::ArcSwap;
+use notify::{RecursiveMode, Watcher};
+use prometheus::{IntCounterVec, IntGauge, Opts, Registry};
+use std::{path::PathBuf, sync::Arc, time::Duration};
+
+/// Reloads the policy file into the PDP's live policy; a policy that fails to load, validate or
+/// lint leaves the previous one in place
+pub struct PolicyReloader {
+    path: PathBuf,
+    policy: Arc<ArcSwap<Engine>>,
+    reloads: IntCounterVec,
+    last_reload: IntGauge,
+}
+
+impl PolicyReloader {
+    /// Register `appgate_policy_reloads_total{result}` and `appgate_policy_last_reload_timestamp_seconds`
+    pub fn new(path: impl Into<PathBuf>, policy: Arc<ArcSwap<Engine>>, registry: &Registry) -> Result<Self> {
+        let reloads = IntCounterVec::new(
+            Opts::new("appgate_policy_reloads_total", "Policy reload attempts by result"),
+            &["result"],
+        )?;
+        let last_reload = IntGauge::new(
+            "appgate_policy_last_reload_timestamp_seconds",
+            "Unix time the current policy was loaded",
+        )?;
+        registry.register(Box::new(reloads.clone()))?;
+        registry.register(Box::new(last_reload.clone()))?;
+        last_reload.set(chrono::Utc::now().timestamp());
+        Ok(Self { path: path.into(), policy, reloads, last_reload })
+    }
+
+    /// Load (with the engine of the current policy), validate, lint and swap in the policy file. In-flight decisions finish on the policy they started with
+    pub fn reload(&self) -> Result<()> {
+        let path = self.path.to_string_lossy();
+        match Engine::load(self.policy.load().kind(), &path) {
+            Ok((p, warnings)) => {
+                for w in warnings {
+                    tracing::warn!(path = %path, "{w}");
+                }
+                let rules = p.rule_names().len();
+                self.policy.store(Arc::new(p));
+                self.reloads.with_label_values(&["success"]).inc();
+                self.last_reload.set(chrono::Utc::now().timestamp());
+                tracing::info!(path = %path, rules, "policy reloaded");
+                Ok(())
+            }
+            Err(e) => {
+                self.reloads.with_label_values(&["failure"]).inc();
+                tracing::error!(path = %path, error = %format!("{e:#}"), "policy reload failed; keeping the previous policy");
+                Err(e)
+            }
+        }
+    }
+
+    /// Reload whenever the policy changes. For a file the parent directory is watched so editors
+    /// that replace the file by renaming are seen too; a directory is watched recursively for
+    /// `.toml` changes. Files included from elsewhere are picked up on SIGHUP
+    pub fn watch(self: &Arc<Self>) -> Result<()> {
+        let (dir, file, mode) = if self.path.is_dir() {
+            (self.path.clone(), None, RecursiveMode::Recursive)
+        } else {
+            let file = self.path.file_name().context("policy path has no file name")?.to_owned();
+            let dir = match self.path.parent() {
+                Some(d) if !d.as_os_str().is_empty() => d.to_path_buf(),
+                _ => PathBuf::from("."),
+            };
+            (dir, Some(file), RecursiveMode::NonRecursive)
+        };
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+            if let Ok(ev) = res {
+                let relevant = |p: &PathBuf| match &file {
+                    Some(f) => p.file_name() == Some(f.as_os_str()),
+                    // files written into a new directory before its watch is added raise no event
+                    // of their own, so the directory's creation counts too
+                    None => p.extension().is_some_and(|e| e == "toml") || (ev.kind.is_create() && p.is_dir()),
+                };
+                if !ev.kind.is_access() && ev.paths.iter().any(relevant) {
+                    let _ = tx.send(());
+                }
+            }
+        })?;
+        watcher.watch(&dir, mode)?;
+        let this = self.clone();
+        tokio::spawn(async move {
+            let _watcher = watcher;
+            while rx.recv().await.is_some() {
+                // a save is often several events (truncate, write, rename); let them settle
+                tokio::time::sleep(Duration::from_millis(200)).await;
+                while rx.try_recv().is_ok() {}
+                let _ = this.reload();
+            }
+        });
+        Ok(())
+    }
+
+    /// Reload on SIGHUP
+    pub fn reload_on_sighup(self: &Arc<Self>) -> Result<()> {
+        let mut hup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())?;
+        let this = self.clone();
+        tokio::spawn(async move {
+            while hup.recv().await.is_some() {
+                tracing::info!("SIGHUP received, reloading policy");
+                let _ = this.reload();
+            }
+        });
+        Ok(())
+    }
+}
@@ -0,0 +1,92 @@
+use anyhow::{bail, Context, Result};
+use chrono::{Duration as ChronoDuration, Utc};
+use std::{sync::Arc, time::Duration};
+
+use crate::{claims::ClaimMapper, cookie::CookieSealer, oidc::OidcClient, store::{SessionRecord, SessionStore}};
+
+/// Renews refreshable sessions before their window ends, re-reading groups and claims from the IdP
+pub struct Renewer {
+    pub oidc: Arc<OidcClient>,
+    pub store: Arc<SessionStore>,
+    pub sealer: Arc<CookieSealer>,
+    pub mapper: Arc<ClaimMapper>,
+    /// Length of the window granted by each refresh (`session_ttl_seconds`)
+    pub ttl: ChronoDuration,
+    /// Sessions are refreshed once less than this much of their window remains
+    pub margin: ChronoDuration,
+}
+
+impl Renewer {
+    /// Renewer refreshing sessions in the last quarter of their window
+    pub fn new(oidc: Arc<OidcClient>, store: Arc<SessionStore>, sealer: Arc<CookieSealer>, mapper: Arc<ClaimMapper>, ttl_seconds: u64) -> Self {
+        let ttl = ChronoDuration::seconds(ttl_seconds as i64);
+        Self { oidc, store, sealer, mapper, ttl, margin: ttl / 4 }
+    }
+
+    /// Refresh every session that is due; failed sessions are revoked. Returns how many were renewed
+    pub async fn renew_due(&self) -> Result<usize> {
+        let mut n = 0;
+        for rec in self.store.due(Utc::now() + self.margin)? {
+            match self.refresh(&rec).await {
+                Ok(next) => {
+                    if self.store.renewed(&next)? {
+                        tracing::info!(
+                            target: "audit",
+                            event = "session.refreshed",
+                            sid = %next.sid,
+                            sub = %next.sub,
+                            groups = ?next.groups,
+                            expires_at = %next.expires_at.to_rfc3339(),
+                        );
+                        n += 1;
+                    }
+                }
+                Err(e) => {
+                    tracing::warn!(
+                        target: "audit",
+                        event = "session.refresh_failed",
+                        sid = %rec.sid,
+                        sub = %rec.sub,
+                        error = %format!("{e:#}"),
+                    );
+                    self.store.revoke(&rec.sid)?;
+                }
+            }
+        }
+        Ok(n)
+    }
+
+    async fn refresh(&self, rec: &SessionRecord) -> Result<SessionRecord> {
+        let sealed = rec.refresh_token.as_deref().context("session has no refresh token")?;
+        let token = self.sealer.open_secret(sealed).context("opening stored refresh token")?;
+        let tokens = self.oidc.refresh(&token).await?;
+        let id_token = tokens.id_token.as_deref().context("refresh response carried no ID token")?;
+        let claims = self.oidc.verifier.verify(id_token).await.context("refreshed ID token rejected")?;
+        if claims.get("sub").and_then(|v| v.as_str()) != Some(rec.sub.as_str()) {
+            bail!("refreshed ID token is for a different subject");
+        }
+        let mut next = rec.clone();
+        next.groups = self.mapper.groups(&claims);
+        next.claims = self.mapper.project(&claims);
+        next.expires_at = (Utc::now() + self.ttl).min(rec.not_after);
+        next.id_token = Some(self.sealer.seal_secret(id_token));
+        // IdPs that rotate refresh tokens invalidate the old one
+        if let Some(t) = tokens.refresh_token {
+            next.refresh_token = Some(self.sealer.seal_secret(&t));
+        }
+        Ok(next)
+    }
+}
+
+/// Run `renewer` every `every` for the life of the process
+pub fn spawn_renewer(renewer: Arc<Renewer>, every: Duration) {
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(every);
+        loop {
+            tick.tick().await;
+            if let Err(e) = renewer.renew_due().await {
+                tracing::error!(error = %e, "session renewal pass failed");
+            }
+        }
+    });
+}
@@ -0,0 +1,26 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+use crate::{claims::ClaimMapper, oidc::random_token};
+
+/// An authenticated AppGate session, carried sealed inside the session cookie
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Session {
+    /// Server-side session id, the key into the session store
+    pub sid: String,
+    pub sub: String,
+    pub groups: Vec<String>,
+    pub claims: HashMap<String, String>,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub expires_at: DateTime<Utc>,
+}
+
+impl Session {
+    /// Build a session from verified ID token claims
+    pub fn from_claims(claims: &Map<String, Value>, mapper: &ClaimMapper, expires_at: DateTime<Utc>) -> Option<Self> {
+        let sub = claims.get("sub")?.as_str()?.to_string();
+        Some(Self { sid: random_token(16), sub, groups: mapper.groups(claims), claims: mapper.project(claims), expires_at })
+    }
+}
@@ -0,0 +1,266 @@
+use anyhow::Result;
+use appgate_ctrl::{SessionConfig, SessionStoreKind};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, path::Path, sync::Arc, sync::Mutex, time::Duration};
+
+use crate::session::Session;
+
+/// Server-side record of an issued session
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionRecord {
+    pub sid: String,
+    pub sub: String,
+    /// Groups and claims as of the last login or refresh
+    #[serde(default)]
+    pub groups: Vec<String>,
+    #[serde(default)]
+    pub claims: HashMap<String, String>,
+    /// End of the current validity window; moved forward by each refresh
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub expires_at: DateTime<Utc>,
+    /// Refreshes never extend the window past this point
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub not_after: DateTime<Utc>,
+    /// Refresh token sealed with [`crate::cookie::CookieSealer::seal_secret`]
+    #[serde(default)]
+    pub refresh_token: Option<String>,
+    /// Latest ID token, sealed like the refresh token; sent as `id_token_hint` on logout
+    #[serde(default)]
+    pub id_token: Option<String>,
+    /// The IdP's `sid` for the login session this session came from
+    #[serde(default)]
+    pub idp_sid: Option<String>,
+    /// Revoked sessions are kept as tombstones until they expire so denials can say why
+    pub revoked: bool,
+}
+
+impl From<&Session> for SessionRecord {
+    /// A record valid for the session's whole lifetime, without refresh
+    fn from(s: &Session) -> Self {
+        Self {
+            sid: s.sid.clone(),
+            sub: s.sub.clone(),
+            groups: s.groups.clone(),
+            claims: s.claims.clone(),
+            expires_at: s.expires_at,
+            not_after: s.expires_at,
+            refresh_token: None,
+            id_token: None,
+            idp_sid: None,
+            revoked: false,
+        }
+    }
+}
+
+/// State of a session id as seen by the store
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionStatus {
+    Active,
+    Revoked,
+    Unknown,
+}
+
+/// Storage for session records
+pub trait SessionBackend: Send + Sync {
+    fn put(&self, rec: &SessionRecord) -> Result<()>;
+    fn get(&self, sid: &str) -> Result<Option<SessionRecord>>;
+    fn remove(&self, sid: &str) -> Result<()>;
+    fn all(&self) -> Result<Vec<SessionRecord>>;
+}
+
+/// Process-local backend; sessions are forgotten on restart
+#[derive(Default)]
+pub struct MemoryBackend {
+    inner: Mutex<HashMap<String, SessionRecord>>,
+}
+
+impl SessionBackend for MemoryBackend {
+    fn put(&self, rec: &SessionRecord) -> Result<()> {
+        self.inner.lock().unwrap().insert(rec.sid.clone(), rec.clone());
+        Ok(())
+    }
+
+    fn get(&self, sid: &str) -> Result<Option<SessionRecord>> {
+        Ok(self.inner.lock().unwrap().get(sid).cloned())
+    }
+
+    fn remove(&self, sid: &str) -> Result<()> {
+        self.inner.lock().unwrap().remove(sid);
+        Ok(())
+    }
+
+    fn all(&self) -> Result<Vec<SessionRecord>> {
+        Ok(self.inner.lock().unwrap().values().cloned().collect())
+    }
+}
+
+/// On-disk backend backed by a sled database
+pub struct SledBackend {
+    db: sled::Db,
+}
+
+impl SledBackend {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self { db: sled::open(path)? })
+    }
+}
+
+impl SessionBackend for SledBackend {
+    fn put(&self, rec: &SessionRecord) -> Result<()> {
+        self.db.insert(rec.sid.as_bytes(), serde_json::to_vec(rec)?)?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    fn get(&self, sid: &str) -> Result<Option<SessionRecord>> {
+        Ok(match self.db.get(sid.as_bytes())? {
+            Some(v) => Some(serde_json::from_slice(&v)?),
+            None => None,
+        })
+    }
+
+    fn remove(&self, sid: &str) -> Result<()> {
+        self.db.remove(sid.as_bytes())?;
+        Ok(())
+    }
+
+    fn all(&self) -> Result<Vec<SessionRecord>> {
+        self.db.iter().values().map(|v| Ok(serde_json::from_slice(&v?)?)).collect()
+    }
+}
+
+/// Session table used for revocation, logout and expiry
+pub struct SessionStore {
+    backend: Box<dyn SessionBackend>,
+}
+
+impl SessionStore {
+    pub fn new(backend: impl SessionBackend + 'static) -> Self {
+        Self { backend: Box::new(backend) }
+    }
+
+    /// In-memory store
+    pub fn memory() -> Self {
+        Self::new(MemoryBackend::default())
+    }
+
+    /// Store selected by `[auth.session].store`; on-disk data lives in `run_dir/sessions`
+    pub fn from_config(cfg: &SessionConfig, run_dir: &str) -> Result<Self> {
+        Ok(match cfg.store {
+            SessionStoreKind::Memory => Self::memory(),
+            SessionStoreKind::Sled => Self::new(SledBackend::open(Path::new(run_dir).join("sessions"))?),
+        })
+    }
+
+    /// Record a freshly issued session that cannot be refreshed
+    pub fn register(&self, s: &Session) -> Result<()> {
+        self.insert(&s.into())
+    }
+
+    /// Record a freshly issued session built by the caller
+    pub fn insert(&self, rec: &SessionRecord) -> Result<()> {
+        self.backend.put(rec)
+    }
+
+    /// The record for `sid`, whatever its state
+    pub fn get(&self, sid: &str) -> Result<Option<SessionRecord>> {
+        self.backend.get(sid)
+    }
+
+    pub fn status(&self, sid: &str) -> Result<SessionStatus> {
+        Ok(match self.backend.get(sid)? {
+            Some(r) if r.revoked => SessionStatus::Revoked,
+            Some(r) if r.expires_at > Utc::now() => SessionStatus::Active,
+            _ => SessionStatus::Unknown,
+        })
+    }
+
+    /// Live refreshable sessions whose window ends before `before`
+    pub fn due(&self, before: DateTime<Utc>) -> Result<Vec<SessionRecord>> {
+        let now = Utc::now();
+        Ok(self
+            .backend
+            .all()?
+            .into_iter()
+            .filter(|r| !r.revoked && r.refresh_token.is_some() && r.expires_at < r.not_after)
+            .filter(|r| r.expires_at > now && r.expires_at <= before)
+            .collect())
+    }
+
+    /// Write back a refreshed record unless the session was revoked meanwhile; returns whether it was stored
+    pub fn renewed(&self, rec: &SessionRecord) -> Result<bool> {
+        match self.backend.get(&rec.sid)? {
+            Some(cur) if !cur.revoked => {
+                self.backend.put(rec)?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Revoke one session; returns whether it was live
+    pub fn revoke(&self, sid: &str) -> Result<bool> {
+        match self.backend.get(sid)? {
+            Some(mut r) if !r.revoked => {
+                r.revoked = true;
+                self.backend.put(&r)?;
+                tracing::info!(sid, sub = %r.sub, "session revoked");
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Revoke every live session of `sub`; returns how many were revoked
+    pub fn revoke_sub(&self, sub: &str) -> Result<usize> {
+        self.revoke_where(|r| r.sub == sub)
+    }
+
+    /// Revoke every live session that came from the IdP login session `idp_sid`
+    pub fn revoke_idp_sid(&self, idp_sid: &str) -> Result<usize> {
+        self.revoke_where(|r| r.idp_sid.as_deref() == Some(idp_sid))
+    }
+
+    /// Live (unrevoked, unexpired) sessions of `sub`, newest window first
+    pub fn live_sessions_of(&self, sub: &str) -> Result<Vec<SessionRecord>> {
+        let now = Utc::now();
+        let mut live: Vec<_> = self.backend.all()?.into_iter().filter(|r| r.sub == sub && !r.revoked && r.expires_at > now).collect();
+        live.sort_by_key(|r| std::cmp::Reverse(r.expires_at));
+        Ok(live)
+    }
+
+    fn revoke_where(&self, pred: impl Fn(&SessionRecord) -> bool) -> Result<usize> {
+        let mut n = 0;
+        for r in self.backend.all()?.into_iter().filter(|r| !r.revoked && pred(r)) {
+            n += usize::from(self.revoke(&r.sid)?);
+        }
+        Ok(n)
+    }
+
+    /// Drop expired records (including revocation tombstones); returns how many were removed
+    pub fn gc(&self) -> Result<usize> {
+        let now = Utc::now();
+        let mut n = 0;
+        for r in self.backend.all()?.into_iter().filter(|r| r.expires_at <= now) {
+            self.backend.remove(&r.sid)?;
+            n += 1;
+        }
+        Ok(n)
+    }
+}
+
+/// Garbage-collect `store` every `every` for the life of the process
+pub fn spawn_gc(store: Arc<SessionStore>, every: Duration) {
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(every);
+        loop {
+            tick.tick().await;
+            match store.gc() {
+                Ok(0) => {}
+                Ok(n) => tracing::debug!(removed = n, "expired sessions collected"),
+                Err(e) => tracing::error!(error = %e, "session GC failed"),
+            }
+        }
+    });
+}
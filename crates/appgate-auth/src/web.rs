@@ -0,0 +1,246 @@
+use axum::{
+    extract::{Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Redirect, Response},
+    routing::{get, post},
+    Form, Router,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+
+use crate::{
+    claims::ClaimMapper,
+    cookie::{CookieSealer, CookieSettings},
+    links::LoginLinks,
+    oidc::OidcClient,
+    session::Session,
+    store::{SessionRecord, SessionStore},
+};
+
+/// Shared state for the browser-facing login endpoints
+#[derive(Clone)]
+pub struct WebState {
+    pub oidc: Arc<OidcClient>,
+    pub sealer: Arc<CookieSealer>,
+    pub mapper: Arc<ClaimMapper>,
+    pub store: Arc<SessionStore>,
+    pub links: Arc<LoginLinks>,
+    pub cookie: CookieSettings,
+}
+
+/// Routes served by appgate-auth to browsers and the IdP (`/oidc/login`, `/oidc/callback`,
+/// `POST /logout`, `/oidc/backchannel-logout`, `/oidc/frontchannel-logout`)
+pub fn router(st: WebState) -> Router {
+    Router::new()
+        .route("/oidc/login", get(login))
+        .route("/oidc/callback", get(callback))
+        .route("/oidc/backchannel-logout", post(backchannel_logout))
+        .route("/oidc/frontchannel-logout", get(frontchannel_logout))
+        // POST only: the cookie is SameSite=Lax, so other sites cannot log users out
+        .route("/logout", post(logout))
+        .with_state(st)
+}
+
+#[derive(Debug, Deserialize)]
+struct LoginParams {
+    return_to: Option<String>,
+    /// Sealed absolute `return_to` from a PDP-minted login link
+    rt: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CallbackParams {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+}
+
+/// Only allow local, absolute-path redirects after login (no open redirects)
+fn safe_return_to(rt: Option<String>) -> String {
+    match rt {
+        Some(p) if p.starts_with('/') && !p.starts_with("//") && !p.contains('\\') => p,
+        _ => "/".into(),
+    }
+}
+
+async fn login(State(st): State<WebState>, Query(q): Query<LoginParams>) -> Response {
+    let return_to = match q.rt.as_deref().map(|rt| st.links.open(rt)) {
+        Some(Some(url)) => url,
+        Some(None) => {
+            tracing::warn!("ignoring invalid or expired sealed return_to");
+            "/".into()
+        }
+        None => safe_return_to(q.return_to),
+    };
+    match st.oidc.begin(&return_to) {
+        Ok(url) => Redirect::to(&url).into_response(),
+        Err(e) => {
+            tracing::error!(error = %e, "building authorize redirect failed");
+            (StatusCode::INTERNAL_SERVER_ERROR, "login unavailable").into_response()
+        }
+    }
+}
+
+async fn callback(State(st): State<WebState>, Query(q): Query<CallbackParams>) -> Response {
+    if let Some(err) = q.error {
+        tracing::warn!(error = %err, "IdP returned an error to the callback");
+        return (StatusCode::UNAUTHORIZED, "login failed").into_response();
+    }
+    let (Some(code), Some(state)) = (q.code, q.state) else {
+        return (StatusCode::BAD_REQUEST, "missing code or state").into_response();
+    };
+    let Some(pending) = st.oidc.take_pending(&state) else {
+        tracing::warn!("callback with unknown or expired state");
+        return (StatusCode::BAD_REQUEST, "unknown or expired login state").into_response();
+    };
+
+    let tokens = match st.oidc.exchange(&code, &pending.verifier).await {
+        Ok(t) => t,
+        Err(e) => {
+            tracing::warn!(error = %e, "code exchange failed");
+            return (StatusCode::UNAUTHORIZED, "login failed").into_response();
+        }
+    };
+    let Some(id_token) = tokens.id_token.as_deref() else {
+        tracing::warn!("token response carried no ID token");
+        return (StatusCode::UNAUTHORIZED, "login failed").into_response();
+    };
+    let claims = match st.oidc.verifier.verify_id_token(id_token, &pending.nonce).await {
+        Ok(c) => c,
+        Err(e) => {
+            tracing::warn!(error = %e, "ID token rejected");
+            return (StatusCode::UNAUTHORIZED, "login failed").into_response();
+        }
+    };
+
+    // with a refresh token the cookie outlives the first window and is capped by the max lifetime
+    let now = chrono::Utc::now();
+    let window_end = now + chrono::Duration::seconds(st.cookie.ttl_seconds as i64);
+    let lifetime = match tokens.refresh_token {
+        Some(_) => st.cookie.max_lifetime_seconds.max(st.cookie.ttl_seconds),
+        None => st.cookie.ttl_seconds,
+    };
+    let not_after = now + chrono::Duration::seconds(lifetime as i64);
+    let Some(session) = Session::from_claims(&claims, &st.mapper, not_after) else {
+        return (StatusCode::UNAUTHORIZED, "login failed").into_response();
+    };
+    tracing::info!(sub = %session.sub, groups = ?session.groups, refreshable = tokens.refresh_token.is_some(), "session established");
+    let rec = SessionRecord {
+        expires_at: window_end.min(not_after),
+        refresh_token: tokens.refresh_token.as_deref().map(|t| st.sealer.seal_secret(t)),
+        id_token: Some(st.sealer.seal_secret(id_token)),
+        idp_sid: claims.get("sid").and_then(|v| v.as_str()).map(str::to_string),
+        ..SessionRecord::from(&session)
+    };
+    if let Err(e) = st.store.insert(&rec) {
+        tracing::error!(error = %e, "storing session failed");
+        return (StatusCode::SERVICE_UNAVAILABLE, "login unavailable").into_response();
+    }
+    let sealed = st.sealer.seal(&session);
+
+    (
+        [(header::SET_COOKIE, st.cookie.set_cookie(&sealed, lifetime))],
+        Redirect::to(&pending.return_to),
+    )
+        .into_response()
+}
+
+#[derive(Debug, Deserialize)]
+struct FrontChannelParams {
+    iss: Option<String>,
+    sid: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BackChannelForm {
+    logout_token: String,
+}
+
+/// The caller's session per the cookie header, if it opens
+fn cookie_session(st: &WebState, headers: &HeaderMap) -> Option<Session> {
+    headers
+        .get(header::COOKIE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|c| st.cookie.find(c))
+        .and_then(|v| st.sealer.unseal(v).ok())
+}
+
+/// Revoke the caller's session (if any), clear the cookie and send them to `return_to`, via the
+/// IdP's `end_session_endpoint` when the session came from an IdP login
+async fn logout(State(st): State<WebState>, headers: HeaderMap, Query(q): Query<LoginParams>) -> Response {
+    let return_to = safe_return_to(q.return_to);
+    let mut target = return_to.clone();
+    if let Some(sess) = cookie_session(&st, &headers) {
+        match st.store.get(&sess.sid) {
+            Ok(Some(rec)) if rec.id_token.is_some() => {
+                let hint = rec.id_token.as_deref().and_then(|t| st.sealer.open_secret(t).ok());
+                match st.oidc.end_session_url(hint.as_deref(), &return_to) {
+                    Ok(Some(url)) => target = url,
+                    Ok(None) => {}
+                    Err(e) => tracing::warn!(error = %e, "building end-session redirect failed"),
+                }
+            }
+            Ok(_) => {}
+            Err(e) => tracing::error!(error = %e, "session store lookup failed"),
+        }
+        match st.store.revoke(&sess.sid) {
+            Ok(_) => tracing::info!(sub = %sess.sub, "user logged out"),
+            Err(e) => tracing::error!(error = %e, "revoking session on logout failed"),
+        }
+    }
+    ([(header::SET_COOKIE, st.cookie.clear_cookie())], Redirect::to(&target)).into_response()
+}
+
+/// OIDC Back-Channel Logout: the IdP POSTs a signed logout token naming a `sid` and/or `sub`
+async fn backchannel_logout(State(st): State<WebState>, Form(f): Form<BackChannelForm>) -> Response {
+    let no_store = [(header::CACHE_CONTROL, "no-store")];
+    let (sid, sub) = match st.oidc.verifier.verify_logout_token(&f.logout_token).await {
+        Ok(ids) => ids,
+        Err(e) => {
+            tracing::warn!(error = %format!("{e:#}"), "logout token rejected");
+            return (StatusCode::BAD_REQUEST, no_store, axum::Json(serde_json::json!({"error": "invalid_request"})))
+                .into_response();
+        }
+    };
+    let revoked = match (&sid, &sub) {
+        (Some(sid), _) => st.store.revoke_idp_sid(sid),
+        (None, Some(sub)) => st.store.revoke_sub(sub),
+        (None, None) => unreachable!("verify_logout_token requires sid or sub"),
+    };
+    match revoked {
+        Ok(n) => {
+            tracing::info!(target: "audit", event = "session.backchannel_logout", idp_sid = ?sid, sub = ?sub, revoked = n);
+            (StatusCode::OK, no_store).into_response()
+        }
+        Err(e) => {
+            tracing::error!(error = %e, "revoking sessions on back-channel logout failed");
+            (StatusCode::SERVICE_UNAVAILABLE, no_store).into_response()
+        }
+    }
+}
+
+/// OIDC Front-Channel Logout: the IdP loads this in an iframe with `iss` and `sid`. The request
+/// is unauthenticated, so it only ends the caller's own session and only when the IdP `sid` matches
+async fn frontchannel_logout(State(st): State<WebState>, headers: HeaderMap, Query(q): Query<FrontChannelParams>) -> Response {
+    let no_store = [(header::CACHE_CONTROL, "no-store")];
+    if let Some(iss) = &q.iss {
+        if iss.trim_end_matches('/') != st.oidc.discovery.issuer.trim_end_matches('/') {
+            return (StatusCode::BAD_REQUEST, no_store).into_response();
+        }
+    }
+    let Some(sess) = cookie_session(&st, &headers) else {
+        return (StatusCode::OK, no_store).into_response();
+    };
+    let matches = match (st.store.get(&sess.sid), &q.sid) {
+        (Ok(Some(rec)), Some(sid)) => rec.idp_sid.as_deref() == Some(sid.as_str()),
+        _ => false,
+    };
+    if !matches {
+        return (StatusCode::OK, no_store).into_response();
+    }
+    match st.store.revoke(&sess.sid) {
+        Ok(_) => tracing::info!(target: "audit", event = "session.frontchannel_logout", sid = %sess.sid, sub = %sess.sub),
+        Err(e) => tracing::error!(error = %e, "revoking session on front-channel logout failed"),
+    }
+    (StatusCode::OK, no_store, [(header::SET_COOKIE, st.cookie.clear_cookie())]).into_response()
+}
@@ -0,0 +1,79 @@
+//! Tests for mapping verified token claims to groups and projected identity claims.
+
+use appgate_auth::claims::ClaimMapper;
+use appgate_ctrl::ClaimMapping;
+use serde_json::{json, Map, Value};
+
+fn mapping(toml_str: &str) -> ClaimMapping {
+    toml::from_str(toml_str).expect("parse [auth.claims]")
+}
+
+fn obj(v: Value) -> Map<String, Value> {
+    v.as_object().unwrap().clone()
+}
+
+#[test]
+fn defaults_read_groups_claim() {
+    let m = ClaimMapper::default();
+    let claims = obj(json!({"sub": "s", "email": "a@x", "groups": ["a", "b"], "exp": 1}));
+    assert_eq!(m.groups(&claims), ["a", "b"]);
+    let projected = m.project(&claims);
+    assert_eq!(projected["sub"], "s");
+    assert_eq!(projected["email"], "a@x");
+    assert!(!projected.contains_key("exp"));
+}
+
+#[test]
+fn keycloak_realm_and_client_roles() {
+    let cfg = mapping(
+        r#"
+        groups = ["/groups", "/realm_access/roles", "/resource_access/{client_id}/roles"]
+        strip_prefix = ["/"]
+        [rename]
+        "gm" = "foundry-admin"
+        "#,
+    );
+    let m = ClaimMapper::new(&cfg, "appgate");
+    let claims = obj(json!({
+        "groups": ["/foundry-players", "/staff"],
+        "realm_access": {"roles": ["offline_access", "foundry-players"]},
+        "resource_access": {"appgate": {"roles": ["gm"]}, "other": {"roles": ["root"]}},
+    }));
+    assert_eq!(m.groups(&claims), ["foundry-players", "staff", "offline_access", "foundry-admin"]);
+}
+
+#[test]
+fn azure_wids_and_roles() {
+    let cfg = mapping(r#"groups = ["/roles", "/wids"]"#);
+    let m = ClaimMapper::new(&cfg, "appgate");
+    let claims = obj(json!({"roles": "Reader", "wids": ["62e90394-69f5-4237-9190-012177145e10"]}));
+    assert_eq!(m.groups(&claims), ["Reader", "62e90394-69f5-4237-9190-012177145e10"]);
+}
+
+#[test]
+fn missing_paths_and_non_string_values_are_ignored() {
+    let cfg = mapping(r#"groups = ["/nope", "/groups", "/realm_access/roles"]"#);
+    let m = ClaimMapper::new(&cfg, "appgate");
+    let claims = obj(json!({"groups": [1, "ok", null], "realm_access": {"roles": {"x": 1}}}));
+    assert_eq!(m.groups(&claims), ["ok"]);
+}
+
+#[test]
+fn projected_claims_follow_custom_pointers() {
+    let cfg = mapping(
+        r#"
+        [claims]
+        sub = "/sub"
+        user = "/preferred_username"
+        tenant = "/ext/tenant"
+        verified = "/email_verified"
+        "#,
+    );
+    let m = ClaimMapper::new(&cfg, "appgate");
+    let claims = obj(json!({"sub": "s", "preferred_username": "alice", "ext": {"tenant": "t1"}, "email_verified": true}));
+    let p = m.project(&claims);
+    assert_eq!(p["user"], "alice");
+    assert_eq!(p["tenant"], "t1");
+    assert_eq!(p["verified"], "true");
+    assert_eq!(p.len(), 4);
+}
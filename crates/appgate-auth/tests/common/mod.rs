@@ -0,0 +1,355 @@
+//! Stand-in OpenID Provider used by the appgate-auth integration tests.
+//!
+//! It implements just enough of Keycloak's surface (discovery, authorize, token, JWKS, logout tokens) for the
+//! Authorization Code + PKCE flow to run in-process without a container. Tokens are signed
+//! with the fixture keys in `fixtures/idp-keys.json`. [`start_gateway`] runs appgate-auth's
+//! browser endpoints against it.
+#![allow(dead_code)]
+
+use appgate_auth::{
+    claims::ClaimMapper,
+    cookie::{CookieSealer, CookieSettings},
+    links::LoginLinks,
+    oidc::OidcClient,
+    store::SessionStore,
+    web,
+};
+use appgate_ctrl::Oidc;
+use axum::{
+    extract::{Form, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Redirect, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    net::{SocketAddr, TcpListener},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+pub const CLIENT_ID: &str = "appgate";
+pub const CLIENT_SECRET: &str = "secret-appgate";
+
+struct Grant {
+    nonce: String,
+    challenge: String,
+    /// IdP login session the code belongs to
+    sid: String,
+}
+
+/// A signing key from `fixtures/idp-keys.json`
+#[derive(Clone, Deserialize)]
+pub struct TestKey {
+    pub kid: String,
+    pub alg: String,
+    pub private_pem: String,
+    pub jwk: Value,
+}
+
+impl TestKey {
+    fn sign(&self, claims: &Value) -> String {
+        let (alg, key) = match self.alg.as_str() {
+            "RS256" => (Algorithm::RS256, EncodingKey::from_rsa_pem(self.private_pem.as_bytes())),
+            "ES256" => (Algorithm::ES256, EncodingKey::from_ec_pem(self.private_pem.as_bytes())),
+            "EdDSA" => (Algorithm::EdDSA, EncodingKey::from_ed_pem(self.private_pem.as_bytes())),
+            other => panic!("unsupported fixture alg {other}"),
+        };
+        let mut header = Header::new(alg);
+        header.kid = Some(self.kid.clone());
+        jsonwebtoken::encode(&header, claims, &key.unwrap()).unwrap()
+    }
+}
+
+/// All fixture keys
+pub fn test_keys() -> Vec<TestKey> {
+    serde_json::from_str(include_str!("../fixtures/idp-keys.json")).unwrap()
+}
+
+struct IdpState {
+    issuer: String,
+    groups: Mutex<Vec<String>>,
+    codes: Mutex<HashMap<String, Grant>>,
+    /// Outstanding refresh tokens and their IdP session ids; each token is single use
+    refresh_tokens: Mutex<Vec<(String, String)>>,
+    refresh_disabled: AtomicBool,
+    keys: Vec<TestKey>,
+    /// kids served from the JWKS endpoint
+    published: Mutex<Vec<String>>,
+    signing_kid: Mutex<String>,
+    jwks_fetches: AtomicUsize,
+    logins: AtomicUsize,
+}
+
+impl IdpState {
+    fn key(&self, kid: &str) -> &TestKey {
+        self.keys.iter().find(|k| k.kid == kid).expect("fixture kid")
+    }
+
+    fn sign(&self, claims: &Value) -> String {
+        self.key(&self.signing_kid.lock().unwrap()).sign(claims)
+    }
+}
+
+/// Handle to a running stand-in IdP
+pub struct StandInIdp {
+    pub issuer: String,
+    st: Arc<IdpState>,
+}
+
+impl StandInIdp {
+    /// Sign `claims` with the current signing key
+    pub fn sign(&self, claims: &Value) -> String {
+        self.st.sign(claims)
+    }
+
+    /// Sign `claims` with a specific fixture key, published or not
+    pub fn sign_with(&self, kid: &str, claims: &Value) -> String {
+        self.st.key(kid).sign(claims)
+    }
+
+    /// Replace the published key set with `kid` alone and sign with it from now on
+    pub fn rotate_to(&self, kid: &str) {
+        *self.st.published.lock().unwrap() = vec![kid.to_string()];
+        *self.st.signing_kid.lock().unwrap() = kid.to_string();
+    }
+
+    /// Change alice's group membership as seen by subsequent token responses
+    pub fn set_groups(&self, groups: &[&str]) {
+        *self.st.groups.lock().unwrap() = groups.iter().map(|g| g.to_string()).collect();
+    }
+
+    /// Reject every refresh token from now on, as if the user's IdP session ended
+    pub fn revoke_refresh_tokens(&self) {
+        self.st.refresh_disabled.store(true, Ordering::SeqCst);
+    }
+
+    /// A signed back-channel logout token naming `sid` and/or `sub`
+    pub fn logout_token(&self, sid: Option<&str>, sub: Option<&str>) -> String {
+        let now = chrono::Utc::now().timestamp();
+        let mut claims = json!({
+            "iss": self.issuer,
+            "aud": CLIENT_ID,
+            "iat": now,
+            "exp": now + 120,
+            "jti": format!("lt-{now}"),
+            "events": { "http://schemas.openid.net/event/backchannel-logout": {} },
+        });
+        if let Some(sid) = sid {
+            claims["sid"] = json!(sid);
+        }
+        if let Some(sub) = sub {
+            claims["sub"] = json!(sub);
+        }
+        self.sign(&claims)
+    }
+
+    /// Number of times the JWKS endpoint has been fetched
+    pub fn jwks_fetches(&self) -> usize {
+        self.st.jwks_fetches.load(Ordering::SeqCst)
+    }
+
+    /// Baseline ID token claims for alice, valid for five minutes
+    pub fn claims(&self, nonce: &str) -> Value {
+        let now = chrono::Utc::now().timestamp();
+        json!({
+            "iss": self.issuer,
+            "aud": CLIENT_ID,
+            "sub": "alice-sub",
+            "nonce": nonce,
+            "iat": now,
+            "exp": now + 300,
+        })
+    }
+}
+
+impl StandInIdp {
+    /// Start an IdP on an ephemeral port whose user "alice" belongs to `groups`
+    pub fn start(groups: &[&str]) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let addr: SocketAddr = listener.local_addr().unwrap();
+        let issuer = format!("http://{addr}/realms/test");
+        let st = Arc::new(IdpState {
+            issuer: issuer.clone(),
+            groups: Mutex::new(groups.iter().map(|g| g.to_string()).collect()),
+            codes: Mutex::new(HashMap::new()),
+            refresh_tokens: Mutex::new(Vec::new()),
+            refresh_disabled: AtomicBool::new(false),
+            keys: test_keys(),
+            published: Mutex::new(vec!["rsa-1".into(), "ec-1".into(), "ed-1".into()]),
+            signing_kid: Mutex::new("rsa-1".into()),
+            jwks_fetches: AtomicUsize::new(0),
+            logins: AtomicUsize::new(0),
+        });
+        let app = Router::new()
+            .route("/realms/test/.well-known/openid-configuration", get(discovery))
+            .route("/realms/test/authorize", get(authorize))
+            .route("/realms/test/token", post(token))
+            .route("/realms/test/certs", get(certs))
+            .with_state(st.clone());
+        tokio::spawn(async move {
+            axum::serve(tokio::net::TcpListener::from_std(listener).unwrap(), app).await.unwrap();
+        });
+        Self { issuer, st }
+    }
+}
+
+async fn certs(State(st): State<Arc<IdpState>>) -> Json<Value> {
+    st.jwks_fetches.fetch_add(1, Ordering::SeqCst);
+    let published = st.published.lock().unwrap().clone();
+    Json(json!({ "keys": published.iter().map(|kid| st.key(kid).jwk.clone()).collect::<Vec<_>>() }))
+}
+
+async fn discovery(State(st): State<Arc<IdpState>>) -> Json<Value> {
+    Json(json!({
+        "issuer": st.issuer,
+        "authorization_endpoint": format!("{}/authorize", st.issuer),
+        "token_endpoint": format!("{}/token", st.issuer),
+        "jwks_uri": format!("{}/certs", st.issuer),
+        "end_session_endpoint": format!("{}/logout", st.issuer),
+    }))
+}
+
+/// Auto-approves alice and redirects straight back with a code
+async fn authorize(State(st): State<Arc<IdpState>>, Query(q): Query<HashMap<String, String>>) -> Response {
+    if q.get("client_id").map(String::as_str) != Some(CLIENT_ID) || q.get("code_challenge_method").map(String::as_str) != Some("S256") {
+        return (StatusCode::BAD_REQUEST, "bad authorize request").into_response();
+    }
+    let n = st.logins.fetch_add(1, Ordering::SeqCst);
+    let code = format!("code-{n}");
+    st.codes.lock().unwrap().insert(
+        code.clone(),
+        Grant { nonce: q["nonce"].clone(), challenge: q["code_challenge"].clone(), sid: format!("kc-sess-{n}") },
+    );
+    let mut url = url::Url::parse(&q["redirect_uri"]).unwrap();
+    url.query_pairs_mut().append_pair("code", &code).append_pair("state", &q["state"]);
+    Redirect::to(url.as_str()).into_response()
+}
+
+fn invalid_grant() -> Response {
+    (StatusCode::BAD_REQUEST, Json(json!({"error": "invalid_grant"}))).into_response()
+}
+
+/// Handles `authorization_code` (with PKCE) and `refresh_token` grants; refresh tokens rotate
+async fn token(State(st): State<Arc<IdpState>>, Form(f): Form<HashMap<String, String>>) -> Response {
+    let (nonce, sid) = match f.get("grant_type").map(String::as_str) {
+        Some("authorization_code") => {
+            let Some(grant) = f.get("code").and_then(|c| st.codes.lock().unwrap().remove(c)) else {
+                return invalid_grant();
+            };
+            let verifier = f.get("code_verifier").cloned().unwrap_or_default();
+            if URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes())) != grant.challenge {
+                return invalid_grant();
+            }
+            (Some(grant.nonce), grant.sid)
+        }
+        Some("refresh_token") => {
+            let presented = f.get("refresh_token").cloned().unwrap_or_default();
+            let mut outstanding = st.refresh_tokens.lock().unwrap();
+            let Some(i) = outstanding.iter().position(|(t, _)| *t == presented) else {
+                return invalid_grant();
+            };
+            let (_, sid) = outstanding.remove(i);
+            if st.refresh_disabled.load(Ordering::SeqCst) {
+                return invalid_grant();
+            }
+            (None, sid)
+        }
+        _ => return (StatusCode::BAD_REQUEST, Json(json!({"error": "unsupported_grant_type"}))).into_response(),
+    };
+    let now = chrono::Utc::now().timestamp();
+    let mut claims = json!({
+        "iss": st.issuer,
+        "aud": CLIENT_ID,
+        "sub": "alice-sub",
+        "email": "alice@example.com",
+        "preferred_username": "alice",
+        "groups": *st.groups.lock().unwrap(),
+        "sid": sid,
+        "iat": now,
+        "exp": now + 300,
+    });
+    if let Some(nonce) = nonce {
+        claims["nonce"] = json!(nonce);
+    }
+    let id_token = st.sign(&claims);
+    let mut outstanding = st.refresh_tokens.lock().unwrap();
+    let refresh_token = format!("rt-{}", appgate_auth::oidc::random_token(16));
+    outstanding.push((refresh_token.clone(), sid));
+    Json(json!({
+        "access_token": "at",
+        "token_type": "Bearer",
+        "expires_in": 300,
+        "id_token": id_token,
+        "refresh_token": refresh_token,
+    }))
+    .into_response()
+}
+
+/// appgate-auth's browser endpoints running against a [`StandInIdp`]
+pub struct Gateway {
+    pub base: String,
+    pub oidc: Arc<OidcClient>,
+    pub sealer: Arc<CookieSealer>,
+    pub store: Arc<SessionStore>,
+    pub links: Arc<LoginLinks>,
+}
+
+/// Serve the login/callback/logout routes on an ephemeral port with sessions of `ttl_seconds`
+pub async fn start_gateway(idp: &StandInIdp, ttl_seconds: u64) -> Gateway {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.set_nonblocking(true).unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+    let cfg = Oidc {
+        issuer: idp.issuer.clone(),
+        client_id: CLIENT_ID.into(),
+        client_secret: CLIENT_SECRET.into(),
+        redirect_uri: format!("{base}/oidc/callback"),
+        cookie_name: "appg_sess".into(),
+        cookie_domain: "127.0.0.1".into(),
+        session_ttl_seconds: ttl_seconds,
+        session_max_lifetime_seconds: 12 * 3600,
+        clock_skew_seconds: 60,
+    };
+    let oidc = Arc::new(OidcClient::discover(&cfg).await.expect("discovery"));
+    let sealer = Arc::new(CookieSealer::ephemeral());
+    let store = Arc::new(SessionStore::memory());
+    let links = Arc::new(LoginLinks::new(&cfg.redirect_uri, sealer.clone()).unwrap());
+    let st = web::WebState {
+        oidc: oidc.clone(),
+        sealer: sealer.clone(),
+        mapper: Arc::new(ClaimMapper::default()),
+        store: store.clone(),
+        links: links.clone(),
+        cookie: CookieSettings {
+            name: cfg.cookie_name,
+            domain: cfg.cookie_domain,
+            ttl_seconds,
+            max_lifetime_seconds: cfg.session_max_lifetime_seconds,
+        },
+    };
+    tokio::spawn(async move {
+        axum::serve(tokio::net::TcpListener::from_std(listener).unwrap(), web::router(st)).await.unwrap();
+    });
+    Gateway { base, oidc, sealer, store, links }
+}
+
+/// Run the browser login round trip and return the session cookie value
+pub async fn login(gw: &Gateway) -> String {
+    let http = reqwest::Client::builder().redirect(reqwest::redirect::Policy::none()).build().unwrap();
+    let location = |r: &reqwest::Response| r.headers()[reqwest::header::LOCATION].to_str().unwrap().to_string();
+    let resp = http.get(format!("{}/oidc/login", gw.base)).send().await.unwrap();
+    let resp = http.get(location(&resp)).send().await.unwrap();
+    let resp = http.get(location(&resp)).send().await.unwrap();
+    let set_cookie = resp.headers()[reqwest::header::SET_COOKIE].to_str().unwrap();
+    set_cookie.split(';').next().unwrap().strip_prefix("appg_sess=").unwrap().to_string()
+}
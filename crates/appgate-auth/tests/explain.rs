@@ -0,0 +1,111 @@
+//! Tests for the session admin `Explain` RPC and its operator report.
+
+use appgate_auth::{admin::AdminSvc, cookie::CookieSealer, explain::report, pdp::PdpSvc, session::Session, store::SessionStore};
+use appgate_ipc::pdp::{session_admin_server::SessionAdmin, Attributes, DecisionRequest, ExplainRequest};
+use arc_swap::ArcSwap;
+use chrono::{Duration, Utc};
+use std::sync::Arc;
+
+fn pdp() -> PdpSvc {
+    let policy: appgate_policy::Policy = toml::from_str(
+        r#"
+        [[rules]]
+        name = "foundry-admin"
+        protocol = "http"
+        resource = "http://foundry/admin"
+        require_groups = ["foundry-admin"]
+
+        [[rules]]
+        name = "foundry-players"
+        protocol = "http"
+        resource = "http://foundry/"
+        require_groups = ["foundry-players"]
+        [rules.when]
+        method = ["GET", "HEAD"]
+        "#,
+    )
+    .unwrap();
+    PdpSvc {
+        policy: Arc::new(ArcSwap::from_pointee(policy.into())),
+        sealer: Arc::new(CookieSealer::ephemeral()),
+        store: Arc::new(SessionStore::memory()),
+        login: None,
+    }
+}
+
+fn admin(pdp: PdpSvc) -> AdminSvc {
+    AdminSvc { store: pdp.store.clone(), pdp: Arc::new(pdp) }
+}
+
+fn request(resource: &str, method: &str) -> DecisionRequest {
+    let kv = [("method".to_string(), method.to_string())].into();
+    DecisionRequest { protocol: "http".into(), resource: resource.into(), attributes: Some(Attributes { kv }), ..Default::default() }
+}
+
+#[tokio::test]
+async fn explains_a_subjects_denial_rule_by_rule() {
+    let pdp = pdp();
+    let alice = Session {
+        sid: "s1".into(),
+        sub: "alice".into(),
+        groups: vec!["foundry-players".into()],
+        claims: Default::default(),
+        expires_at: Utc::now() + Duration::hours(1),
+    };
+    pdp.store.register(&alice).unwrap();
+    let admin = admin(pdp);
+
+    let req = ExplainRequest { request: Some(request("http://foundry/admin", "POST")), sub: "alice".into(), groups: vec![] };
+    let x = admin.explain(tonic::Request::new(req)).await.unwrap().into_inner();
+    assert_eq!(x.identity, "sub alice (session s1)");
+    assert_eq!(x.groups, ["foundry-players"]);
+    assert_eq!(x.decision.as_ref().unwrap().reason, "missing group");
+    assert_eq!(x.rules.len(), 2);
+    assert!(x.rules[0].resource_matched && !x.rules[0].groups_matched);
+    assert_eq!(x.rules[1].summary, "condition failed: method");
+
+    let text = report(&x);
+    assert!(text.contains("decision: DENY (forbidden): missing group"), "{text}");
+    assert!(text.contains("foundry-admin    allow      0  caller lacks required groups"), "{text}");
+    assert!(text.contains("method = \"POST\": failed"), "{text}");
+
+    // the same request through the token path agrees
+    let mut r = request("http://foundry/admin", "POST");
+    r.session_token = admin.pdp.sealer.seal(&alice);
+    let x = admin.explain(tonic::Request::new(ExplainRequest { request: Some(r), ..Default::default() })).await.unwrap().into_inner();
+    assert_eq!(x.identity, "sub alice (session s1)");
+    assert_eq!(x.decision.unwrap().reason, "missing group");
+}
+
+#[tokio::test]
+async fn explains_for_given_groups_or_reports_missing_sessions() {
+    let admin = admin(pdp());
+    let req = ExplainRequest { request: Some(request("http://foundry/game", "GET")), sub: String::new(), groups: vec!["foundry-players".into()] };
+    let x = admin.explain(tonic::Request::new(req)).await.unwrap().into_inner();
+    assert_eq!(x.identity, "given groups");
+    assert_eq!(x.decision.as_ref().unwrap().reason, "policy: foundry-players");
+    assert!(x.rules[1].decisive);
+    assert!(report(&x).contains("* foundry-players"));
+
+    let req = ExplainRequest { request: Some(request("http://foundry/", "GET")), sub: "nobody".into(), groups: vec![] };
+    let err = admin.explain(tonic::Request::new(req)).await.unwrap_err();
+    assert_eq!(err.code(), tonic::Code::NotFound);
+
+    let mut r = request("http://foundry/", "GET");
+    r.session_token = "garbage".into();
+    let x = admin.explain(tonic::Request::new(ExplainRequest { request: Some(r), ..Default::default() })).await.unwrap().into_inner();
+    assert_eq!(x.identity, "no usable session");
+    assert!(x.decision.unwrap().reason.starts_with("unauthenticated: "));
+}
+
+#[tokio::test]
+async fn report_shows_where_rules_are_defined() {
+    let file = concat!(env!("CARGO_MANIFEST_DIR"), "/../../config/policy/foundry.toml");
+    let admin = admin(PdpSvc { policy: Arc::new(ArcSwap::from_pointee(appgate_policy::Policy::load(file).unwrap().into())), ..pdp() });
+    let req = ExplainRequest { request: Some(request("http://foundry/", "GET")), sub: String::new(), groups: vec!["foundry-players".into()] };
+    let x = admin.explain(tonic::Request::new(req)).await.unwrap().into_inner();
+    assert_eq!(x.rules[1].source, format!("{file}:11"));
+    let text = report(&x);
+    assert!(text.contains("SOURCE"), "{text}");
+    assert!(text.contains(&format!("* foundry-players  {file}:11  allow")), "{text}");
+}
@@ -0,0 +1,110 @@
+//! Tests for JWKS-backed ID token verification.
+
+mod common;
+
+use appgate_auth::jwt::{JwksCache, TokenVerifier};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use common::{StandInIdp, CLIENT_ID};
+use serde_json::json;
+use std::time::Duration;
+
+async fn verifier(idp: &StandInIdp, min_refresh: Duration) -> TokenVerifier {
+    let jwks = JwksCache::fetch(&format!("{}/certs", idp.issuer), reqwest::Client::new())
+        .await
+        .unwrap()
+        .with_min_refresh(min_refresh);
+    TokenVerifier { issuer: idp.issuer.clone(), audience: CLIENT_ID.into(), skew: 30, jwks }
+}
+
+#[tokio::test]
+async fn accepts_rs256_es256_and_eddsa() {
+    let idp = StandInIdp::start(&[]);
+    let v = verifier(&idp, Duration::from_secs(30)).await;
+    for kid in ["rsa-1", "ec-1", "ed-1"] {
+        let token = idp.sign_with(kid, &idp.claims("n-1"));
+        let claims = v.verify_id_token(&token, "n-1").await.unwrap_or_else(|e| panic!("{kid}: {e:#}"));
+        assert_eq!(claims["sub"], "alice-sub");
+    }
+}
+
+#[tokio::test]
+async fn rejects_bad_claims() {
+    let idp = StandInIdp::start(&[]);
+    let v = verifier(&idp, Duration::from_secs(30)).await;
+    let now = chrono::Utc::now().timestamp();
+    let mut cases = Vec::new();
+    for (field, value) in [
+        ("iss", json!("https://evil.example/realms/test")),
+        ("aud", json!("someone-else")),
+        ("exp", json!(now - 120)),
+        ("nbf", json!(now + 120)),
+        ("iat", json!(now + 120)),
+    ] {
+        let mut c = idp.claims("n-1");
+        c[field] = value;
+        cases.push((field, c));
+    }
+    for (field, claims) in cases {
+        let token = idp.sign(&claims);
+        assert!(v.verify_id_token(&token, "n-1").await.is_err(), "{field} should be rejected");
+    }
+    // nonce bound to a different login attempt
+    let token = idp.sign(&idp.claims("n-1"));
+    assert!(v.verify_id_token(&token, "n-2").await.is_err());
+}
+
+#[tokio::test]
+async fn tolerates_configured_clock_skew() {
+    let idp = StandInIdp::start(&[]);
+    let v = verifier(&idp, Duration::from_secs(30)).await;
+    let mut claims = idp.claims("n-1");
+    claims["exp"] = json!(chrono::Utc::now().timestamp() - 10);
+    assert!(v.verify_id_token(&idp.sign(&claims), "n-1").await.is_ok());
+}
+
+#[tokio::test]
+async fn rejects_tampered_and_unsigned_tokens() {
+    let idp = StandInIdp::start(&[]);
+    let v = verifier(&idp, Duration::from_secs(30)).await;
+    let token = idp.sign(&idp.claims("n-1"));
+    let (head, rest) = token.split_once('.').unwrap();
+    let (_, sig) = rest.split_once('.').unwrap();
+    let mut forged = idp.claims("n-1");
+    forged["sub"] = json!("mallory");
+    let swapped = format!("{head}.{}.{sig}", URL_SAFE_NO_PAD.encode(forged.to_string()));
+    assert!(v.verify(&swapped).await.is_err());
+
+    let unsigned = format!(
+        "{}.{}.",
+        URL_SAFE_NO_PAD.encode(br#"{"alg":"none","kid":"rsa-1"}"#),
+        URL_SAFE_NO_PAD.encode(idp.claims("n-1").to_string())
+    );
+    assert!(v.verify(&unsigned).await.is_err());
+}
+
+#[tokio::test]
+async fn unknown_kid_refreshes_jwks_after_rotation() {
+    let idp = StandInIdp::start(&[]);
+    let v = verifier(&idp, Duration::ZERO).await;
+    assert_eq!(idp.jwks_fetches(), 1);
+
+    idp.rotate_to("rsa-2");
+    let token = idp.sign(&idp.claims("n-1"));
+    assert!(v.verify_id_token(&token, "n-1").await.is_ok());
+    assert_eq!(idp.jwks_fetches(), 2);
+
+    // cached now: no further fetch
+    assert!(v.verify_id_token(&token, "n-1").await.is_ok());
+    assert_eq!(idp.jwks_fetches(), 2);
+}
+
+#[tokio::test]
+async fn unknown_kid_refresh_is_rate_limited() {
+    let idp = StandInIdp::start(&[]);
+    let v = verifier(&idp, Duration::from_secs(3600)).await;
+    for _ in 0..5 {
+        let token = idp.sign_with("rsa-2", &idp.claims("n-1"));
+        assert!(v.verify(&token).await.is_err());
+    }
+    assert_eq!(idp.jwks_fetches(), 1);
+}
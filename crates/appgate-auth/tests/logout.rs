@@ -0,0 +1,132 @@
+//! Tests for IdP-driven logout: back-channel, front-channel and RP-initiated.
+
+mod common;
+
+use appgate_auth::store::SessionStatus;
+use common::{login, start_gateway, Gateway, StandInIdp};
+use reqwest::{header, redirect::Policy as RedirectPolicy, StatusCode};
+
+fn http() -> reqwest::Client {
+    reqwest::Client::builder().redirect(RedirectPolicy::none()).build().unwrap()
+}
+
+async fn backchannel(gw: &Gateway, token: &str) -> StatusCode {
+    http()
+        .post(format!("{}/oidc/backchannel-logout", gw.base))
+        .form(&[("logout_token", token)])
+        .send()
+        .await
+        .unwrap()
+        .status()
+}
+
+fn status(gw: &Gateway, cookie: &str) -> SessionStatus {
+    gw.store.status(&gw.sealer.unseal(cookie).unwrap().sid).unwrap()
+}
+
+fn idp_sid(gw: &Gateway, cookie: &str) -> String {
+    let sid = gw.sealer.unseal(cookie).unwrap().sid;
+    gw.store.get(&sid).unwrap().unwrap().idp_sid.expect("IdP sid recorded")
+}
+
+#[tokio::test]
+async fn backchannel_logout_revokes_by_sid_or_sub() {
+    let idp = StandInIdp::start(&[]);
+    let gw = start_gateway(&idp, 3600).await;
+    let first = login(&gw).await;
+    let second = login(&gw).await;
+    let third = login(&gw).await;
+
+    let token = idp.logout_token(Some(&idp_sid(&gw, &first)), Some("alice-sub"));
+    assert_eq!(backchannel(&gw, &token).await, StatusCode::OK);
+    assert_eq!(status(&gw, &first), SessionStatus::Revoked);
+    assert_eq!(status(&gw, &second), SessionStatus::Active);
+
+    // sub alone ends every session of the user
+    assert_eq!(backchannel(&gw, &idp.logout_token(None, Some("alice-sub"))).await, StatusCode::OK);
+    assert_eq!(status(&gw, &second), SessionStatus::Revoked);
+    assert_eq!(status(&gw, &third), SessionStatus::Revoked);
+}
+
+#[tokio::test]
+async fn backchannel_logout_rejects_invalid_tokens() {
+    let idp = StandInIdp::start(&[]);
+    let gw = start_gateway(&idp, 3600).await;
+    let cookie = login(&gw).await;
+    let sid = idp_sid(&gw, &cookie);
+    let now = chrono::Utc::now().timestamp();
+    let base = serde_json::json!({
+        "iss": idp.issuer,
+        "aud": common::CLIENT_ID,
+        "iat": now,
+        "exp": now + 120,
+        "sid": sid,
+        "events": { "http://schemas.openid.net/event/backchannel-logout": {} },
+    });
+
+    let mut no_event = base.clone();
+    no_event.as_object_mut().unwrap().remove("events");
+    let mut with_nonce = base.clone();
+    with_nonce["nonce"] = "n".into();
+    let mut no_ids = base.clone();
+    no_ids.as_object_mut().unwrap().remove("sid");
+    let mut wrong_aud = base.clone();
+    wrong_aud["aud"] = "someone-else".into();
+
+    for token in [
+        idp.sign(&no_event),
+        idp.sign(&with_nonce),
+        idp.sign(&no_ids),
+        idp.sign(&wrong_aud),
+        idp.sign_with("rsa-2", &base),
+        "not-a-jwt".to_string(),
+    ] {
+        assert_eq!(backchannel(&gw, &token).await, StatusCode::BAD_REQUEST);
+    }
+    assert_eq!(status(&gw, &cookie), SessionStatus::Active);
+}
+
+#[tokio::test]
+async fn frontchannel_logout_requires_matching_sid() {
+    let idp = StandInIdp::start(&[]);
+    let gw = start_gateway(&idp, 3600).await;
+    let cookie = login(&gw).await;
+    let frontchannel = |sid: String| {
+        http()
+            .get(format!("{}/oidc/frontchannel-logout", gw.base))
+            .query(&[("iss", idp.issuer.as_str()), ("sid", sid.as_str())])
+            .header(header::COOKIE, format!("appg_sess={cookie}"))
+            .send()
+    };
+
+    let resp = frontchannel("kc-sess-other".into()).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(resp.headers().get(header::SET_COOKIE).is_none());
+    assert_eq!(status(&gw, &cookie), SessionStatus::Active);
+
+    let resp = frontchannel(idp_sid(&gw, &cookie)).await.unwrap();
+    assert!(resp.headers()[header::SET_COOKIE].to_str().unwrap().starts_with("appg_sess=; "));
+    assert_eq!(status(&gw, &cookie), SessionStatus::Revoked);
+}
+
+#[tokio::test]
+async fn logout_redirects_to_end_session_endpoint() {
+    let idp = StandInIdp::start(&[]);
+    let gw = start_gateway(&idp, 3600).await;
+    let cookie = login(&gw).await;
+
+    let resp = http()
+        .post(format!("{}/logout?return_to=/bye", gw.base))
+        .header(header::COOKIE, format!("appg_sess={cookie}"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+    let location = url::Url::parse(resp.headers()[header::LOCATION].to_str().unwrap()).unwrap();
+    assert!(location.as_str().starts_with(&format!("{}/logout?", idp.issuer)));
+    let q: std::collections::HashMap<_, _> = location.query_pairs().into_owned().collect();
+    assert_eq!(q["client_id"], common::CLIENT_ID);
+    assert_eq!(q["post_logout_redirect_uri"], format!("{}/bye", gw.base));
+    assert_eq!(q["id_token_hint"].split('.').count(), 3);
+    assert_eq!(status(&gw, &cookie), SessionStatus::Revoked);
+}
@@ -0,0 +1,130 @@
+//! Integration tests for the OIDC Authorization Code + PKCE login flow.
+//!
+//! A stand-in IdP (see `common`) replaces Keycloak so the full browser round trip — login
+//! redirect, authorize, callback, code exchange — runs in-process, after which the minted
+//! session cookie is presented to the PDP service.
+
+mod common;
+
+use appgate_auth::pdp::PdpSvc;
+use appgate_ipc::pdp::{pdp_server::Pdp, Attributes, DecisionRequest, Outcome};
+use arc_swap::ArcSwap;
+use common::{start_gateway, StandInIdp};
+use reqwest::{header, redirect::Policy as RedirectPolicy, StatusCode};
+use std::sync::Arc;
+
+fn location(resp: &reqwest::Response) -> String {
+    resp.headers()[header::LOCATION].to_str().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn login_flow_mints_session_accepted_by_pdp() {
+    let idp = StandInIdp::start(&["foundry-players"]);
+    let gw = start_gateway(&idp, 3600).await;
+    let http = reqwest::Client::builder().redirect(RedirectPolicy::none()).build().unwrap();
+
+    // 1) gateway login → IdP authorize
+    let resp = http.get(format!("{}/oidc/login?return_to=/foundry/", gw.base)).send().await.unwrap();
+    assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+    let authorize = location(&resp);
+    assert!(authorize.starts_with(&idp.issuer));
+    assert!(authorize.contains("code_challenge_method=S256"));
+
+    // 2) IdP → gateway callback
+    let resp = http.get(&authorize).send().await.unwrap();
+    let callback = location(&resp);
+    assert!(callback.starts_with(&format!("{}/oidc/callback", gw.base)));
+
+    // 3) callback → session cookie + redirect back to the original page
+    let resp = http.get(&callback).send().await.unwrap();
+    assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+    assert_eq!(location(&resp), "/foundry/");
+    let set_cookie = resp.headers()[header::SET_COOKIE].to_str().unwrap();
+    assert!(set_cookie.contains("HttpOnly") && set_cookie.contains("Secure") && set_cookie.contains("SameSite=Lax"));
+    let token = set_cookie.split(';').next().unwrap().strip_prefix("appg_sess=").unwrap().to_string();
+
+    // 4) the PDP now knows alice and her groups
+    let policy = appgate_policy::Policy::load(concat!(env!("CARGO_MANIFEST_DIR"), "/../../config/policy/foundry.toml")).unwrap();
+    let pdp = PdpSvc { policy: Arc::new(ArcSwap::from_pointee(policy.into())), sealer: gw.sealer.clone(), store: gw.store.clone(), login: None };
+    let resp = pdp
+        .decide(tonic::Request::new(DecisionRequest {
+            session_token: token,
+            protocol: "http".into(),
+            resource: "http://foundry/".into(),
+            ..Default::default()
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(resp.claims.get("sub").map(String::as_str), Some("alice-sub"));
+    assert_eq!(resp.claims.get("email").map(String::as_str), Some("alice@example.com"));
+    assert!(resp.allow, "reason: {}", resp.reason);
+    assert_eq!(resp.reason, "policy: foundry-players");
+}
+
+#[tokio::test]
+async fn callback_rejects_unknown_state() {
+    let idp = StandInIdp::start(&[]);
+    let gw = start_gateway(&idp, 3600).await;
+    let resp = reqwest::get(format!("{}/oidc/callback?code=code-0&state=forged", gw.base)).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn unauthenticated_requests_get_a_login_link_back_to_the_site() {
+    let idp = StandInIdp::start(&["foundry-admin"]);
+    let gw = start_gateway(&idp, 3600).await;
+    let policy = appgate_policy::Policy::load(concat!(env!("CARGO_MANIFEST_DIR"), "/../../config/policy/foundry.toml")).unwrap();
+    let pdp = PdpSvc { policy: Arc::new(ArcSwap::from_pointee(policy.into())), sealer: gw.sealer.clone(), store: gw.store.clone(), login: Some(gw.links.clone()) };
+    let site = "https://foundry.example.com/game?scene=1";
+    let decide = |token: String| {
+        pdp.decide(tonic::Request::new(DecisionRequest {
+            session_token: token,
+            protocol: "http".into(),
+            resource: "http://foundry/game".into(),
+            attributes: Some(Attributes { kv: [("url".to_string(), site.to_string())].into() }),
+            ..Default::default()
+        }))
+    };
+
+    let resp = decide(String::new()).await.unwrap().into_inner();
+    assert_eq!(resp.outcome(), Outcome::Unauthenticated);
+    assert!(resp.login_url.starts_with(&format!("{}/oidc/login?rt=", gw.base)), "{}", resp.login_url);
+
+    // the sealed return_to survives the whole login round trip
+    let http = reqwest::Client::builder().redirect(RedirectPolicy::none()).build().unwrap();
+    let resp = http.get(&resp.login_url).send().await.unwrap();
+    let resp = http.get(location(&resp)).send().await.unwrap();
+    let resp = http.get(location(&resp)).send().await.unwrap();
+    assert_eq!(location(&resp), site);
+    let set_cookie = resp.headers()[header::SET_COOKIE].to_str().unwrap();
+    let token = set_cookie.split(';').next().unwrap().strip_prefix("appg_sess=").unwrap().to_string();
+    let resp = decide(token).await.unwrap().into_inner();
+    assert_eq!(resp.outcome(), Outcome::Allow);
+    assert!(resp.login_url.is_empty());
+}
+
+#[tokio::test]
+async fn authenticated_users_without_groups_are_forbidden() {
+    let idp = StandInIdp::start(&[]);
+    let gw = start_gateway(&idp, 3600).await;
+    let token = common::login(&gw).await;
+    let policy = appgate_policy::Policy::load(concat!(env!("CARGO_MANIFEST_DIR"), "/../../config/policy/foundry.toml")).unwrap();
+    let pdp = PdpSvc { policy: Arc::new(ArcSwap::from_pointee(policy.into())), sealer: gw.sealer.clone(), store: gw.store.clone(), login: Some(gw.links.clone()) };
+    let req = DecisionRequest { session_token: token, protocol: "http".into(), resource: "http://foundry/".into(), ..Default::default() };
+    let resp = pdp.decide(tonic::Request::new(req)).await.unwrap().into_inner();
+    assert_eq!(resp.outcome(), Outcome::Forbidden);
+    assert!(resp.login_url.is_empty());
+}
+
+#[tokio::test]
+async fn forged_sealed_return_to_falls_back_to_root() {
+    let idp = StandInIdp::start(&[]);
+    let gw = start_gateway(&idp, 3600).await;
+    let other = appgate_auth::links::LoginLinks::new(&format!("{}/oidc/callback", gw.base), Arc::new(appgate_auth::cookie::CookieSealer::ephemeral())).unwrap();
+    let http = reqwest::Client::builder().redirect(RedirectPolicy::none()).build().unwrap();
+    let resp = http.get(other.login_url("https://evil.example/")).send().await.unwrap();
+    let resp = http.get(location(&resp)).send().await.unwrap();
+    let resp = http.get(location(&resp)).send().await.unwrap();
+    assert_eq!(location(&resp), "/");
+}
@@ -0,0 +1,104 @@
+//! Tests for policy hot reload: file watching, failed reloads and reload metrics.
+
+use appgate_auth::reload::PolicyReloader;
+use appgate_policy::{engine::Engine, Policy};
+use arc_swap::ArcSwap;
+use prometheus::Registry;
+use std::{path::Path, sync::Arc, time::Duration};
+
+fn rule(name: &str) -> String {
+    format!("[[rules]]\nname = \"{name}\"\nprotocol = \"http\"\nresource = \"http://foundry/\"\n")
+}
+
+fn metric(registry: &Registry, name: &str, result: Option<&str>) -> f64 {
+    registry
+        .gather()
+        .iter()
+        .filter(|f| f.get_name() == name)
+        .flat_map(|f| f.get_metric().to_vec())
+        .find(|m| result.is_none_or(|r| m.get_label().iter().any(|l| l.get_value() == r)))
+        .map_or(0.0, |m| if m.has_counter() { m.get_counter().get_value() } else { m.get_gauge().get_value() })
+}
+
+async fn eventually(what: &str, mut check: impl FnMut() -> bool) {
+    for _ in 0..50 {
+        if check() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("timed out waiting for {what}");
+}
+
+fn setup(dir: &Path) -> (Arc<ArcSwap<Engine>>, Arc<PolicyReloader>, Registry) {
+    let _ = std::fs::remove_dir_all(dir);
+    std::fs::create_dir_all(dir).unwrap();
+    let path = dir.join("policy.toml");
+    std::fs::write(&path, rule("first")).unwrap();
+    let policy = Arc::new(ArcSwap::from_pointee(Policy::load(path.to_str().unwrap()).unwrap().into()));
+    let registry = Registry::new();
+    let reloader = Arc::new(PolicyReloader::new(&path, policy.clone(), &registry).unwrap());
+    (policy, reloader, registry)
+}
+
+#[tokio::test]
+async fn invalid_policy_keeps_the_previous_one() {
+    let dir = std::env::temp_dir().join(format!("appgate-reload-{}-invalid", std::process::id()));
+    let (policy, reloader, registry) = setup(&dir);
+
+    std::fs::write(dir.join("policy.toml"), rule("second")).unwrap();
+    reloader.reload().unwrap();
+    assert_eq!(policy.load().rule_names()[0], "second");
+    assert_eq!(metric(&registry, "appgate_policy_reloads_total", Some("success")), 1.0);
+    assert!(metric(&registry, "appgate_policy_last_reload_timestamp_seconds", None) > 0.0);
+
+    // parse errors and validation errors both leave the live policy alone
+    for broken in ["[[rules]]\nname = ", "[[rules]]\nname = \"re\"\nprotocol = \"http\"\nresource = \"(\"\nmatch = \"regex\"\n"] {
+        std::fs::write(dir.join("policy.toml"), broken).unwrap();
+        assert!(reloader.reload().is_err());
+        assert_eq!(policy.load().rule_names()[0], "second");
+    }
+    assert_eq!(metric(&registry, "appgate_policy_reloads_total", Some("failure")), 2.0);
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn file_changes_are_picked_up() {
+    let dir = std::env::temp_dir().join(format!("appgate-reload-{}-watch", std::process::id()));
+    let (policy, reloader, registry) = setup(&dir);
+    reloader.watch().unwrap();
+
+    std::fs::write(dir.join("policy.toml"), rule("edited")).unwrap();
+    eventually("in-place edit", || policy.load().rule_names()[0] == "edited").await;
+
+    // editors that save by renaming a new file over the old one
+    std::fs::write(dir.join("policy.toml.tmp"), rule("renamed")).unwrap();
+    std::fs::rename(dir.join("policy.toml.tmp"), dir.join("policy.toml")).unwrap();
+    eventually("rename over", || policy.load().rule_names()[0] == "renamed").await;
+
+    // a decision in progress keeps the policy it loaded
+    let held = policy.load_full();
+    std::fs::write(dir.join("policy.toml"), rule("later")).unwrap();
+    eventually("later edit", || policy.load().rule_names()[0] == "later").await;
+    assert_eq!(held.rule_names()[0], "renamed");
+    assert!(metric(&registry, "appgate_policy_reloads_total", Some("success")) >= 3.0);
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn policy_directories_are_watched_recursively() {
+    let dir = std::env::temp_dir().join(format!("appgate-reload-{}-dir", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("teams/a")).unwrap();
+    std::fs::write(dir.join("teams/a/rules.toml"), rule("team-a")).unwrap();
+    let policy = Arc::new(ArcSwap::from_pointee(Policy::load(dir.to_str().unwrap()).unwrap().into()));
+    let reloader = Arc::new(PolicyReloader::new(&dir, policy.clone(), &Registry::new()).unwrap());
+    reloader.watch().unwrap();
+
+    std::fs::write(dir.join("teams/a/rules.toml"), rule("team-a-edited")).unwrap();
+    eventually("edit in a subdirectory", || policy.load().rule_names()[0] == "team-a-edited").await;
+    std::fs::create_dir_all(dir.join("teams/b")).unwrap();
+    std::fs::write(dir.join("teams/b/rules.toml"), rule("team-b").replace("foundry", "b")).unwrap();
+    eventually("new team file", || policy.load().rule_names().len() == 2).await;
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,76 @@
+//! Tests for refresh-token driven session renewal against the stand-in IdP.
+
+mod common;
+
+use appgate_auth::{claims::ClaimMapper, pdp::PdpSvc, renew::Renewer, store::SessionStatus};
+use appgate_ipc::pdp::{pdp_server::Pdp, DecisionRequest};
+use arc_swap::ArcSwap;
+use common::{login, start_gateway, Gateway, StandInIdp};
+use std::sync::Arc;
+
+fn renewer(gw: &Gateway) -> Renewer {
+    let mut r = Renewer::new(gw.oidc.clone(), gw.store.clone(), gw.sealer.clone(), Arc::new(ClaimMapper::default()), 60);
+    // every live session is due
+    r.margin = r.ttl;
+    r
+}
+
+async fn decide(gw: &Gateway, cookie: &str) -> String {
+    let policy = appgate_policy::Policy::load(concat!(env!("CARGO_MANIFEST_DIR"), "/../../config/policy/foundry.toml")).unwrap();
+    let pdp = PdpSvc { policy: Arc::new(ArcSwap::from_pointee(policy.into())), sealer: gw.sealer.clone(), store: gw.store.clone(), login: None };
+    let req = DecisionRequest { session_token: cookie.into(), protocol: "http".into(), resource: "http://foundry/".into(), ..Default::default() };
+    pdp.decide(tonic::Request::new(req)).await.unwrap().into_inner().reason
+}
+
+#[tokio::test]
+async fn refresh_renews_window_and_picks_up_group_changes() {
+    let idp = StandInIdp::start(&["foundry-players"]);
+    let gw = start_gateway(&idp, 60).await;
+    let cookie = login(&gw).await;
+    let sid = gw.sealer.unseal(&cookie).unwrap().sid;
+
+    let before = gw.store.get(&sid).unwrap().unwrap();
+    assert_eq!(before.groups, ["foundry-players"]);
+    let sealed = before.refresh_token.clone().expect("refresh token stored");
+    assert!(!sealed.starts_with("rt-"), "refresh token must be encrypted at rest");
+    assert!(before.not_after > before.expires_at, "refreshable sessions outlive their first window");
+
+    idp.set_groups(&["foundry-gms"]);
+    tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+    assert_eq!(renewer(&gw).renew_due().await.unwrap(), 1);
+
+    let after = gw.store.get(&sid).unwrap().unwrap();
+    assert_eq!(after.groups, ["foundry-gms"]);
+    assert!(after.expires_at > before.expires_at);
+    assert_ne!(after.refresh_token, before.refresh_token, "rotated refresh token is kept");
+    assert!(!decide(&gw, &cookie).await.starts_with("unauthenticated"));
+
+    // the rotated token keeps working
+    assert_eq!(renewer(&gw).renew_due().await.unwrap(), 1);
+}
+
+#[tokio::test]
+async fn refresh_failure_ends_session() {
+    let idp = StandInIdp::start(&["foundry-players"]);
+    let gw = start_gateway(&idp, 60).await;
+    let cookie = login(&gw).await;
+    let sid = gw.sealer.unseal(&cookie).unwrap().sid;
+
+    idp.revoke_refresh_tokens();
+    assert_eq!(renewer(&gw).renew_due().await.unwrap(), 0);
+    assert_eq!(gw.store.status(&sid).unwrap(), SessionStatus::Revoked);
+    assert_eq!(decide(&gw, &cookie).await, "unauthenticated: session revoked");
+}
+
+#[tokio::test]
+async fn sessions_outside_the_margin_are_left_alone() {
+    let idp = StandInIdp::start(&[]);
+    let gw = start_gateway(&idp, 3600).await;
+    let cookie = login(&gw).await;
+    let sid = gw.sealer.unseal(&cookie).unwrap().sid;
+    let before = gw.store.get(&sid).unwrap().unwrap();
+
+    let r = Renewer::new(gw.oidc.clone(), gw.store.clone(), gw.sealer.clone(), Arc::new(ClaimMapper::default()), 3600);
+    assert_eq!(r.renew_due().await.unwrap(), 0);
+    assert_eq!(gw.store.get(&sid).unwrap().unwrap(), before);
+}
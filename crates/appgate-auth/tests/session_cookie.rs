@@ -0,0 +1,120 @@
+//! Tests for AEAD-sealed session cookies and the PDP's handling of them.
+
+use appgate_auth::{
+    cookie::{CookieSealer, UnsealError},
+    pdp::PdpSvc,
+    session::Session,
+    store::SessionStore,
+};
+use appgate_ipc::pdp::{pdp_server::Pdp, DecisionRequest};
+use arc_swap::ArcSwap;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{Duration, Utc};
+use std::{collections::HashMap, sync::Arc};
+
+fn session(ttl: Duration) -> Session {
+    Session {
+        sid: "sid-1".into(),
+        sub: "alice-sub".into(),
+        groups: vec!["foundry-players".into()],
+        claims: [("sub".to_string(), "alice-sub".to_string())].into_iter().collect(),
+        expires_at: Utc::now() + ttl,
+    }
+}
+
+fn sealer(keys: &[(&str, u8)], current: &str) -> CookieSealer {
+    let keys: HashMap<String, [u8; 32]> = keys.iter().map(|(id, b)| (id.to_string(), [*b; 32])).collect();
+    CookieSealer::new(keys, current).unwrap()
+}
+
+#[test]
+fn round_trips_and_hides_contents() {
+    let s = sealer(&[("k1", 1)], "k1");
+    let sess = session(Duration::minutes(5));
+    let cookie = s.seal(&sess);
+    assert!(cookie.starts_with("v1.k1."));
+    assert!(!cookie.contains("alice"));
+    let opened = s.unseal(&cookie).unwrap();
+    assert_eq!(opened.sub, sess.sub);
+    assert_eq!(opened.groups, sess.groups);
+    assert_eq!(opened.expires_at.timestamp(), sess.expires_at.timestamp());
+}
+
+#[test]
+fn rejects_tampering() {
+    let s = sealer(&[("k1", 1)], "k1");
+    let cookie = s.seal(&session(Duration::minutes(5)));
+    let (prefix, data) = cookie.rsplit_once('.').unwrap();
+    let mut blob = URL_SAFE_NO_PAD.decode(data).unwrap();
+    let last = blob.len() - 1;
+    blob[last] ^= 1;
+    let tampered = format!("{prefix}.{}", URL_SAFE_NO_PAD.encode(blob));
+    assert_eq!(s.unseal(&tampered).unwrap_err(), UnsealError::Tampered);
+    assert_eq!(s.unseal("v1.k1.!!").unwrap_err(), UnsealError::Malformed);
+    assert_eq!(s.unseal("raw-cookie-value").unwrap_err(), UnsealError::Malformed);
+}
+
+#[test]
+fn rejects_expired_sessions() {
+    let s = sealer(&[("k1", 1)], "k1");
+    let cookie = s.seal(&session(Duration::seconds(-1)));
+    assert_eq!(s.unseal(&cookie).unwrap_err(), UnsealError::Expired);
+}
+
+#[test]
+fn key_rotation_and_wrong_keys() {
+    let old = sealer(&[("k1", 1)], "k1");
+    let cookie = old.seal(&session(Duration::minutes(5)));
+
+    // rotated: k2 seals, k1 still opens existing cookies
+    let rotated = sealer(&[("k1", 1), ("k2", 2)], "k2");
+    assert!(rotated.unseal(&cookie).is_ok());
+    assert!(rotated.seal(&session(Duration::minutes(5))).starts_with("v1.k2."));
+
+    // k1 retired entirely
+    let retired = sealer(&[("k2", 2)], "k2");
+    assert_eq!(retired.unseal(&cookie).unwrap_err(), UnsealError::UnknownKey("k1".into()));
+
+    // same id, different key material
+    let imposter = sealer(&[("k1", 9)], "k1");
+    assert_eq!(imposter.unseal(&cookie).unwrap_err(), UnsealError::Tampered);
+
+    // relabelling a cookie with another known key id breaks the associated data
+    let relabelled = cookie.replacen("v1.k1.", "v1.k2.", 1);
+    let both = sealer(&[("k1", 1), ("k2", 1)], "k1");
+    assert_eq!(both.unseal(&relabelled).unwrap_err(), UnsealError::Tampered);
+}
+
+#[tokio::test]
+async fn pdp_denies_with_distinct_reasons() {
+    let s = Arc::new(sealer(&[("k1", 1)], "k1"));
+    let store = Arc::new(SessionStore::memory());
+    let pdp = PdpSvc { policy: Arc::new(ArcSwap::from_pointee(appgate_policy::Policy { rules: vec![] }.into())), sealer: s.clone(), store: store.clone(), login: None };
+    let live = session(Duration::minutes(5));
+    store.register(&live).unwrap();
+    let good = s.seal(&live);
+    let expired = s.seal(&session(Duration::seconds(-1)));
+    let foreign = sealer(&[("k0", 1)], "k0").seal(&session(Duration::minutes(5)));
+
+    let mut reasons = Vec::new();
+    for token in ["", "garbage", expired.as_str(), foreign.as_str()] {
+        let resp = pdp
+            .decide(tonic::Request::new(DecisionRequest { session_token: token.into(), ..Default::default() }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(!resp.allow);
+        assert!(resp.reason.starts_with("unauthenticated: "), "{}", resp.reason);
+        reasons.push(resp.reason);
+    }
+    reasons.dedup();
+    assert_eq!(reasons.len(), 4, "{reasons:?}");
+
+    // a valid session reaches policy evaluation
+    let resp = pdp
+        .decide(tonic::Request::new(DecisionRequest { session_token: good, ..Default::default() }))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(resp.reason, "default-deny");
+}
@@ -0,0 +1,242 @@
+//! Tests for the server-side session store: revocation, logout and expiry.
+
+use appgate_auth::{
+    admin::AdminSvc,
+    cookie::CookieSealer,
+    pdp::PdpSvc,
+    session::Session,
+    store::{SessionStatus, SessionStore, SledBackend},
+};
+use appgate_ipc::pdp::{pdp_server::Pdp, session_admin_server::SessionAdmin, DecisionRequest, RevokeRequest};
+use arc_swap::ArcSwap;
+use chrono::{Duration, Utc};
+use reqwest::{header, redirect::Policy as RedirectPolicy, StatusCode};
+use std::sync::Arc;
+
+mod common;
+
+fn session(sid: &str, sub: &str, ttl: Duration) -> Session {
+    Session {
+        sid: sid.into(),
+        sub: sub.into(),
+        groups: vec![],
+        claims: Default::default(),
+        expires_at: Utc::now() + ttl,
+    }
+}
+
+fn exercise(store: &SessionStore) {
+    store.register(&session("a1", "alice", Duration::minutes(5))).unwrap();
+    store.register(&session("a2", "alice", Duration::minutes(5))).unwrap();
+    store.register(&session("b1", "bob", Duration::minutes(5))).unwrap();
+    store.register(&session("old", "bob", Duration::seconds(-5))).unwrap();
+
+    assert_eq!(store.status("a1").unwrap(), SessionStatus::Active);
+    assert_eq!(store.status("old").unwrap(), SessionStatus::Unknown);
+    assert_eq!(store.status("nope").unwrap(), SessionStatus::Unknown);
+
+    assert!(store.revoke("b1").unwrap());
+    assert!(!store.revoke("b1").unwrap());
+    assert_eq!(store.status("b1").unwrap(), SessionStatus::Revoked);
+
+    assert_eq!(store.revoke_sub("alice").unwrap(), 2);
+    assert_eq!(store.status("a2").unwrap(), SessionStatus::Revoked);
+
+    assert_eq!(store.gc().unwrap(), 1);
+}
+
+#[test]
+fn memory_store_revokes_and_collects() {
+    exercise(&SessionStore::memory());
+}
+
+#[test]
+fn sled_store_revokes_collects_and_persists() {
+    let dir = std::env::temp_dir().join(format!("appgate-sessions-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    {
+        exercise(&SessionStore::new(SledBackend::open(&dir).unwrap()));
+    }
+    // revocations survive a restart; sled's flusher thread can hold the lock a moment after the drop
+    let backend = (0..50)
+        .find_map(|_| {
+            let opened = SledBackend::open(&dir).ok();
+            if opened.is_none() {
+                std::thread::sleep(std::time::Duration::from_millis(20));
+            }
+            opened
+        })
+        .expect("reopen the sled store");
+    let reopened = SessionStore::new(backend);
+    assert_eq!(reopened.status("a1").unwrap(), SessionStatus::Revoked);
+    assert_eq!(reopened.status("old").unwrap(), SessionStatus::Unknown);
+    drop(reopened);
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn pdp_denies_revoked_and_unregistered_sessions() {
+    let sealer = Arc::new(CookieSealer::ephemeral());
+    let store = Arc::new(SessionStore::memory());
+    let pdp = Arc::new(PdpSvc { policy: Arc::new(ArcSwap::from_pointee(appgate_policy::Policy { rules: vec![] }.into())), sealer: sealer.clone(), store: store.clone(), login: None });
+    let decide = |token: String| {
+        let req = tonic::Request::new(DecisionRequest { session_token: token, ..Default::default() });
+        async { pdp.decide(req).await.unwrap().into_inner().reason }
+    };
+
+    let s = session("s1", "alice", Duration::minutes(5));
+    let cookie = sealer.seal(&s);
+    assert_eq!(decide(cookie.clone()).await, "unauthenticated: unknown session");
+    store.register(&s).unwrap();
+    assert_eq!(decide(cookie.clone()).await, "default-deny");
+
+    let admin = AdminSvc { store: store.clone(), pdp: pdp.clone() };
+    let resp = admin
+        .revoke(tonic::Request::new(RevokeRequest { sub: "alice".into(), ..Default::default() }))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(resp.revoked, 1);
+    assert_eq!(decide(cookie).await, "unauthenticated: session revoked");
+
+    let both = RevokeRequest { sid: "s1".into(), sub: "alice".into() };
+    assert!(admin.revoke(tonic::Request::new(both)).await.is_err());
+}
+
+#[tokio::test]
+async fn logout_revokes_session_and_clears_cookie() {
+    let idp = common::StandInIdp::start(&[]);
+    let common::Gateway { base, sealer, store, .. } = common::start_gateway(&idp, 3600).await;
+
+    let s = session("s1", "alice", Duration::minutes(5));
+    store.register(&s).unwrap();
+    let http = reqwest::Client::builder().redirect(RedirectPolicy::none()).build().unwrap();
+    let resp = http
+        .post(format!("{base}/logout?return_to=/bye"))
+        .header(header::COOKIE, format!("other=1; appg_sess={}", sealer.seal(&s)))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+    assert_eq!(resp.headers()[header::LOCATION], "/bye");
+    assert!(resp.headers()[header::SET_COOKIE].to_str().unwrap().starts_with("appg_sess=; "));
+    assert_eq!(store.status("s1").unwrap(), SessionStatus::Revoked);
+
+    // logging out without a session still clears the cookie
+    let resp = http.post(format!("{base}/logout")).send().await.unwrap();
+    assert_eq!(resp.headers()[header::LOCATION], "/");
+
+    // a link or image elsewhere cannot log anyone out
+    let s = session("s2", "alice", Duration::minutes(5));
+    store.register(&s).unwrap();
+    let resp = http
+        .get(format!("{base}/logout"))
+        .header(header::COOKIE, format!("appg_sess={}", sealer.seal(&s)))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(store.status("s2").unwrap(), SessionStatus::Active);
+}
+
+#[tokio::test]
+async fn pdp_expiry_ends_with_the_schedule_window() {
+    let sealer = Arc::new(CookieSealer::ephemeral());
+    let store = Arc::new(SessionStore::memory());
+    let window_end = (Utc::now() + Duration::minutes(10)).format("%Y-%m-%dT%H:%M:%SZ").to_string();
+    let policy: appgate_policy::Policy = toml::from_str(&format!(
+        r#"
+        [[rules]]
+        name = "game-night"
+        protocol = "http"
+        resource = "http://foundry/"
+        schedule = {{ not_after = "{window_end}" }}
+
+        [[rules]]
+        name = "wiki"
+        protocol = "http"
+        resource = "http://wiki/"
+        "#
+    ))
+    .unwrap();
+    let pdp = PdpSvc { policy: Arc::new(ArcSwap::from_pointee(policy.into())), sealer: sealer.clone(), store: store.clone(), login: None };
+    let s = session("s1", "alice", Duration::hours(1));
+    store.register(&s).unwrap();
+    let expiry = |resource: &str| {
+        let req = DecisionRequest { session_token: sealer.seal(&s), protocol: "http".into(), resource: resource.into(), ..Default::default() };
+        async { pdp.decide(tonic::Request::new(req)).await.unwrap().into_inner() }
+    };
+
+    let scheduled = expiry("http://foundry/").await;
+    assert!(scheduled.allow);
+    let parse = |t: &str| chrono::DateTime::parse_from_rfc3339(t).unwrap();
+    assert_eq!(parse(&scheduled.expiry), parse(&window_end));
+    // unscheduled rules keep the session's own expiry
+    assert_eq!(expiry("http://wiki/").await.expiry, s.expires_at.to_rfc3339());
+}
+
+#[tokio::test]
+async fn pdp_renders_identity_into_injected_headers() {
+    let sealer = Arc::new(CookieSealer::ephemeral());
+    let store = Arc::new(SessionStore::memory());
+    let policy: appgate_policy::Policy = toml::from_str(
+        r#"
+        [[rules]]
+        name = "foundry"
+        protocol = "http"
+        resource = "http://foundry/"
+        [rules.inject]
+        "X-User-Sub" = "{{claims.sub}}"
+        "X-User-Email" = "{{claims.email}}"
+        "X-User-Groups" = "{{groups|join(',')}}"
+        "X-Role" = "player"
+        "#,
+    )
+    .unwrap();
+    policy.validate().unwrap();
+    let pdp = PdpSvc { policy: Arc::new(ArcSwap::from_pointee(policy.into())), sealer: sealer.clone(), store: store.clone(), login: None };
+    let mut s = session("s1", "alice-sub", Duration::hours(1));
+    s.groups = vec!["foundry-players".into(), "foundry-gms".into()];
+    s.claims = [("sub", "alice-sub"), ("email", "alice@example.com")].into_iter().map(|(k, v)| (k.into(), v.into())).collect();
+    store.register(&s).unwrap();
+
+    let req = DecisionRequest { session_token: sealer.seal(&s), protocol: "http".into(), resource: "http://foundry/".into(), ..Default::default() };
+    let inject = pdp.decide(tonic::Request::new(req)).await.unwrap().into_inner().inject;
+    assert_eq!(inject["X-User-Sub"], "alice-sub");
+    assert_eq!(inject["X-User-Email"], "alice@example.com");
+    assert_eq!(inject["X-User-Groups"], "foundry-players,foundry-gms");
+    assert_eq!(inject["X-Role"], "player");
+}
+
+#[tokio::test]
+async fn pdp_decides_with_a_cedar_policy() {
+    let sealer = Arc::new(CookieSealer::ephemeral());
+    let store = Arc::new(SessionStore::memory());
+    let policy: appgate_policy::cedar::CedarPolicy = r#"
+        @id("alice-only")
+        @inject_X_User("{{claims.email}}")
+        permit (principal == AppGate::User::"alice-sub", action == AppGate::Action::"GET", resource)
+        when { resource.host == "foundry" };
+    "#
+    .parse()
+    .unwrap();
+    let pdp = PdpSvc { policy: Arc::new(ArcSwap::from_pointee(policy.into())), sealer: sealer.clone(), store: store.clone(), login: None };
+    let mut alice = session("s1", "alice-sub", Duration::hours(1));
+    alice.claims = [("sub", "alice-sub"), ("email", "alice@example.com")].into_iter().map(|(k, v)| (k.into(), v.into())).collect();
+    let mut bob = session("s2", "bob-sub", Duration::hours(1));
+    bob.claims = [("sub".to_string(), "bob-sub".to_string())].into();
+    store.register(&alice).unwrap();
+    store.register(&bob).unwrap();
+
+    let get = |s: &Session, method: &str| {
+        let kv = [("method".to_string(), method.to_string())].into();
+        let attributes = Some(appgate_ipc::pdp::Attributes { kv });
+        DecisionRequest { session_token: sealer.seal(s), protocol: "http".into(), resource: "http://foundry/".into(), attributes, ..Default::default() }
+    };
+    let d = pdp.decide(tonic::Request::new(get(&alice, "GET"))).await.unwrap().into_inner();
+    assert!(d.allow, "{}", d.reason);
+    assert_eq!(d.reason, "policy: alice-only");
+    assert_eq!(d.inject["X-User"], "alice@example.com");
+    assert!(!pdp.decide(tonic::Request::new(get(&alice, "POST"))).await.unwrap().into_inner().allow);
+    assert_eq!(pdp.decide(tonic::Request::new(get(&bob, "GET"))).await.unwrap().into_inner().reason, "default-deny");
+}
@@ -1,4 +1,5 @@
 use serde::Deserialize;
+use std::collections::HashMap;
 use thiserror::Error;
 
 /// Errors that can occur during configuration validation
@@ -35,6 +36,348 @@ pub struct Oidc {
     pub cookie_name: String,
     pub cookie_domain: String,
     pub session_ttl_seconds: u64,
+    /// Upper bound on a session renewed with refresh tokens; each renewal extends it by `session_ttl_seconds`
+    #[serde(default = "default_session_max_lifetime")]
+    pub session_max_lifetime_seconds: u64,
+    /// Allowed clock skew when checking token exp/nbf/iat
+    #[serde(default = "default_clock_skew")]
+    pub clock_skew_seconds: u64,
+}
+
+fn default_session_max_lifetime() -> u64 {
+    12 * 3600
+}
+
+fn default_clock_skew() -> u64 {
+    60
+}
+
+/// Resolve a secret value, reading it from the environment when written as `env:NAME`
+pub fn resolve_secret(key: &'static str, value: &str) -> Result<String, ConfigError> {
+    match value.strip_prefix("env:") {
+        Some(var) => std::env::var(var).map_err(|_| ConfigError::Invalid {
+            key,
+            reason: format!("environment variable {var} is not set"),
+        }),
+        None => Ok(value.to_string()),
+    }
+}
+
+impl Oidc {
+    /// Resolve the client secret (see [`resolve_secret`])
+    pub fn resolve_client_secret(&self) -> Result<String, ConfigError> {
+        resolve_secret("auth.oidc.client_secret", &self.client_secret)
+    }
+}
+
+/// Where appgate-auth keeps its server-side session table
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionStoreKind {
+    /// Lost on restart; every existing cookie is then rejected
+    #[default]
+    Memory,
+    /// sled database under `global.run_dir`
+    Sled,
+}
+
+/// Session cookie sealing keys and server-side session storage
+#[derive(Debug, Default, Deserialize)]
+pub struct SessionConfig {
+    #[serde(default)]
+    pub store: SessionStoreKind,
+    /// Key id used to seal new cookies
+    #[serde(default)]
+    pub current_key: String,
+    /// Key id → base64-encoded 32-byte key (or `env:NAME`); older ids keep opening existing cookies
+    #[serde(default)]
+    pub keys: HashMap<String, String>,
+}
+
+impl SessionConfig {
+    /// Resolve every key value (see [`resolve_secret`])
+    pub fn resolve(&self) -> Result<HashMap<String, String>, ConfigError> {
+        self.keys
+            .iter()
+            .map(|(id, v)| Ok((id.clone(), resolve_secret("auth.session.keys", v)?)))
+            .collect()
+    }
+}
+
+/// How groups and identity claims are read from verified ID tokens.
+///
+/// Paths are JSON pointers (RFC 6901) into the token claims; `{client_id}` expands to
+/// `auth.oidc.client_id`, e.g. `/resource_access/{client_id}/roles` for Keycloak client roles.
+#[derive(Debug, Deserialize)]
+pub struct ClaimMapping {
+    /// Pointers whose string or string-array values become groups
+    #[serde(default = "default_group_paths")]
+    pub groups: Vec<String>,
+    /// Prefixes removed from group names (first match wins), e.g. `/` for Keycloak group paths
+    #[serde(default)]
+    pub strip_prefix: Vec<String>,
+    /// Group renames applied after prefix stripping
+    #[serde(default)]
+    pub rename: HashMap<String, String>,
+    /// Claim name → pointer for identity claims passed to modules in `DecisionResponse.claims`
+    #[serde(default = "default_projected_claims")]
+    pub claims: HashMap<String, String>,
+}
+
+fn default_group_paths() -> Vec<String> {
+    vec!["/groups".into()]
+}
+
+fn default_projected_claims() -> HashMap<String, String> {
+    ["sub", "email", "name", "preferred_username"]
+        .into_iter()
+        .map(|c| (c.to_string(), format!("/{c}")))
+        .collect()
+}
+
+impl Default for ClaimMapping {
+    fn default() -> Self {
+        Self {
+            groups: default_group_paths(),
+            strip_prefix: Vec::new(),
+            rename: HashMap::new(),
+            claims: default_projected_claims(),
+        }
+    }
+}
+
+/// Authentication configuration values (`[auth.*]` tables)
+#[derive(Debug, Deserialize)]
+pub struct Auth {
+    pub oidc: Oidc,
+    #[serde(default)]
+    pub session: SessionConfig,
+    #[serde(default)]
+    pub claims: ClaimMapping,
+}
+
+/// Access policy configuration values
+#[derive(Debug, Default, Deserialize)]
+pub struct PolicyConfig {
+    /// Policy file (or TOML directory) served by appgate-auth (its `--policy` overrides this); loaded
+    /// and, for TOML, linted by [`Config::validate`]
+    pub file: Option<String>,
+    /// Engine reading `file`: `toml` (default) or `cedar`
+    #[serde(default)]
+    pub engine: appgate_policy::engine::EngineKind,
+}
+
+/// How a route spreads requests over its upstreams
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Balance {
+    #[default]
+    RoundRobin,
+    /// The upstream with the fewest requests in flight
+    LeastConnections,
+    /// Consistent hashing of the session cookie (the client address without one), so a session
+    /// stays on one upstream while it is available
+    SessionHash,
+}
+
+/// Active health checks: `GET path` on every upstream each interval; 2xx and 3xx are healthy
+#[derive(Debug, Clone, Deserialize)]
+pub struct HealthCheck {
+    #[serde(default = "default_health_path")]
+    pub path: String,
+    #[serde(default = "default_health_interval")]
+    pub interval_seconds: u64,
+    #[serde(default = "default_health_timeout")]
+    pub timeout_seconds: u64,
+    /// Consecutive failures that mark an upstream unhealthy
+    #[serde(default = "default_unhealthy_threshold")]
+    pub unhealthy_threshold: u32,
+    /// Consecutive successes that mark it healthy again
+    #[serde(default = "default_healthy_threshold")]
+    pub healthy_threshold: u32,
+}
+
+fn default_health_path() -> String {
+    "/".into()
+}
+
+fn default_health_interval() -> u64 {
+    10
+}
+
+fn default_health_timeout() -> u64 {
+    2
+}
+
+fn default_unhealthy_threshold() -> u32 {
+    3
+}
+
+fn default_healthy_threshold() -> u32 {
+    2
+}
+
+/// Passive outlier detection: an upstream failing `consecutive_failures` proxied requests in a row
+/// (connection errors, 502, 503, 504) is ejected for `ejection_seconds`
+#[derive(Debug, Clone, Deserialize)]
+pub struct OutlierDetection {
+    #[serde(default = "default_consecutive_failures")]
+    pub consecutive_failures: u32,
+    #[serde(default = "default_ejection_seconds")]
+    pub ejection_seconds: u64,
+}
+
+fn default_consecutive_failures() -> u32 {
+    5
+}
+
+fn default_ejection_seconds() -> u64 {
+    30
+}
+
+impl Default for OutlierDetection {
+    fn default() -> Self {
+        Self { consecutive_failures: default_consecutive_failures(), ejection_seconds: default_ejection_seconds() }
+    }
+}
+
+/// Per-upstream circuit breaker: opens when, over the last `window_seconds`, at least
+/// `min_requests` requests were sent and at least `error_ratio` of them failed (5xx or connection
+/// error). An open upstream gets no requests; after `open_seconds` it lets `probe_requests` through
+/// (half-open), closing once they all succeed and reopening on any failure
+#[derive(Debug, Clone, Deserialize)]
+pub struct CircuitBreaker {
+    #[serde(default = "default_error_ratio")]
+    pub error_ratio: f64,
+    #[serde(default = "default_breaker_window")]
+    pub window_seconds: u64,
+    #[serde(default = "default_min_requests")]
+    pub min_requests: u32,
+    #[serde(default = "default_open_seconds")]
+    pub open_seconds: u64,
+    #[serde(default = "default_probe_requests")]
+    pub probe_requests: u32,
+}
+
+fn default_error_ratio() -> f64 {
+    0.5
+}
+
+fn default_breaker_window() -> u64 {
+    10
+}
+
+fn default_min_requests() -> u32 {
+    10
+}
+
+fn default_open_seconds() -> u64 {
+    30
+}
+
+fn default_probe_requests() -> u32 {
+    3
+}
+
+/// One `[[modules.http.routes]]` entry: requests for `hosts` under `path_prefix` go to `upstream`
+/// (or are balanced over `upstreams`)
+#[derive(Debug, Clone, Deserialize)]
+pub struct HttpRoute {
+    /// Logical application name; the PDP resource is `http://<name><path>`, so policies target the
+    /// route rather than the upstream address
+    pub name: String,
+    /// `Host` names (without port) this route serves; empty serves every host
+    #[serde(default)]
+    pub hosts: Vec<String>,
+    /// Path prefix this route serves, matched on whole segments
+    #[serde(default = "default_path_prefix")]
+    pub path_prefix: String,
+    /// Upstream base URL, e.g. `http://127.0.0.1:30000`
+    #[serde(default)]
+    pub upstream: Option<String>,
+    /// A pool of upstream base URLs, instead of `upstream`
+    #[serde(default)]
+    pub upstreams: Vec<String>,
+    #[serde(default)]
+    pub balance: Balance,
+    #[serde(default)]
+    pub health_check: Option<HealthCheck>,
+    #[serde(default)]
+    pub outlier_detection: OutlierDetection,
+    #[serde(default)]
+    pub circuit_breaker: Option<CircuitBreaker>,
+    /// Replaces `path_prefix` in the forwarded path; `"/"` strips it
+    #[serde(default)]
+    pub rewrite_prefix: Option<String>,
+}
+
+impl HttpRoute {
+    /// `upstream` and `upstreams` together
+    pub fn endpoints(&self) -> Vec<&str> {
+        self.upstream.iter().chain(&self.upstreams).map(String::as_str).collect()
+    }
+}
+
+fn default_path_prefix() -> String {
+    "/".into()
+}
+
+/// HTTP module configuration (`[modules.http]`)
+#[derive(Debug, Default, Deserialize)]
+pub struct HttpModule {
+    /// Routing table; the most specific match wins (a listed host over any host, then the longest
+    /// prefix, then the first entry)
+    #[serde(default)]
+    pub routes: Vec<HttpRoute>,
+    /// appgate-mod-http's metrics listener (`--metrics-bind`), whose `/readyz` appgate-ctrl's
+    /// readiness reflects
+    #[serde(default)]
+    pub metrics_bind: Option<String>,
+}
+
+impl HttpModule {
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |reason: String| ConfigError::Invalid { key: "modules.http.routes", reason };
+        for r in &self.routes {
+            if r.name.is_empty() || r.name.contains(['/', ':', ' ']) {
+                return Err(invalid(format!("route name {:?} must be a non-empty host-like name", r.name)));
+            }
+            if !r.path_prefix.starts_with('/') || r.rewrite_prefix.as_ref().is_some_and(|p| !p.starts_with('/')) {
+                return Err(invalid(format!("route {}: path prefixes must start with '/'", r.name)));
+            }
+            let endpoints = r.endpoints();
+            if endpoints.is_empty() {
+                return Err(invalid(format!("route {}: needs an upstream or upstreams", r.name)));
+            }
+            if let Some(u) = endpoints.iter().find(|u| !u.starts_with("http://")) {
+                return Err(invalid(format!("route {}: upstream {u:?} must be an http:// URL", r.name)));
+            }
+            if let Some(hc) = &r.health_check {
+                if !hc.path.starts_with('/') || hc.interval_seconds == 0 || hc.timeout_seconds == 0 || hc.unhealthy_threshold == 0 || hc.healthy_threshold == 0 {
+                    return Err(invalid(format!("route {}: health_check needs a path starting with '/' and non-zero intervals and thresholds", r.name)));
+                }
+            }
+            if r.outlier_detection.consecutive_failures == 0 {
+                return Err(invalid(format!("route {}: outlier_detection.consecutive_failures must be >= 1", r.name)));
+            }
+            if let Some(cb) = &r.circuit_breaker {
+                if !(cb.error_ratio > 0.0 && cb.error_ratio <= 1.0) {
+                    return Err(invalid(format!("route {}: circuit_breaker.error_ratio must be in (0, 1]", r.name)));
+                }
+                if cb.window_seconds == 0 || cb.open_seconds == 0 || cb.min_requests == 0 || cb.probe_requests == 0 {
+                    return Err(invalid(format!("route {}: circuit_breaker needs non-zero window, open time, minimum and probes", r.name)));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Module configuration values (`[modules.*]` tables)
+#[derive(Debug, Default, Deserialize)]
+pub struct Modules {
+    #[serde(default)]
+    pub http: HttpModule,
 }
 
 /// Top-level configuration structure
@@ -42,8 +385,11 @@ pub struct Oidc {
 pub struct Config {
     pub global: Global,
     pub certs: Certs,
-    #[serde(rename = "auth.oidc")]
-    pub auth_oidc: Oidc,
+    pub auth: Auth,
+    #[serde(default)]
+    pub policy: PolicyConfig,
+    #[serde(default)]
+    pub modules: Modules,
 }
 
 impl Config {
@@ -55,18 +401,53 @@ impl Config {
         if self.certs.trust_store.is_empty() {
             return Err(ConfigError::Missing("certs.trust_store"));
         }
-        if self.auth_oidc.session_ttl_seconds < 60 {
+        if self.auth.oidc.session_ttl_seconds < 60 {
             return Err(ConfigError::Invalid {
                 key: "auth.oidc.session_ttl_seconds",
                 reason: "must be >= 60".into(),
             });
         }
-        if self.auth_oidc.cookie_name.len() < 5 {
+        if self.auth.oidc.session_max_lifetime_seconds < self.auth.oidc.session_ttl_seconds {
+            return Err(ConfigError::Invalid {
+                key: "auth.oidc.session_max_lifetime_seconds",
+                reason: "must be >= session_ttl_seconds".into(),
+            });
+        }
+        if self.auth.oidc.clock_skew_seconds > 300 {
+            return Err(ConfigError::Invalid {
+                key: "auth.oidc.clock_skew_seconds",
+                reason: "must be <= 300".into(),
+            });
+        }
+        if !self.auth.session.keys.is_empty() && !self.auth.session.keys.contains_key(&self.auth.session.current_key) {
+            return Err(ConfigError::Invalid {
+                key: "auth.session.current_key",
+                reason: "must name one of auth.session.keys".into(),
+            });
+        }
+        let claims = &self.auth.claims;
+        if let Some(p) = claims.groups.iter().chain(claims.claims.values()).find(|p| !p.starts_with('/')) {
+            return Err(ConfigError::Invalid {
+                key: "auth.claims",
+                reason: format!("{p:?} is not a JSON pointer (must start with '/')"),
+            });
+        }
+        if self.auth.oidc.cookie_name.len() < 5 {
             return Err(ConfigError::Invalid {
                 key: "auth.oidc.cookie_name",
                 reason: "too short".into(),
             });
         }
+        self.modules.http.validate()?;
+        if let Some(file) = &self.policy.file {
+            let (_, warnings) = appgate_policy::engine::Engine::load(self.policy.engine, file).map_err(|e| ConfigError::Invalid {
+                key: "policy.file",
+                reason: format!("{e:#}"),
+            })?;
+            for w in warnings {
+                tracing::warn!(policy = %file, "{w}");
+            }
+        }
         Ok(())
     }
 }
\ No newline at end of file
@@ -1,12 +1,10 @@
 use anyhow::Result;
 use clap::Parser;
-use axum::{routing::get, Router};
+use axum::{http::StatusCode, routing::get, Router};
 use prometheus::{Encoder, TextEncoder, Registry};
-use std::net::SocketAddr;
-use tracing_subscriber::EnvFilter;
+use std::{net::SocketAddr, time::Duration};
 use appgate_ctrl::Config;
 use std::fs;
-use toml;
 
 #[derive(Parser, Debug)]
 struct Args {
@@ -30,6 +28,23 @@ fn init_json_logger() {
         .init();
 }
 
+/// Readiness: appgate-mod-http's `/readyz` when `modules.http.metrics_bind` is set
+async fn ready(mod_http: Option<String>) -> (StatusCode, String) {
+    let Some(addr) = mod_http else {
+        return (StatusCode::OK, "ready".into());
+    };
+    let probe = async {
+        let resp = reqwest::get(format!("http://{addr}/readyz")).await?;
+        let status = resp.status();
+        Ok::<_, reqwest::Error>((status, resp.text().await?))
+    };
+    match tokio::time::timeout(Duration::from_secs(2), probe).await {
+        Ok(Ok((status, body))) => (status, format!("appgate-mod-http: {body}")),
+        Ok(Err(e)) => (StatusCode::SERVICE_UNAVAILABLE, format!("appgate-mod-http unreachable: {e}")),
+        Err(_) => (StatusCode::SERVICE_UNAVAILABLE, "appgate-mod-http did not answer".into()),
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     // initialise structured JSON logger with RFC3339 timestamps
@@ -43,12 +58,14 @@ async fn main() -> Result<()> {
     let cfg: Config = toml::from_str(&conf_text)?;
     cfg.validate().map_err(|e| anyhow::anyhow!(e))?;
 
-    // health
-    let health_app = Router::new().route("/healthz", get(|| async { "ok" }));
-    tokio::spawn({
-        let addr: SocketAddr = args.health_addr.parse().unwrap();
-        async move { axum::Server::bind(&addr).serve(health_app.into_make_service()).await.unwrap(); }
-    });
+    // health; readiness also needs the HTTP module to have an upstream for every route
+    let mod_http = cfg.modules.http.metrics_bind.clone();
+    let health_app = Router::new()
+        .route("/healthz", get(|| async { "ok" }))
+        .route("/readyz", get(move || ready(mod_http.clone())));
+    let health_addr: SocketAddr = args.health_addr.parse()?;
+    let health_listener = tokio::net::TcpListener::bind(health_addr).await?;
+    tokio::spawn(async move { axum::serve(health_listener, health_app).await.unwrap(); });
 
     // metrics
     let reg = Registry::new();
@@ -62,6 +79,6 @@ async fn main() -> Result<()> {
         }
     }));
     let addr: SocketAddr = args.metrics_addr.parse()?;
-    axum::Server::bind(&addr).serve(metrics_app.into_make_service()).await?;
+    axum::serve(tokio::net::TcpListener::bind(addr).await?, metrics_app).await?;
     Ok(())
 }
\ No newline at end of file
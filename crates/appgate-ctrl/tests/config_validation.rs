@@ -31,4 +31,166 @@ fn config_too_short_cookie() {
     "#;
     let cfg: Config = toml::from_str(toml_str).expect("parse inline config");
     assert!(cfg.validate().is_err());
-}
\ No newline at end of file
+}
+
+#[test]
+fn config_rejects_non_pointer_claim_paths() {
+    let toml_str = r#"
+        [global]
+        run_dir = "/run/x"
+        log_level = "info"
+
+        [certs]
+        trust_store = "/etc/ca.pem"
+
+        [auth.oidc]
+        issuer = "https://kc/realms/main"
+        client_id = "x"
+        client_secret = "env:K"
+        redirect_uri = "https://app/oidc/callback"
+        cookie_name = "appg_sess"
+        cookie_domain = "example.com"
+        session_ttl_seconds = 3600
+
+        [auth.claims]
+        groups = ["realm_access.roles"]
+    "#;
+    let cfg: Config = toml::from_str(toml_str).expect("parse inline config");
+    assert!(cfg.validate().is_err());
+}
+
+fn config_with_policy(file: &str) -> Config {
+    let toml_str = format!(
+        r#"
+        [global]
+        run_dir = "/run/x"
+        log_level = "info"
+
+        [certs]
+        trust_store = "/etc/ca.pem"
+
+        [auth.oidc]
+        issuer = "https://kc/realms/main"
+        client_id = "x"
+        client_secret = "env:K"
+        redirect_uri = "https://app/oidc/callback"
+        cookie_name = "appg_sess"
+        cookie_domain = "example.com"
+        session_ttl_seconds = 3600
+
+        [policy]
+        file = {file:?}
+    "#
+    );
+    toml::from_str(&toml_str).expect("parse inline config")
+}
+
+#[test]
+fn config_lints_the_policy_file() {
+    let shipped = concat!(env!("CARGO_MANIFEST_DIR"), "/../../config/policy/foundry.toml");
+    assert!(config_with_policy(shipped).validate().is_ok());
+
+    let path = std::env::temp_dir().join(format!("appgate-ctrl-lint-{}.toml", std::process::id()));
+    std::fs::write(
+        &path,
+        r#"
+        [[rules]]
+        name = "players"
+        protocol = "http"
+        resource = "http://foundry/"
+
+        [[rules]]
+        name = "admin"
+        protocol = "http"
+        resource = "http://foundry/"
+        require_groups = ["foundry-admin"]
+        inject = { "X-Role" = "admin" }
+        "#,
+    )
+    .unwrap();
+    let err = config_with_policy(path.to_str().unwrap()).validate().unwrap_err();
+    std::fs::remove_file(&path).unwrap();
+    let msg = err.to_string();
+    assert!(msg.starts_with("invalid value for policy.file"), "{msg}");
+    assert!(msg.contains("rule admin: never decides: shadowed by rule players"), "{msg}");
+
+    assert!(config_with_policy("/nonexistent/policy.toml").validate().is_err());
+}
+
+#[test]
+fn config_loads_cedar_policies_with_the_cedar_engine() {
+    let shipped = concat!(env!("CARGO_MANIFEST_DIR"), "/../../config/policy/foundry.cedar");
+    let mut cfg = config_with_policy(shipped);
+    assert!(cfg.validate().is_err(), "not TOML");
+    cfg.policy.engine = appgate_policy::engine::EngineKind::Cedar;
+    assert!(cfg.validate().is_ok());
+}
+
+#[test]
+fn config_validates_http_routes() {
+    let with_route = |route: &str| -> Config {
+        let toml_str = format!(
+            r#"
+            [global]
+            run_dir = "/run/x"
+            log_level = "info"
+
+            [certs]
+            trust_store = "/etc/ca.pem"
+
+            [auth.oidc]
+            issuer = "https://kc/realms/main"
+            client_id = "x"
+            client_secret = "env:K"
+            redirect_uri = "https://app/oidc/callback"
+            cookie_name = "appg_sess"
+            cookie_domain = "example.com"
+            session_ttl_seconds = 3600
+
+            [[modules.http.routes]]
+            {route}
+            "#
+        );
+        toml::from_str(&toml_str).expect("parse inline config")
+    };
+
+    let cfg = with_route(r#"name = "foundry"
+            hosts = ["vtt.example.com"]
+            path_prefix = "/foundry/"
+            rewrite_prefix = "/"
+            upstream = "http://10.0.0.5:30000""#);
+    assert!(cfg.validate().is_ok());
+    let route = &cfg.modules.http.routes[0];
+    assert_eq!((route.name.as_str(), route.rewrite_prefix.as_deref()), ("foundry", Some("/")));
+
+    let defaults = with_route(r#"name = "wiki"
+            upstream = "http://10.0.0.6""#);
+    assert_eq!(defaults.modules.http.routes[0].path_prefix, "/");
+    assert!(defaults.modules.http.routes[0].hosts.is_empty());
+
+    let pool = with_route(r#"name = "wiki"
+            upstreams = ["http://10.0.0.6", "http://10.0.0.7"]
+            balance = "least_connections"
+            health_check = { path = "/healthz" }
+            circuit_breaker = { error_ratio = 0.25 }"#);
+    assert!(pool.validate().is_ok());
+    let route = &pool.modules.http.routes[0];
+    assert_eq!(route.endpoints(), ["http://10.0.0.6", "http://10.0.0.7"]);
+    assert_eq!(route.balance, appgate_ctrl::Balance::LeastConnections);
+    assert_eq!(route.health_check.as_ref().map(|h| (h.interval_seconds, h.unhealthy_threshold)), Some((10, 3)));
+    assert_eq!(route.outlier_detection.consecutive_failures, 5);
+    assert_eq!(route.circuit_breaker.as_ref().map(|c| (c.error_ratio, c.min_requests, c.probe_requests)), Some((0.25, 10, 3)));
+
+    for bad in [
+        "name = \"wiki\"",
+        "name = \"wiki\"\nupstreams = [\"http://10.0.0.6\", \"10.0.0.7\"]",
+        "name = \"wiki\"\nupstream = \"http://10.0.0.6\"\nhealth_check = { interval_seconds = 0 }",
+        "name = \"wiki\"\nupstream = \"http://10.0.0.6\"\ncircuit_breaker = { error_ratio = 1.5 }",
+        "name = \"\"\nupstream = \"http://10.0.0.6\"",
+        "name = \"wiki\"\nupstream = \"10.0.0.6:80\"",
+        "name = \"wiki\"\npath_prefix = \"wiki/\"\nupstream = \"http://10.0.0.6\"",
+    ] {
+        let err = with_route(bad).validate().unwrap_err();
+        assert!(err.to_string().starts_with("invalid value for modules.http.routes"), "{err}");
+    }
+}
@@ -1,5 +1,5 @@
 fn main() {
     tonic_build::configure()
-        .compile(&["proto/pdp.proto"], &["proto"])
+        .compile_protos(&["proto/pdp.proto"], &["proto"])
         .unwrap();
 }
\ No newline at end of file
@@ -3,17 +3,20 @@ pub mod pdp {
 }
 
 use anyhow::Result;
-use tonic::transport::{Endpoint, Server};
-use tonic::transport::server::Router;
+use hyper_util::rt::TokioIo;
+use tonic::transport::{Endpoint, Server, Uri};
 use tower::service_fn;
 
-use std::{path::Path, sync::Arc};
+use std::{convert::Infallible, path::Path};
 use tokio::net::UnixListener;
 
 pub async fn uds_server<S>(svc: S, uds_path: &str) -> Result<()>
 where
-    S: tonic::codegen::Service<http::Request<hyper::body::Incoming>, Response=http::Response<tonic::body::BoxBody>>
-        + Clone + Send + 'static,
+    S: tower::Service<http::Request<tonic::body::BoxBody>, Response = http::Response<tonic::body::BoxBody>, Error = Infallible>
+        + tonic::server::NamedService
+        + Clone
+        + Send
+        + 'static,
     S::Future: Send + 'static,
 {
     if Path::new(uds_path).exists() {
@@ -27,10 +30,14 @@ where
 
 // Client connector for UDS: use http+unix “h2c over UDS”
 pub async fn uds_channel(uds_path: &str) -> Result<tonic::transport::Channel> {
-    let path = format!("http://localhost"); // dummy
-    let ep = Endpoint::try_from(path)?.connect_with_connector(service_fn(move |_| {
-        let p = uds_path.to_owned();
-        async move { tokio::net::UnixStream::connect(p).await }
-    }));
-    Ok(ep.await?)
-}
\ No newline at end of file
+    let path = "http://localhost".to_string(); // dummy; the connector ignores it
+    let uds_path = uds_path.to_owned();
+    let ep = Endpoint::try_from(path)?;
+    let chan = ep
+        .connect_with_connector(service_fn(move |_: Uri| {
+            let p = uds_path.clone();
+            async move { Ok::<_, std::io::Error>(TokioIo::new(tokio::net::UnixStream::connect(p).await?)) }
+        }))
+        .await?;
+    Ok(chan)
+}
@@ -0,0 +1,167 @@
+//! Per-upstream circuit breakers (`circuit_breaker` on a route).
+//!
+//! Outcomes are counted in ten buckets spanning the window. An open breaker turns half-open on
+//! the first request after `open_seconds`.
+
+use appgate_ctrl::CircuitBreaker;
+use prometheus::{IntCounterVec, IntGaugeVec};
+use std::{sync::Mutex, time::Duration};
+use tokio::time::Instant;
+
+const BUCKETS: u64 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+impl State {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Closed => "closed",
+            Self::Open => "open",
+            Self::HalfOpen => "half_open",
+        }
+    }
+}
+
+/// Requests and failures in one slice of the window
+#[derive(Clone, Copy, Default)]
+struct Bucket {
+    index: u64,
+    requests: u32,
+    failures: u32,
+}
+
+struct Inner {
+    state: State,
+    open_until: Instant,
+    buckets: [Bucket; BUCKETS as usize],
+    probes_in_flight: u32,
+    probe_successes: u32,
+}
+
+/// Breaker metrics, shared by every breaker
+#[derive(Clone)]
+pub struct BreakerMetrics {
+    pub state: IntGaugeVec,
+    pub transitions: IntCounterVec,
+}
+
+pub struct Breaker {
+    cfg: CircuitBreaker,
+    /// `route` and `endpoint` labels
+    labels: [String; 2],
+    metrics: BreakerMetrics,
+    started: Instant,
+    inner: Mutex<Inner>,
+}
+
+impl Breaker {
+    pub fn new(cfg: CircuitBreaker, route: &str, endpoint: &str, metrics: BreakerMetrics) -> Self {
+        let now = Instant::now();
+        metrics.state.with_label_values(&[route, endpoint]).set(0);
+        let inner = Inner { state: State::Closed, open_until: now, buckets: Default::default(), probes_in_flight: 0, probe_successes: 0 };
+        Self { cfg, labels: [route.into(), endpoint.into()], metrics, started: now, inner: Mutex::new(inner) }
+    }
+
+    /// Whether a request could be sent now
+    pub fn permits(&self, now: Instant) -> bool {
+        let inner = self.inner.lock().unwrap();
+        match inner.state {
+            State::Closed => true,
+            State::Open => now >= inner.open_until,
+            State::HalfOpen => inner.probes_in_flight + inner.probe_successes < self.cfg.probe_requests,
+        }
+    }
+
+    /// Admit a request: `Some(true)` for a half-open probe, `Some(false)` for a normal request,
+    /// `None` when the breaker turns it away
+    pub fn acquire(&self, now: Instant) -> Option<bool> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.state == State::Open && now >= inner.open_until {
+            self.transition(&mut inner, State::HalfOpen, now);
+        }
+        match inner.state {
+            State::Closed => Some(false),
+            State::Open => None,
+            State::HalfOpen if inner.probes_in_flight + inner.probe_successes < self.cfg.probe_requests => {
+                inner.probes_in_flight += 1;
+                Some(true)
+            }
+            State::HalfOpen => None,
+        }
+    }
+
+    /// Record the outcome of a request admitted by [`Breaker::acquire`]
+    pub fn record(&self, ok: bool, probe: bool, now: Instant) {
+        let mut inner = self.inner.lock().unwrap();
+        match (inner.state, probe) {
+            (State::HalfOpen, true) => {
+                inner.probes_in_flight -= 1;
+                if !ok {
+                    self.transition(&mut inner, State::Open, now);
+                } else {
+                    inner.probe_successes += 1;
+                    if inner.probe_successes >= self.cfg.probe_requests {
+                        self.transition(&mut inner, State::Closed, now);
+                    }
+                }
+            }
+            (State::Closed, _) => {
+                let index = self.bucket(now);
+                let bucket = &mut inner.buckets[(index % BUCKETS) as usize];
+                if bucket.index != index {
+                    *bucket = Bucket { index, ..Default::default() };
+                }
+                bucket.requests += 1;
+                bucket.failures += u32::from(!ok);
+                let live = inner.buckets.iter().filter(|b| b.index + BUCKETS > index);
+                let (requests, failures) = live.fold((0, 0), |(r, f), b| (r + b.requests, f + b.failures));
+                if requests >= self.cfg.min_requests && f64::from(failures) >= self.cfg.error_ratio * f64::from(requests) {
+                    self.transition(&mut inner, State::Open, now);
+                }
+            }
+            // requests admitted before the breaker opened, or probes outliving their half-open state
+            _ => {}
+        }
+    }
+
+    /// Give back the slot of a probe that ended without an outcome
+    pub fn release_probe(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.state == State::HalfOpen {
+            inner.probes_in_flight = inner.probes_in_flight.saturating_sub(1);
+        }
+    }
+
+    fn bucket(&self, now: Instant) -> u64 {
+        let width = Duration::from_secs(self.cfg.window_seconds) / BUCKETS as u32;
+        (now.duration_since(self.started).as_nanos() / width.as_nanos()) as u64
+    }
+
+    fn transition(&self, inner: &mut Inner, to: State, now: Instant) {
+        let from = inner.state;
+        inner.state = to;
+        inner.probes_in_flight = 0;
+        inner.probe_successes = 0;
+        match to {
+            State::Open => inner.open_until = now + Duration::from_secs(self.cfg.open_seconds),
+            State::Closed => inner.buckets = Default::default(),
+            State::HalfOpen => {}
+        }
+        let [route, endpoint] = &self.labels;
+        self.metrics.state.with_label_values(&[route, endpoint]).set(match to {
+            State::Closed => 0,
+            State::Open => 1,
+            State::HalfOpen => 2,
+        });
+        self.metrics.transitions.with_label_values(&[route, endpoint, to.as_str()]).inc();
+        match to {
+            State::Open => tracing::warn!(route = %route, endpoint = %endpoint, from = from.as_str(), seconds = self.cfg.open_seconds, "circuit opened"),
+            _ => tracing::info!(route = %route, endpoint = %endpoint, from = from.as_str(), to = to.as_str(), "circuit state changed"),
+        }
+    }
+}
@@ -1,16 +1,33 @@
 use anyhow::Result;
 use clap::Parser;
-use axum::{Router, routing::any, extract::State};
-use hyper::{Request, Response, Body, Client};
-use tracing_subscriber::EnvFilter;
-use appgate_ipc::{pdp::{p_d_p_client::PdpClient, DecisionRequest}, uds_channel};
-use std::{net::SocketAddr, sync::Arc};
+use axum::{Router, routing::{any, get}, body::Body, extract::{ConnectInfo, State}};
+use hyper::{Request, Response, upgrade::OnUpgrade};
+use hyper_util::{client::legacy::{connect::HttpConnector, Client}, rt::{TokioExecutor, TokioIo}};
+use appgate_ipc::{pdp::{pdp_client::PdpClient, DecisionRequest, Outcome}, uds_channel};
+use ipnet::IpNet;
+use percent_encoding::percent_decode_str;
+use std::{collections::HashMap, net::{IpAddr, SocketAddr}, sync::Arc, time::Duration};
+use tokio::{io::{AsyncReadExt, AsyncWriteExt}, time::Instant};
+
+mod breaker;
+mod pool;
+mod routes;
+use pool::{Lease, PoolMetrics};
+use prometheus::{Encoder, Registry, TextEncoder};
+use routes::Routes;
 
 #[derive(Clone)]
 struct AppState {
     pdp: Arc<tokio::sync::Mutex<PdpClient<tonic::transport::Channel>>>,
-    upstream: http::Uri,
+    client: Client<HttpConnector, Body>,
+    routes: Arc<Routes>,
     cookie_name: String,
+    public_scheme: String,
+    api_prefixes: Arc<Vec<String>>,
+    attr_headers: Arc<Vec<String>>,
+    trusted_proxies: Arc<Vec<IpNet>>,
+    identity_prefixes: Arc<Vec<String>>,
+    upgrade_idle: Duration,
 }
 
 #[derive(Parser, Debug)]
@@ -19,56 +36,321 @@ struct Args {
     bind: String,
     #[arg(long, default_value="/run/appgate/pdp.sock")]
     pdp_uds: String,
+    /// Upstream for every request when `--config` has no `[[modules.http.routes]]`
     #[arg(long, default_value="http://localhost:3000")]
     upstream: String,
+    /// appgate.toml whose `[[modules.http.routes]]` route requests by host and path
+    #[arg(long)]
+    config: Option<String>,
+    /// Listener for Prometheus `/metrics` and `/readyz` [default: `modules.http.metrics_bind`, else 127.0.0.1:9103]
+    #[arg(long)]
+    metrics_bind: Option<String>,
     #[arg(long, default_value="appg_sess")]
     cookie_name: String,
+    /// Scheme browsers use to reach this module (for post-login redirects back to the page)
+    #[arg(long, default_value="http")]
+    public_scheme: String,
+    /// Path prefix served to API clients: unauthenticated requests get 401 instead of a login redirect
+    #[arg(long = "api-prefix")]
+    api_prefixes: Vec<String>,
+    /// Request header passed to the PDP as a `header.<name>` attribute for policy conditions
+    #[arg(long = "attr-header", default_values = ["accept", "content-type", "user-agent", "x-requested-with"])]
+    attr_headers: Vec<String>,
+    /// Proxy address range (CIDR) whose `X-Forwarded-For` is believed when finding the client address
+    #[arg(long = "trusted-proxy")]
+    trusted_proxies: Vec<IpNet>,
+    /// Header name prefix reserved for identity headers: client-sent headers starting with it are
+    /// dropped, so only the PDP's injected headers reach the upstream
+    #[arg(long = "identity-header-prefix", default_values = ["x-user-", "x-role", "x-appgate-", "remote-user"])]
+    identity_prefixes: Vec<String>,
+    /// Seconds without traffic either way after which an upgraded (e.g. WebSocket) connection is closed
+    #[arg(long = "upgrade-idle-timeout", default_value_t = 300)]
+    upgrade_idle_secs: u64,
+}
+
+/// Hop-by-hop headers (RFC 9110 section 7.6.1), plus the non-standard `Proxy-Connection`
+const HOP_BY_HOP: [&str; 9] = [
+    "connection", "keep-alive", "proxy-connection", "proxy-authenticate", "proxy-authorization",
+    "te", "trailer", "transfer-encoding", "upgrade",
+];
+
+/// Headers a client could use to impersonate a proxy or pass its own credentials upstream
+const SPOOFABLE: [&str; 4] = ["forwarded", "via", "authorization", "x-real-ip"];
+
+fn status(code: u16, body: &'static str) -> Response<Body> {
+    Response::builder().status(code).body(Body::from(body)).unwrap()
+}
+
+/// Browsers navigating to a page get redirected to login; API clients get 401
+fn wants_login_redirect(st: &AppState, req: &Request<Body>) -> bool {
+    let accepts_html = req.headers().get(http::header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|a| a.contains("text/html"));
+    accepts_html && !st.api_prefixes.iter().any(|p| req.uri().path().starts_with(p.as_str()))
+}
+
+/// The client address: the TCP peer, or when that is a trusted proxy, the right-most
+/// `X-Forwarded-For` entry not itself a trusted proxy
+fn client_ip(st: &AppState, peer: IpAddr, req: &Request<Body>) -> IpAddr {
+    let trusted = |ip: &IpAddr| st.trusted_proxies.iter().any(|n| n.contains(&ip.to_canonical()));
+    let peer = peer.to_canonical();
+    if !trusted(&peer) {
+        return peer;
+    }
+    let forwarded: Vec<&str> = req.headers().get_all("x-forwarded-for").iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .collect();
+    let mut client = peer;
+    for hop in forwarded.iter().rev() {
+        // a malformed entry means everything to its left is unverifiable
+        let Some(ip) = hop.parse::<IpAddr>().ok().or_else(|| hop.parse::<SocketAddr>().ok().map(|s| s.ip())) else {
+            break;
+        };
+        client = ip;
+        if !trusted(&ip) {
+            break;
+        }
+    }
+    client
+}
+
+/// Attributes policy conditions can test: method, host, path, url, `query.<key>` and `header.<name>`
+fn request_attributes(st: &AppState, req: &Request<Body>, host: &str) -> HashMap<String, String> {
+    let path_and_query = req.uri().path_and_query().map(|p| p.as_str()).unwrap_or("/");
+    let mut attrs: HashMap<String, String> = [
+        ("method", req.method().as_str().to_string()),
+        ("host", host.to_string()),
+        ("path", req.uri().path().to_string()),
+        ("url", format!("{}://{host}{path_and_query}", st.public_scheme)),
+    ].into_iter().map(|(k,v)|(k.to_string(),v)).collect();
+    let decode = |s: &str| percent_decode_str(&s.replace('+', " ")).decode_utf8_lossy().into_owned();
+    for pair in req.uri().query().unwrap_or("").split('&').filter(|p| !p.is_empty()) {
+        let (k, v) = pair.split_once('=').unwrap_or((pair, ""));
+        // repeated keys keep their first value
+        attrs.entry(format!("query.{}", decode(k))).or_insert_with(|| decode(v));
+    }
+    for name in st.attr_headers.iter() {
+        let values: Vec<&str> = req.headers().get_all(name.as_str()).iter().filter_map(|v| v.to_str().ok()).collect();
+        if !values.is_empty() {
+            attrs.insert(format!("header.{}", name.to_ascii_lowercase()), values.join(", "));
+        }
+    }
+    attrs
+}
+
+/// Drop what the client must not decide for the upstream: hop-by-hop headers (and any `Connection`
+/// names), forwarding headers, `Authorization`, the identity namespace and the session cookie
+fn strip_inbound(st: &AppState, headers: &mut http::HeaderMap) {
+    let listed: Vec<String> = headers.get_all(http::header::CONNECTION).iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|n| n.trim().to_ascii_lowercase())
+        .collect();
+    let drop: Vec<http::header::HeaderName> = headers.keys()
+        .filter(|n| {
+            let n = n.as_str();
+            HOP_BY_HOP.contains(&n)
+                || SPOOFABLE.contains(&n)
+                || n.starts_with("x-forwarded-")
+                || listed.iter().any(|l| l == n)
+                || st.identity_prefixes.iter().any(|p| n.starts_with(p.as_str()))
+        })
+        .cloned()
+        .collect();
+    for name in drop {
+        headers.remove(name);
+    }
+    // other cookies belong to the application
+    let cookies: Vec<&str> = headers.get_all(http::header::COOKIE).iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .map(str::trim)
+        .filter(|c| !c.is_empty() && c.split_once('=').map_or(*c, |(k, _)| k) != st.cookie_name)
+        .collect();
+    let cookies = cookies.join("; ");
+    headers.remove(http::header::COOKIE);
+    if let Ok(v) = http::HeaderValue::from_str(&cookies) {
+        if !cookies.is_empty() {
+            headers.insert(http::header::COOKIE, v);
+        }
+    }
+}
+
+/// The protocol a request asks to switch to (`Connection: upgrade` plus `Upgrade`), such as `websocket`
+fn upgrade_protocol(headers: &http::HeaderMap) -> Option<http::HeaderValue> {
+    let asks = headers.get_all(http::header::CONNECTION).iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|t| t.trim().eq_ignore_ascii_case("upgrade"));
+    asks.then(|| headers.get(http::header::UPGRADE).cloned()).flatten()
+}
+
+/// When the PDP decision expires (`DecisionResponse.expiry`, RFC 3339), if it says
+fn deadline(expiry: &str) -> Option<Instant> {
+    let at = chrono::DateTime::parse_from_rfc3339(expiry).ok()?;
+    let left = (at.with_timezone(&chrono::Utc) - chrono::Utc::now()).to_std().unwrap_or_default();
+    Some(Instant::now() + left)
 }
 
-async fn handler(State(st): State<AppState>, mut req: Request<Body>) -> Result<Response<Body>, axum::http::Error> {
-    // Extract bearer/cookie as “session token” (MVP: raw cookie value)
+/// Relay an upgraded connection both ways until both sides have closed, neither has sent anything
+/// for `idle`, or the decision that allowed it expires at `deadline`. The connection counts
+/// against its upstream while `_lease` is held
+async fn splice(client: OnUpgrade, upstream: OnUpgrade, idle: Duration, deadline: Option<Instant>, _lease: Lease) {
+    let (client, upstream) = match tokio::try_join!(client, upstream) {
+        Ok(pair) => pair,
+        Err(e) => {
+            tracing::warn!(error = %e, "upgrade failed");
+            return;
+        }
+    };
+    let expired = async {
+        match deadline {
+            Some(at) => tokio::time::sleep_until(at).await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::pin!(expired);
+    let (mut client_rd, mut client_wr) = tokio::io::split(TokioIo::new(client));
+    let (mut upstream_rd, mut upstream_wr) = tokio::io::split(TokioIo::new(upstream));
+    let (mut from_client, mut from_upstream) = (vec![0u8; 16 * 1024], vec![0u8; 16 * 1024]);
+    let (mut client_open, mut upstream_open) = (true, true);
+    while client_open || upstream_open {
+        tokio::select! {
+            n = client_rd.read(&mut from_client), if client_open => match n {
+                // half-close: pass the EOF on and keep relaying the other way
+                Ok(0) => { client_open = false; let _ = upstream_wr.shutdown().await; }
+                Ok(n) => if upstream_wr.write_all(&from_client[..n]).await.is_err() { break },
+                Err(_) => break,
+            },
+            n = upstream_rd.read(&mut from_upstream), if upstream_open => match n {
+                Ok(0) => { upstream_open = false; let _ = client_wr.shutdown().await; }
+                Ok(n) => if client_wr.write_all(&from_upstream[..n]).await.is_err() { break },
+                Err(_) => break,
+            },
+            _ = tokio::time::sleep(idle) => {
+                tracing::debug!("closing idle upgraded connection");
+                break;
+            }
+            _ = &mut expired => {
+                tracing::info!("closing upgraded connection: decision expired");
+                break;
+            }
+        }
+    }
+}
+
+/// Fresh `X-Forwarded-*` headers describing the request as this module received it
+fn set_forwarded(st: &AppState, headers: &mut http::HeaderMap, client: IpAddr, host: &str) {
+    let pairs = [("x-forwarded-for", client.to_string()), ("x-forwarded-proto", st.public_scheme.clone()), ("x-forwarded-host", host.to_string())];
+    for (name, value) in pairs {
+        if let Ok(v) = http::HeaderValue::from_str(&value) {
+            headers.insert(name, v);
+        }
+    }
+}
+
+async fn handler(State(st): State<AppState>, ConnectInfo(remote): ConnectInfo<SocketAddr>, mut req: Request<Body>) -> Response<Body> {
     let token = req.headers().get(http::header::COOKIE)
         .and_then(|v| v.to_str().ok())
-        .and_then(|s| s.split(';').find(|p| p.trim_start().starts_with(&st.cookie_name)))
-        .and_then(|kv| kv.split_once('=')).map(|(_,v)| v.to_string())
+        .and_then(|s| s.split(';').filter_map(|kv| kv.trim().split_once('=')).find(|(k, _)| *k == st.cookie_name))
+        .map(|(_, v)| v.to_string())
         .unwrap_or_default();
+    let host = req.headers().get(http::header::HOST)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("localhost")
+        .to_string();
+    let path = req.uri().path().to_string();
+    let Some((route, rest)) = st.routes.find(&host, &path) else {
+        return status(404, "no route");
+    };
+    let forward_path = route.forward_path(&path, rest);
+    let client_addr = client_ip(&st, remote.ip(), &req);
+    let upgrade = upgrade_protocol(req.headers());
+    let expires;
+    let session_key = match token.is_empty() {
+        true => client_addr.to_string(),
+        false => token.clone(),
+    };
 
     // PDP decision
     {
         let mut client = st.pdp.lock().await;
-        let attrs = [("method","GET")].into_iter().map(|(k,v)|(k.to_string(),v.to_string())).collect();
+        let attrs = request_attributes(&st, &req, &host);
         let dr = DecisionRequest {
             session_token: token,
             protocol: "http".into(),
-            resource: req.uri().to_string(),
-            peer: "unknown".into(),
+            // named routes are resources in the application's own path space
+            resource: match &route.name {
+                Some(name) => format!("http://{name}{forward_path}"),
+                None => format!("http://{host}{path}"),
+            },
+            peer: client_addr.to_string(),
             attributes: Some(appgate_ipc::pdp::Attributes{ kv: attrs }),
         };
-        let resp = client.decide(dr).await.map_err(|_| axum::http::Error::new_infallible())?.into_inner();
+        let resp = match client.decide(dr).await {
+            Ok(r) => r.into_inner(),
+            Err(e) => {
+                tracing::error!(error = %e, "PDP call failed");
+                return status(503, "authorization unavailable");
+            }
+        };
         if !resp.allow {
-            return Ok(Response::builder().status(403).body(Body::from("forbidden")).unwrap());
+            return match resp.outcome() {
+                Outcome::Unauthenticated if !resp.login_url.is_empty() && wants_login_redirect(&st, &req) => {
+                    Response::builder().status(302).header(http::header::LOCATION, resp.login_url).body(Body::empty()).unwrap()
+                }
+                Outcome::Unauthenticated => status(401, "unauthenticated"),
+                _ => status(403, "forbidden"),
+            };
         }
+        expires = deadline(&resp.expiry);
+        strip_inbound(&st, req.headers_mut());
         for (k,v) in resp.inject {
-            req.headers_mut().insert(
-                http::header::HeaderName::from_bytes(k.as_bytes()).unwrap(),
-                http::HeaderValue::from_str(&v).unwrap()
-            );
+            match (http::header::HeaderName::from_bytes(k.as_bytes()), http::HeaderValue::from_str(&v)) {
+                (Ok(name), Ok(value)) => { req.headers_mut().insert(name, value); }
+                _ => tracing::warn!(header = %k, "skipping invalid injected header"),
+            }
+        }
+        set_forwarded(&st, req.headers_mut(), client_addr, &host);
+        // the handshake itself is hop-by-hop, so it was stripped above; ask the upstream anew
+        if let Some(protocol) = &upgrade {
+            req.headers_mut().insert(http::header::CONNECTION, http::HeaderValue::from_static("upgrade"));
+            req.headers_mut().insert(http::header::UPGRADE, protocol.clone());
         }
     }
+    let Some(mut lease) = route.pool.pick(&session_key) else {
+        tracing::warn!(route = %route.pool.route, "no healthy upstream");
+        return status(503, "no healthy upstream");
+    };
+    let client_upgrade = upgrade.is_some().then(|| hyper::upgrade::on(&mut req));
 
     // Proxy to upstream
     let (mut parts, body) = req.into_parts();
     let mut uri = parts.uri.clone().into_parts();
-    let upstream: http::Uri = st.upstream.clone();
+    let upstream = &lease.endpoint.uri;
     uri.scheme = upstream.scheme().cloned();
     uri.authority = upstream.authority().cloned();
+    let query = parts.uri.query().map(|q| format!("?{q}")).unwrap_or_default();
+    uri.path_and_query = Some(format!("{forward_path}{query}").parse().expect("rewrite prefixes are checked at startup"));
     parts.uri = http::Uri::from_parts(uri).unwrap();
     let fwd_req = Request::from_parts(parts, body);
-    let client = Client::new();
-    match client.request(fwd_req).await {
-        Ok(r) => Ok(r),
-        Err(_) => Ok(Response::builder().status(502).body(Body::from("bad gateway")).unwrap())
+    let mut resp = match st.client.request(fwd_req).await {
+        Ok(r) => r,
+        Err(_) => {
+            route.pool.report(&mut lease, None);
+            return status(502, "bad gateway");
+        }
+    };
+    route.pool.report(&mut lease, Some(resp.status()));
+    if let Some(client_upgrade) = client_upgrade {
+        if resp.status() == http::StatusCode::SWITCHING_PROTOCOLS {
+            let upstream_upgrade = hyper::upgrade::on(&mut resp);
+            tokio::spawn(splice(client_upgrade, upstream_upgrade, st.upgrade_idle, expires, lease));
+        }
     }
+    resp.map(Body::new)
 }
 
 #[tokio::main]
@@ -78,18 +360,75 @@ async fn main() -> Result<()> {
 
     let args = Args::parse();
     let chan = uds_channel(&args.pdp_uds).await?;
-    let pdp = Arc::new(tokio::sync::Mutex::new(appgate_ipc::pdp::p_d_p_client::PdpClient::new(chan)));
+    let pdp = Arc::new(tokio::sync::Mutex::new(appgate_ipc::pdp::pdp_client::PdpClient::new(chan)));
+
+    let module = match &args.config {
+        Some(path) => {
+            let cfg: appgate_ctrl::Config = toml::from_str(&std::fs::read_to_string(path)?)?;
+            cfg.modules.http.validate()?;
+            cfg.modules.http
+        }
+        None => Default::default(),
+    };
+    let registry = Registry::new();
+    let metrics = PoolMetrics::new(&registry)?;
+    let routes = Arc::new(if module.routes.is_empty() {
+        Routes::single(args.upstream.parse()?, &metrics)
+    } else {
+        Routes::from_config(&module.routes, &metrics)?
+    });
+
+    let metrics_addr: SocketAddr = args.metrics_bind.or(module.metrics_bind).as_deref().unwrap_or("127.0.0.1:9103").parse()?;
+    let metrics_app = Router::new()
+        .route("/metrics", get({
+            let routes = routes.clone();
+            move || async move {
+                routes.pools().for_each(|p| p.refresh_metrics());
+                let mut buf = Vec::new();
+                TextEncoder::new().encode(&registry.gather(), &mut buf).unwrap();
+                String::from_utf8(buf).unwrap()
+            }
+        }))
+        // ready while every route has an upstream up; open circuit breakers do not count
+        .route("/readyz", get({
+            let routes = routes.clone();
+            move || async move {
+                let down: Vec<&str> = routes.pools().filter(|p| !p.ready()).map(|p| p.route.as_str()).collect();
+                match down.is_empty() {
+                    true => (http::StatusCode::OK, "ready".to_string()),
+                    false => (http::StatusCode::SERVICE_UNAVAILABLE, format!("no healthy upstream: {}", down.join(", "))),
+                }
+            }
+        }));
+    tracing::info!("metrics on {}", metrics_addr);
+    tokio::spawn(async move {
+        let served = match tokio::net::TcpListener::bind(metrics_addr).await {
+            Ok(listener) => axum::serve(listener, metrics_app).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = served {
+            tracing::error!(error = %e, "metrics listener failed");
+        }
+    });
 
     let state = AppState {
         pdp,
-        upstream: args.upstream.parse().unwrap(),
+        client: Client::builder(TokioExecutor::new()).build_http(),
+        routes,
         cookie_name: args.cookie_name,
+        public_scheme: args.public_scheme,
+        api_prefixes: Arc::new(args.api_prefixes),
+        attr_headers: Arc::new(args.attr_headers),
+        trusted_proxies: Arc::new(args.trusted_proxies),
+        identity_prefixes: Arc::new(args.identity_prefixes.iter().map(|p| p.to_ascii_lowercase()).collect()),
+        upgrade_idle: Duration::from_secs(args.upgrade_idle_secs),
     };
 
-    let app = Router::new().route("/*path", any(handler)).with_state(state);
+    let app = Router::new().route("/", any(handler)).route("/*path", any(handler)).with_state(state);
     let addr: SocketAddr = args.bind.parse()?;
     tracing::info!("HTTP module on {}", addr);
-    axum::Server::bind(&addr).serve(app.into_make_service()).await?;
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await?;
     Ok(())
 }
 
@@ -0,0 +1,246 @@
+//! Upstream pools: load balancing, active health checks and passive outlier detection.
+//!
+//! An endpoint takes requests while it is healthy (active checks, when configured) and not
+//! ejected (passive detection). Ejection never takes the last available endpoint of a pool: a
+//! pool that is failing anyway keeps trying rather than answering `503` for every request. An
+//! open [circuit breaker](crate::breaker) does take it out, so the route answers a fast `503`, but
+//! leaves readiness alone: a burst of errors should not pull the gateway out of its load balancer.
+
+use anyhow::Result;
+use appgate_ctrl::{Balance, CircuitBreaker, HealthCheck, OutlierDetection};
+use axum::body::Body;
+use hyper_util::{client::legacy::Client, rt::TokioExecutor};
+use prometheus::{IntCounterVec, IntGaugeVec, Opts, Registry};
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    sync::{
+        atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+use tokio::time::Instant;
+
+use crate::breaker::{Breaker, BreakerMetrics};
+
+/// Pool metrics, shared by every pool
+#[derive(Clone)]
+pub struct PoolMetrics {
+    up: IntGaugeVec,
+    in_flight: IntGaugeVec,
+    ejections: IntCounterVec,
+    breaker: BreakerMetrics,
+}
+
+impl PoolMetrics {
+    /// Register `appgate_http_upstream_up`, `appgate_http_upstream_in_flight`,
+    /// `appgate_http_upstream_ejections_total`, `appgate_http_upstream_circuit_state` (0 closed,
+    /// 1 open, 2 half-open) and `appgate_http_upstream_circuit_transitions_total{to}`, each by
+    /// `route` and `endpoint`
+    pub fn new(registry: &Registry) -> Result<Self> {
+        let labels = ["route", "endpoint"];
+        let up = IntGaugeVec::new(Opts::new("appgate_http_upstream_up", "Whether the upstream takes requests (healthy and not ejected)"), &labels)?;
+        let in_flight = IntGaugeVec::new(Opts::new("appgate_http_upstream_in_flight", "Requests and upgraded connections in flight"), &labels)?;
+        let ejections = IntCounterVec::new(Opts::new("appgate_http_upstream_ejections_total", "Outlier ejections"), &labels)?;
+        registry.register(Box::new(up.clone()))?;
+        registry.register(Box::new(in_flight.clone()))?;
+        let state = IntGaugeVec::new(Opts::new("appgate_http_upstream_circuit_state", "Circuit breaker state: 0 closed, 1 open, 2 half-open"), &labels)?;
+        let transitions = IntCounterVec::new(
+            Opts::new("appgate_http_upstream_circuit_transitions_total", "Circuit breaker state changes by new state"),
+            &["route", "endpoint", "to"],
+        )?;
+        registry.register(Box::new(ejections.clone()))?;
+        registry.register(Box::new(state.clone()))?;
+        registry.register(Box::new(transitions.clone()))?;
+        Ok(Self { up, in_flight, ejections, breaker: BreakerMetrics { state, transitions } })
+    }
+}
+
+/// One upstream address of a pool
+pub struct Endpoint {
+    pub uri: http::Uri,
+    /// Last active health check verdict (true until checks say otherwise)
+    healthy: AtomicBool,
+    /// Consecutive check results agreeing against the current verdict
+    streak: AtomicU32,
+    /// Consecutive failed requests
+    failures: AtomicU32,
+    ejected_until: Mutex<Option<Instant>>,
+    in_flight: AtomicUsize,
+    breaker: Option<Breaker>,
+}
+
+impl Endpoint {
+    /// Healthy and not ejected
+    fn up(&self, now: Instant) -> bool {
+        self.healthy.load(Ordering::Relaxed) && self.ejected_until.lock().unwrap().is_none_or(|until| until <= now)
+    }
+
+    /// Up, and its circuit breaker (if any) lets a request through
+    fn available(&self, now: Instant) -> bool {
+        self.up(now) && self.breaker.as_ref().is_none_or(|b| b.permits(now))
+    }
+}
+
+/// An endpoint picked for a request; counts as in flight until dropped
+pub struct Lease {
+    pub endpoint: Arc<Endpoint>,
+    /// A half-open circuit breaker probe whose outcome is not yet reported
+    probe: bool,
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        self.endpoint.in_flight.fetch_sub(1, Ordering::Relaxed);
+        if let (true, Some(b)) = (self.probe, &self.endpoint.breaker) {
+            b.release_probe();
+        }
+    }
+}
+
+pub struct Pool {
+    pub route: String,
+    endpoints: Vec<Arc<Endpoint>>,
+    balance: Balance,
+    outlier: OutlierDetection,
+    next: AtomicUsize,
+    metrics: PoolMetrics,
+}
+
+impl Pool {
+    pub fn new(
+        route: &str,
+        uris: Vec<http::Uri>,
+        balance: Balance,
+        outlier: OutlierDetection,
+        breaker: Option<CircuitBreaker>,
+        metrics: PoolMetrics,
+    ) -> Self {
+        let endpoints = uris
+            .into_iter()
+            .map(|uri| {
+                let breaker = breaker.clone().map(|cfg| Breaker::new(cfg, route, &uri.to_string(), metrics.breaker.clone()));
+                Arc::new(Endpoint {
+                    uri,
+                    healthy: AtomicBool::new(true),
+                    streak: AtomicU32::new(0),
+                    failures: AtomicU32::new(0),
+                    ejected_until: Mutex::new(None),
+                    in_flight: AtomicUsize::new(0),
+                    breaker,
+                })
+            })
+            .collect();
+        Self { route: route.to_string(), endpoints, balance, outlier, next: AtomicUsize::new(0), metrics }
+    }
+
+    /// Pick an available endpoint; `session` keys [`Balance::SessionHash`]
+    pub fn pick(&self, session: &str) -> Option<Lease> {
+        let now = Instant::now();
+        let available: Vec<&Arc<Endpoint>> = self.endpoints.iter().filter(|e| e.available(now)).collect();
+        let endpoint = match self.balance {
+            _ if available.is_empty() => return None,
+            Balance::RoundRobin => available[self.next.fetch_add(1, Ordering::Relaxed) % available.len()],
+            Balance::LeastConnections => {
+                // rotate the starting point so ties are spread too
+                let start = self.next.fetch_add(1, Ordering::Relaxed);
+                let n = available.len();
+                (0..n).map(|i| available[(start + i) % n]).min_by_key(|e| e.in_flight.load(Ordering::Relaxed))?
+            }
+            // rendezvous hashing: losing an endpoint only moves the sessions it had
+            Balance::SessionHash => *available.iter().max_by_key(|e| {
+                let mut h = DefaultHasher::new();
+                (session, e.uri.to_string()).hash(&mut h);
+                h.finish()
+            })?,
+        };
+        let probe = match &endpoint.breaker {
+            Some(b) => b.acquire(now)?,
+            None => false,
+        };
+        endpoint.in_flight.fetch_add(1, Ordering::Relaxed);
+        Some(Lease { endpoint: endpoint.clone(), probe })
+    }
+
+    /// Whether any endpoint is up; open circuit breakers do not count against readiness
+    pub fn ready(&self) -> bool {
+        let now = Instant::now();
+        self.endpoints.iter().any(|e| e.up(now))
+    }
+
+    /// Record a proxied request's outcome (`None` for a connection error) for the circuit breaker
+    /// (5xx fail) and passive outlier detection (502, 503 and 504 fail)
+    pub fn report(&self, lease: &mut Lease, status: Option<http::StatusCode>) {
+        let endpoint = &lease.endpoint;
+        if let Some(b) = &endpoint.breaker {
+            b.record(status.is_some_and(|s| !s.is_server_error()), lease.probe, Instant::now());
+            lease.probe = false;
+        }
+        if status.is_some_and(|s| !matches!(s.as_u16(), 502..=504)) {
+            endpoint.failures.store(0, Ordering::Relaxed);
+            return;
+        }
+        if endpoint.failures.fetch_add(1, Ordering::Relaxed) + 1 < self.outlier.consecutive_failures {
+            return;
+        }
+        endpoint.failures.store(0, Ordering::Relaxed);
+        let now = Instant::now();
+        if !endpoint.available(now) || self.endpoints.iter().filter(|e| e.available(now)).count() < 2 {
+            return;
+        }
+        *endpoint.ejected_until.lock().unwrap() = Some(now + Duration::from_secs(self.outlier.ejection_seconds));
+        let uri = endpoint.uri.to_string();
+        self.metrics.ejections.with_label_values(&[&self.route, &uri]).inc();
+        tracing::warn!(route = %self.route, endpoint = %uri, seconds = self.outlier.ejection_seconds, "ejecting failing upstream");
+    }
+
+    /// Bring the exported gauges up to date
+    pub fn refresh_metrics(&self) {
+        let now = Instant::now();
+        for e in &self.endpoints {
+            let labels = [self.route.as_str(), &e.uri.to_string()];
+            self.metrics.up.with_label_values(&labels).set(e.up(now).into());
+            self.metrics.in_flight.with_label_values(&labels).set(e.in_flight.load(Ordering::Relaxed) as i64);
+        }
+    }
+
+    /// Check every endpoint each `hc.interval_seconds`, forever
+    pub fn spawn_health_checks(self: &Arc<Self>, hc: HealthCheck) {
+        let pool = self.clone();
+        tokio::spawn(async move {
+            let client = Client::builder(TokioExecutor::new()).build_http::<Body>();
+            let mut every = tokio::time::interval(Duration::from_secs(hc.interval_seconds));
+            loop {
+                every.tick().await;
+                for e in &pool.endpoints {
+                    let uri = format!("{}{}", e.uri.to_string().trim_end_matches('/'), hc.path);
+                    let ok = match uri.parse::<http::Uri>() {
+                        Ok(uri) => tokio::time::timeout(Duration::from_secs(hc.timeout_seconds), client.get(uri))
+                            .await
+                            .is_ok_and(|r| r.is_ok_and(|r| r.status().is_success() || r.status().is_redirection())),
+                        Err(_) => false,
+                    };
+                    pool.record_check(e, ok, &hc);
+                }
+            }
+        });
+    }
+
+    fn record_check(&self, e: &Endpoint, ok: bool, hc: &HealthCheck) {
+        let healthy = e.healthy.load(Ordering::Relaxed);
+        if ok == healthy {
+            e.streak.store(0, Ordering::Relaxed);
+            return;
+        }
+        let threshold = if ok { hc.healthy_threshold } else { hc.unhealthy_threshold };
+        if e.streak.fetch_add(1, Ordering::Relaxed) + 1 >= threshold {
+            e.streak.store(0, Ordering::Relaxed);
+            e.healthy.store(ok, Ordering::Relaxed);
+            match ok {
+                true => tracing::info!(route = %self.route, endpoint = %e.uri, "upstream healthy again"),
+                false => tracing::warn!(route = %self.route, endpoint = %e.uri, "upstream failed health checks"),
+            }
+        }
+    }
+}
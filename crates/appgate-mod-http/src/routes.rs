@@ -0,0 +1,96 @@
+//! Host- and path-based routing to upstreams (`[[modules.http.routes]]` in appgate.toml).
+
+use anyhow::{Context, Result};
+use appgate_ctrl::{Balance, HttpRoute, OutlierDetection};
+use std::sync::Arc;
+
+use crate::pool::{Pool, PoolMetrics};
+
+/// A route ready to serve requests
+pub struct Route {
+    /// Logical name used for the PDP resource; `None` for the `--upstream` fallback, which names
+    /// resources after the `Host` header
+    pub name: Option<String>,
+    /// Lowercase host names; empty matches every host
+    hosts: Vec<String>,
+    /// Path prefix without its trailing `/` (`""` for the root)
+    prefix: String,
+    /// Replacement for `prefix`, also without a trailing `/`
+    rewrite: Option<String>,
+    pub pool: Arc<Pool>,
+}
+
+impl Route {
+    /// The path forwarded upstream for a request whose path is `rest` below the prefix
+    pub fn forward_path(&self, path: &str, rest: &str) -> String {
+        match &self.rewrite {
+            Some(rewrite) if rewrite.is_empty() && rest.is_empty() => "/".into(),
+            Some(rewrite) => format!("{rewrite}{rest}"),
+            None => path.to_string(),
+        }
+    }
+}
+
+/// The routing table
+pub struct Routes(Vec<Route>);
+
+impl Routes {
+    /// Routes for the configuration, each pool health-checked when the route asks for it
+    pub fn from_config(routes: &[HttpRoute], metrics: &PoolMetrics) -> Result<Self> {
+        routes
+            .iter()
+            .map(|r| {
+                let uris = r
+                    .endpoints()
+                    .into_iter()
+                    .map(|u| u.parse().with_context(|| format!("route {}: upstream {u}", r.name)))
+                    .collect::<Result<_>>()?;
+                let pool = Pool::new(&r.name, uris, r.balance, r.outlier_detection.clone(), r.circuit_breaker.clone(), metrics.clone());
+                let pool = Arc::new(pool);
+                if let Some(hc) = &r.health_check {
+                    pool.spawn_health_checks(hc.clone());
+                }
+                let rewrite = r.rewrite_prefix.as_deref().map(|p| p.trim_end_matches('/').to_string());
+                if let Some(p) = &rewrite {
+                    format!("{p}/").parse::<http::uri::PathAndQuery>().with_context(|| format!("route {}: rewrite_prefix", r.name))?;
+                }
+                Ok(Route {
+                    name: Some(r.name.clone()),
+                    hosts: r.hosts.iter().map(|h| h.to_ascii_lowercase()).collect(),
+                    prefix: r.path_prefix.trim_end_matches('/').to_string(),
+                    rewrite,
+                    pool,
+                })
+            })
+            .collect::<Result<_>>()
+            .map(Self)
+    }
+
+    /// Everything to `upstream`, as when no routes are configured
+    pub fn single(upstream: http::Uri, metrics: &PoolMetrics) -> Self {
+        let pool = Pool::new("default", vec![upstream], Balance::RoundRobin, OutlierDetection::default(), None, metrics.clone());
+        Self(vec![Route { name: None, hosts: Vec::new(), prefix: String::new(), rewrite: None, pool: Arc::new(pool) }])
+    }
+
+    pub fn pools(&self) -> impl Iterator<Item = &Pool> {
+        self.0.iter().map(|r| r.pool.as_ref())
+    }
+
+    /// The most specific route for `host` (its port ignored) and `path`, with the part of `path`
+    /// below the route's prefix: a listed host beats any host, then the longest prefix wins, then
+    /// the first route
+    pub fn find<'a>(&self, host: &str, path: &'a str) -> Option<(&Route, &'a str)> {
+        let host = host.parse::<http::uri::Authority>().map_or_else(|_| host.to_ascii_lowercase(), |a| a.host().to_ascii_lowercase());
+        self.0
+            .iter()
+            .enumerate()
+            .filter(|(_, r)| r.hosts.is_empty() || r.hosts.contains(&host))
+            .filter_map(|(i, r)| {
+                // whole segments only: `/wiki` serves `/wiki` and `/wiki/x`, not `/wikis`
+                let rest = path.strip_prefix(r.prefix.as_str())?;
+                (rest.is_empty() || rest.starts_with('/')).then_some((i, r, rest))
+            })
+            .max_by_key(|(i, r, _)| (!r.hosts.is_empty(), r.prefix.len(), std::cmp::Reverse(*i)))
+            .map(|(_, r, rest)| (r, rest))
+    }
+}
@@ -1,18 +1,53 @@
 //! Integration tests for the HTTP module.
 //!
 //! These tests use a stub PDP service over a Unix domain socket and a tiny in-process upstream to
-//! verify that the HTTP reverse proxy calls the PDP, injects headers, and forwards requests.
+//! verify that the HTTP reverse proxy calls the PDP, injects headers, and forwards requests, and
+//! that denials become a login redirect, 401 or 403 depending on the PDP outcome and the client,
+//! that clients cannot pass spoofed identity or forwarding headers to the upstream, and that
+//! upgraded connections are relayed until they go idle or their decision expires.
 
 use appgate_ipc::pdp::{
-    p_d_p_server::{Pdp, PdpServer},
-    DecisionRequest, DecisionResponse,
+    pdp_server::{Pdp, PdpServer},
+    DecisionRequest, DecisionResponse, Outcome,
 };
 use appgate_ipc::uds_server;
-use axum::{routing::get, Router};
-use std::{collections::HashMap, net::SocketAddr, time::Duration};
-use tokio::{task, time::sleep};
+use axum::{body::Body, http::HeaderMap, routing::{any, get}, Router};
+use http_body_util::BodyExt;
+use hyper_util::{
+    client::legacy::{connect::HttpConnector, Client},
+    rt::{TokioExecutor, TokioIo},
+};
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    task,
+    time::{sleep, timeout},
+};
 use tonic::{Request as TRequest, Response as TResponse, Status};
 
+/// Kills the spawned module when dropped, so a failed assertion doesn't leak it (and its port).
+struct Module(std::process::Child);
+
+impl Drop for Module {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+/// A plain HTTP/1 client for the module and its metrics listener
+fn client() -> Client<HttpConnector, Body> {
+    Client::builder(TokioExecutor::new()).build_http()
+}
+
 /// Stub PDP server that always allows and injects a demo subject header.
 struct AllowAll;
 
@@ -27,6 +62,8 @@ impl Pdp for AllowAll {
             claims: HashMap::new(),
             inject,
             reason: "allow".into(),
+            outcome: Outcome::Allow.into(),
+            login_url: String::new(),
         }))
     }
 }
@@ -49,34 +86,800 @@ async fn proxies_when_pdp_allows() {
     let upstream = Router::new().route("/", get(ok));
     let up_addr: SocketAddr = "127.0.0.1:38080".parse().unwrap();
     task::spawn(async move {
-        axum::Server::bind(&up_addr)
-            .serve(upstream.into_make_service())
-            .await
-            .unwrap();
+        axum::serve(tokio::net::TcpListener::bind(up_addr).await.unwrap(), upstream).await.unwrap();
     });
 
     // 3) Spawn the HTTP module binary as a child process
     let http_bin = env!("CARGO_BIN_EXE_appgate-mod-http");
-    // note: spawn instead of status() so we don't block; we'll kill it later
-    let mut child = std::process::Command::new(http_bin)
-        .args(&["--bind", "127.0.0.1:38081", "--pdp-uds", uds, "--upstream", "http://127.0.0.1:38080"])
-        .spawn()
-        .expect("spawn http module");
+    // note: spawn instead of status() so we don't block; it is killed when `_module` drops
+    let _module = Module(
+        std::process::Command::new(http_bin)
+            .args(["--bind", "127.0.0.1:38081", "--pdp-uds", uds, "--upstream", "http://127.0.0.1:38080"])
+            .spawn()
+            .expect("spawn http module"),
+    );
 
     // give processes time to start
     sleep(Duration::from_millis(500)).await;
 
     // 4) Send a request to the HTTP module and expect success
-    let client = hyper::Client::new();
+    let client = client();
     let req = hyper::Request::builder()
         .uri("http://127.0.0.1:38081/")
-        .body(hyper::Body::empty())
+        .body(Body::empty())
         .unwrap();
     let resp = client.request(req).await.expect("send request");
     assert!(resp.status().is_success(), "unexpected status: {}", resp.status());
-    let bytes = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+    let bytes = resp.into_body().collect().await.unwrap().to_bytes();
     assert_eq!(&bytes[..], b"OK");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn strips_spoofed_headers_before_injecting() {
+    let uds = "/tmp/appgate-test-pdp-hygiene.sock";
+    let _ = std::fs::remove_file(uds);
+    task::spawn(async move {
+        uds_server(PdpServer::new(AllowAll), uds).await.unwrap();
+    });
+
+    // upstream echoes the request headers it received, one `name: value` per line
+    async fn echo(headers: HeaderMap) -> String {
+        let mut lines: Vec<String> = headers.iter().map(|(k, v)| format!("{k}: {}", v.to_str().unwrap())).collect();
+        lines.sort();
+        lines.join("\n")
+    }
+    let upstream = Router::new().route("/", any(echo));
+    let up_addr: SocketAddr = "127.0.0.1:38084".parse().unwrap();
+    task::spawn(async move {
+        axum::serve(tokio::net::TcpListener::bind(up_addr).await.unwrap(), upstream).await.unwrap();
+    });
+
+    let http_bin = env!("CARGO_BIN_EXE_appgate-mod-http");
+    let _module = Module(
+        std::process::Command::new(http_bin)
+            .args(["--bind", "127.0.0.1:38085", "--pdp-uds", uds, "--upstream", "http://127.0.0.1:38084"])
+            .args(["--public-scheme", "https"])
+            .spawn()
+            .expect("spawn http module"),
+    );
+    sleep(Duration::from_millis(500)).await;
+
+    let req = hyper::Request::builder()
+        .uri("http://127.0.0.1:38085/")
+        .header("x-user-sub", "mallory")
+        .header("x-role", "admin")
+        .header("x-appgate-groups", "foundry-admin")
+        .header("authorization", "Bearer stolen")
+        .header("cookie", "theme=dark; appg_sess=secret")
+        .header("x-forwarded-for", "203.0.113.9")
+        .header("x-forwarded-host", "evil.test")
+        .header("forwarded", "for=203.0.113.9")
+        .header("connection", "x-private")
+        .header("x-private", "hop")
+        .header("x-trace", "kept")
+        .body(Body::empty())
+        .unwrap();
+    let resp = client().request(req).await.expect("send request");
+    assert!(resp.status().is_success(), "unexpected status: {}", resp.status());
+    let bytes = resp.into_body().collect().await.unwrap().to_bytes();
+    let seen: HashMap<String, String> = std::str::from_utf8(&bytes).unwrap()
+        .lines()
+        .filter_map(|l| l.split_once(": "))
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+
+    assert_eq!(seen.get("x-user-sub").map(String::as_str), Some("demo"));
+    assert_eq!(seen.get("cookie").map(String::as_str), Some("theme=dark"));
+    assert_eq!(seen.get("x-forwarded-for").map(String::as_str), Some("127.0.0.1"));
+    assert_eq!(seen.get("x-forwarded-proto").map(String::as_str), Some("https"));
+    assert_eq!(seen.get("x-forwarded-host").map(String::as_str), Some("127.0.0.1:38085"));
+    assert_eq!(seen.get("x-trace").map(String::as_str), Some("kept"));
+    for gone in ["x-role", "x-appgate-groups", "authorization", "forwarded", "x-private"] {
+        assert!(!seen.contains_key(gone), "{gone} reached the upstream: {seen:?}");
+    }
+}
+
+/// Stub PDP: no cookie is unauthenticated, `member` is allowed, anyone else is forbidden.
+struct Gate;
+
+#[tonic::async_trait]
+impl Pdp for Gate {
+    async fn decide(&self, req: TRequest<DecisionRequest>) -> Result<TResponse<DecisionResponse>, Status> {
+        let req = req.into_inner();
+        let kv = req.attributes.map(|a| a.kv).unwrap_or_default();
+        let url = kv.get("url").cloned().unwrap_or_default();
+        let resp = match req.session_token.as_str() {
+            "" => DecisionResponse {
+                reason: "unauthenticated: no session".into(),
+                outcome: Outcome::Unauthenticated.into(),
+                login_url: format!("http://auth.test/oidc/login?rt={url}"),
+                ..Default::default()
+            },
+            "member" => DecisionResponse { allow: true, outcome: Outcome::Allow.into(), ..Default::default() },
+            _ => DecisionResponse { reason: "missing group".into(), outcome: Outcome::Forbidden.into(), ..Default::default() },
+        };
+        Ok(TResponse::new(resp))
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn denials_map_to_login_redirect_401_or_403() {
+    // 1) Start stub PDP over UDS
+    let uds = "/tmp/appgate-test-pdp-gate.sock";
+    let _ = std::fs::remove_file(uds);
+    task::spawn(async move {
+        uds_server(PdpServer::new(Gate), uds).await.unwrap();
+    });
+
+    // 2) Start tiny upstream (returns "OK")
+    async fn ok() -> &'static str {
+        "OK"
+    }
+    let upstream = Router::new().route("/page", any(ok));
+    let up_addr: SocketAddr = "127.0.0.1:38082".parse().unwrap();
+    task::spawn(async move {
+        axum::serve(tokio::net::TcpListener::bind(up_addr).await.unwrap(), upstream).await.unwrap();
+    });
+
+    // 3) Spawn the HTTP module with an API prefix
+    let http_bin = env!("CARGO_BIN_EXE_appgate-mod-http");
+    let _module = Module(
+        std::process::Command::new(http_bin)
+            .args(["--bind", "127.0.0.1:38083", "--pdp-uds", uds, "--upstream", "http://127.0.0.1:38082"])
+            .args(["--public-scheme", "https", "--api-prefix", "/api/"])
+            .spawn()
+            .expect("spawn http module"),
+    );
+    sleep(Duration::from_millis(500)).await;
+
+    // 4) Exercise each kind of client
+    let client = client();
+    let get = |path: &str, accept: &str, cookie: Option<&str>| {
+        let mut req = hyper::Request::builder()
+            .uri(format!("http://127.0.0.1:38083{path}"))
+            .header("accept", accept);
+        if let Some(c) = cookie {
+            req = req.header("cookie", format!("appg_sess={c}"));
+        }
+        client.request(req.body(Body::empty()).unwrap())
+    };
+
+    let resp = get("/page?x=1", "text/html,application/xhtml+xml", None).await.unwrap();
+    assert_eq!(resp.status(), 302);
+    assert_eq!(resp.headers()["location"], "http://auth.test/oidc/login?rt=https://127.0.0.1:38083/page?x=1");
+
+    let resp = get("/page", "application/json", None).await.unwrap();
+    assert_eq!(resp.status(), 401);
+
+    let resp = get("/api/items", "text/html", None).await.unwrap();
+    assert_eq!(resp.status(), 401);
+
+    let resp = get("/page", "text/html", Some("stranger")).await.unwrap();
+    assert_eq!(resp.status(), 403);
+
+    let resp = get("/page", "text/html", Some("member")).await.unwrap();
+    assert_eq!(resp.status(), 200);
+
+    // only a cookie named exactly `appg_sess` carries the session
+    let smuggled = hyper::Request::builder()
+        .uri("http://127.0.0.1:38083/page")
+        .header("accept", "application/json")
+        .header("cookie", "appg_sessfoo=member; appg_sess_x=member")
+        .body(Body::empty())
+        .unwrap();
+    assert_eq!(client.request(smuggled).await.unwrap().status(), 401);
+}
+
+/// Stub PDP that allows only the request attributes its test sends
+struct Inspector;
+
+#[tonic::async_trait]
+impl Pdp for Inspector {
+    async fn decide(&self, req: TRequest<DecisionRequest>) -> Result<TResponse<DecisionResponse>, Status> {
+        let req = req.into_inner();
+        let kv = req.attributes.map(|a| a.kv).unwrap_or_default();
+        let inspected = kv.get("method").map(String::as_str) == Some("POST")
+            && kv.get("host").map(String::as_str) == Some("127.0.0.1:38102")
+            && kv.get("path").map(String::as_str) == Some("/page")
+            && kv.get("query.q").map(String::as_str) == Some("a b")
+            && kv.get("header.x-requested-with").map(String::as_str) == Some("XMLHttpRequest")
+            && !kv.contains_key("header.cookie")
+            && req.peer == "127.0.0.1";
+        let resp = match inspected {
+            true => DecisionResponse { allow: true, outcome: Outcome::Allow.into(), ..Default::default() },
+            false => DecisionResponse { reason: "unexpected attributes".into(), outcome: Outcome::Forbidden.into(), ..Default::default() },
+        };
+        Ok(TResponse::new(resp))
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn request_attributes_reach_the_pdp() {
+    let uds = "/tmp/appgate-test-pdp-inspect.sock";
+    let _ = std::fs::remove_file(uds);
+    task::spawn(async move {
+        uds_server(PdpServer::new(Inspector), uds).await.unwrap();
+    });
+
+    async fn ok() -> &'static str {
+        "OK"
+    }
+    let upstream = Router::new().route("/page", any(ok));
+    let up_addr: SocketAddr = "127.0.0.1:38101".parse().unwrap();
+    task::spawn(async move {
+        axum::serve(tokio::net::TcpListener::bind(up_addr).await.unwrap(), upstream).await.unwrap();
+    });
+
+    let http_bin = env!("CARGO_BIN_EXE_appgate-mod-http");
+    let _module = Module(
+        std::process::Command::new(http_bin)
+            .args(["--bind", "127.0.0.1:38102", "--pdp-uds", uds, "--upstream", "http://127.0.0.1:38101"])
+            .spawn()
+            .expect("spawn http module"),
+    );
+    sleep(Duration::from_millis(500)).await;
+
+    // method, host, path, decoded query and selected headers reach the PDP as attributes; the
+    // cookie does not
+    let client = client();
+    let inspect = |method: &str| {
+        let req = hyper::Request::builder()
+            .method(method)
+            .uri("http://127.0.0.1:38102/page?q=a%20b&q=ignored")
+            .header("cookie", "appg_sess=inspector")
+            .header("x-requested-with", "XMLHttpRequest")
+            .body(Body::empty())
+            .unwrap();
+        client.request(req)
+    };
+    assert_eq!(inspect("POST").await.unwrap().status(), 200);
+    assert_eq!(inspect("GET").await.unwrap().status(), 403);
+}
+
+/// Stub PDP that allows only requests from the VPN client address 203.0.113.9
+struct Vpn;
+
+#[tonic::async_trait]
+impl Pdp for Vpn {
+    async fn decide(&self, req: TRequest<DecisionRequest>) -> Result<TResponse<DecisionResponse>, Status> {
+        let resp = match req.into_inner().peer.as_str() {
+            "203.0.113.9" => DecisionResponse { allow: true, outcome: Outcome::Allow.into(), ..Default::default() },
+            _ => DecisionResponse { reason: "not on the VPN".into(), outcome: Outcome::Forbidden.into(), ..Default::default() },
+        };
+        Ok(TResponse::new(resp))
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn client_address_comes_from_trusted_proxies_only() {
+    let uds = "/tmp/appgate-test-pdp-vpn.sock";
+    let _ = std::fs::remove_file(uds);
+    task::spawn(async move {
+        uds_server(PdpServer::new(Vpn), uds).await.unwrap();
+    });
+
+    async fn ok() -> &'static str {
+        "OK"
+    }
+    let upstream = Router::new().route("/page", any(ok));
+    let up_addr: SocketAddr = "127.0.0.1:38103".parse().unwrap();
+    task::spawn(async move {
+        axum::serve(tokio::net::TcpListener::bind(up_addr).await.unwrap(), upstream).await.unwrap();
+    });
+
+    // the test client (127.0.0.1) is a trusted proxy of the first module but not of the second
+    let http_bin = env!("CARGO_BIN_EXE_appgate-mod-http");
+    let spawn = |bind: &str, trusted: &str| {
+        Module(
+            std::process::Command::new(http_bin)
+                .args(["--bind", bind, "--pdp-uds", uds, "--upstream", "http://127.0.0.1:38103"])
+                .args(["--trusted-proxy", trusted])
+                .spawn()
+                .expect("spawn http module"),
+        )
+    };
+    let _trusting = spawn("127.0.0.1:38104", "127.0.0.1/32");
+    let _untrusting = spawn("127.0.0.1:38105", "10.0.0.0/8");
+    sleep(Duration::from_millis(500)).await;
+
+    let client = client();
+    let forwarded = |port: u16, xff: Option<&str>| {
+        let mut req = hyper::Request::builder().uri(format!("http://127.0.0.1:{port}/page"));
+        if let Some(xff) = xff {
+            req = req.header("x-forwarded-for", xff);
+        }
+        client.request(req.body(Body::empty()).unwrap())
+    };
 
-    // terminate the HTTP module process
-    let _ = child.kill();
-}
\ No newline at end of file
+    // past trusted hops, the right-most untrusted entry is the client
+    assert_eq!(forwarded(38104, Some("203.0.113.9")).await.unwrap().status(), 200);
+    assert_eq!(forwarded(38104, Some("203.0.113.9, 127.0.0.1")).await.unwrap().status(), 200);
+    assert_eq!(forwarded(38104, Some("203.0.113.9, 198.51.100.7")).await.unwrap().status(), 403);
+    assert_eq!(forwarded(38104, None).await.unwrap().status(), 403);
+
+    // an untrusted peer's X-Forwarded-For is ignored
+    assert_eq!(forwarded(38105, Some("203.0.113.9")).await.unwrap().status(), 403);
+}
+
+/// Stub PDP for upgrades: `brief` sessions expire in two seconds, `stranger` is forbidden and
+/// anyone else is allowed until 2099.
+struct Expiring;
+
+#[tonic::async_trait]
+impl Pdp for Expiring {
+    async fn decide(&self, req: TRequest<DecisionRequest>) -> Result<TResponse<DecisionResponse>, Status> {
+        let expiry = match req.into_inner().session_token.as_str() {
+            "stranger" => {
+                return Ok(TResponse::new(DecisionResponse { outcome: Outcome::Forbidden.into(), ..Default::default() }));
+            }
+            "brief" => (chrono::Utc::now() + chrono::Duration::seconds(2)).to_rfc3339(),
+            _ => "2099-01-01T00:00:00Z".to_string(),
+        };
+        Ok(TResponse::new(DecisionResponse { allow: true, expiry, outcome: Outcome::Allow.into(), ..Default::default() }))
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn upgrades_are_spliced_until_idle_or_expiry() {
+    let uds = "/tmp/appgate-test-pdp-upgrade.sock";
+    let _ = std::fs::remove_file(uds);
+    task::spawn(async move {
+        uds_server(PdpServer::new(Expiring), uds).await.unwrap();
+    });
+
+    // upstream switches to an `echo` protocol and echoes bytes back
+    async fn switch(mut req: hyper::Request<Body>) -> hyper::Response<Body> {
+        assert_eq!(req.headers()["upgrade"], "echo");
+        let on = hyper::upgrade::on(&mut req);
+        task::spawn(async move {
+            let mut io = TokioIo::new(on.await.unwrap());
+            let mut buf = [0u8; 64];
+            while let Ok(n @ 1..) = io.read(&mut buf).await {
+                if io.write_all(&buf[..n]).await.is_err() {
+                    break;
+                }
+            }
+        });
+        hyper::Response::builder()
+            .status(101)
+            .header("connection", "upgrade")
+            .header("upgrade", "echo")
+            .body(Body::empty())
+            .unwrap()
+    }
+    let upstream = Router::new().route("/socket", any(switch));
+    let up_addr: SocketAddr = "127.0.0.1:38086".parse().unwrap();
+    task::spawn(async move {
+        axum::serve(tokio::net::TcpListener::bind(up_addr).await.unwrap(), upstream).await.unwrap();
+    });
+
+    let http_bin = env!("CARGO_BIN_EXE_appgate-mod-http");
+    let _module = Module(
+        std::process::Command::new(http_bin)
+            .args(["--bind", "127.0.0.1:38087", "--pdp-uds", uds, "--upstream", "http://127.0.0.1:38086"])
+            .args(["--upgrade-idle-timeout", "1"])
+            .spawn()
+            .expect("spawn http module"),
+    );
+    sleep(Duration::from_millis(500)).await;
+
+    let client = client();
+    let handshake = |session: &str| {
+        let req = hyper::Request::builder()
+            .uri("http://127.0.0.1:38087/socket")
+            .header("connection", "upgrade")
+            .header("upgrade", "echo")
+            .header("cookie", format!("appg_sess={session}"))
+            .body(Body::empty())
+            .unwrap();
+        client.request(req)
+    };
+
+    // the handshake is authorized like any request
+    assert_eq!(handshake("stranger").await.unwrap().status(), 403);
+
+    // allowed: bytes flow both ways, then the idle timeout closes the connection
+    let resp = handshake("member").await.unwrap();
+    assert_eq!(resp.status(), 101);
+    let mut io = TokioIo::new(hyper::upgrade::on(resp).await.unwrap());
+    io.write_all(b"ping").await.unwrap();
+    let mut buf = [0u8; 4];
+    io.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"ping");
+    let closed = timeout(Duration::from_secs(3), io.read(&mut buf)).await.expect("idle connection closed");
+    assert!(matches!(closed, Ok(0) | Err(_)));
+
+    // a busy connection is still cut off when its decision expires
+    let started = std::time::Instant::now();
+    let resp = handshake("brief").await.unwrap();
+    assert_eq!(resp.status(), 101);
+    let mut io = TokioIo::new(hyper::upgrade::on(resp).await.unwrap());
+    let cut = timeout(Duration::from_secs(5), async {
+        loop {
+            if io.write_all(b"ping").await.is_err() || io.read_exact(&mut buf).await.is_err() {
+                break;
+            }
+            sleep(Duration::from_millis(200)).await;
+        }
+    })
+    .await;
+    assert!(cut.is_ok(), "connection outlived its decision");
+    assert!(started.elapsed() >= Duration::from_millis(1500), "cut off too early: {:?}", started.elapsed());
+}
+
+/// Stub PDP that allows the `foundry` and `wiki` routes and injects the resource it was asked about.
+struct Routed;
+
+#[tonic::async_trait]
+impl Pdp for Routed {
+    async fn decide(&self, req: TRequest<DecisionRequest>) -> Result<TResponse<DecisionResponse>, Status> {
+        let resource = req.into_inner().resource;
+        if !resource.starts_with("http://foundry/") && !resource.starts_with("http://wiki/") {
+            return Ok(TResponse::new(DecisionResponse { outcome: Outcome::Forbidden.into(), ..Default::default() }));
+        }
+        let inject = HashMap::from([("X-Appgate-Resource".to_string(), resource)]);
+        Ok(TResponse::new(DecisionResponse { allow: true, inject, outcome: Outcome::Allow.into(), ..Default::default() }))
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn routes_by_host_and_path_prefix() {
+    let uds = "/tmp/appgate-test-pdp-routes.sock";
+    let _ = std::fs::remove_file(uds);
+    task::spawn(async move {
+        uds_server(PdpServer::new(Routed), uds).await.unwrap();
+    });
+
+    // two upstreams answering `<name> <path and query> <resource the PDP saw>`
+    for (name, port) in [("foundry", 38088), ("wiki", 38089)] {
+        let upstream = Router::new().fallback(move |req: hyper::Request<Body>| async move {
+            let resource = req.headers()["x-appgate-resource"].to_str().unwrap().to_string();
+            format!("{name} {} {resource}", req.uri())
+        });
+        let addr: SocketAddr = ([127, 0, 0, 1], port).into();
+        task::spawn(async move {
+            axum::serve(tokio::net::TcpListener::bind(addr).await.unwrap(), upstream).await.unwrap();
+        });
+    }
+
+    let config = std::env::temp_dir().join(format!("appgate-mod-http-routes-{}.toml", std::process::id()));
+    std::fs::write(
+        &config,
+        r#"
+        [global]
+        run_dir = "/run/x"
+        log_level = "info"
+
+        [certs]
+        trust_store = "/etc/ca.pem"
+
+        [auth.oidc]
+        issuer = "https://kc/realms/main"
+        client_id = "x"
+        client_secret = "env:K"
+        redirect_uri = "https://app/oidc/callback"
+        cookie_name = "appg_sess"
+        cookie_domain = "example.com"
+        session_ttl_seconds = 3600
+
+        [[modules.http.routes]]
+        name = "foundry"
+        hosts = ["vtt.test"]
+        upstream = "http://127.0.0.1:38088"
+
+        [[modules.http.routes]]
+        name = "wiki"
+        path_prefix = "/wiki/"
+        rewrite_prefix = "/"
+        upstream = "http://127.0.0.1:38089"
+        "#,
+    )
+    .unwrap();
+
+    let http_bin = env!("CARGO_BIN_EXE_appgate-mod-http");
+    let _module = Module(
+        std::process::Command::new(http_bin)
+            .args(["--bind", "127.0.0.1:38090", "--pdp-uds", uds, "--config", config.to_str().unwrap()])
+            .spawn()
+            .expect("spawn http module"),
+    );
+    sleep(Duration::from_millis(500)).await;
+
+    let client = client();
+    let get = |host: &str, path: &str| {
+        let req = hyper::Request::builder()
+            .uri(format!("http://127.0.0.1:38090{path}"))
+            .header("host", host)
+            .body(Body::empty())
+            .unwrap();
+        let resp = client.request(req);
+        async move {
+            let resp = resp.await.unwrap();
+            let status = resp.status().as_u16();
+            let body = resp.into_body().collect().await.unwrap().to_bytes();
+            (status, String::from_utf8(body.to_vec()).unwrap())
+        }
+    };
+
+    // a listed host wins over a longer prefix for any host
+    assert_eq!(get("vtt.test:8080", "/game").await, (200, "foundry /game http://foundry/game".into()));
+    assert_eq!(get("VTT.test", "/wiki/page").await, (200, "foundry /wiki/page http://foundry/wiki/page".into()));
+    // the prefix is rewritten before forwarding, and the resource is the route's own path
+    assert_eq!(get("other.test", "/wiki/page?x=1").await, (200, "wiki /page?x=1 http://wiki/page".into()));
+    assert_eq!(get("other.test", "/wiki").await, (200, "wiki / http://wiki/".into()));
+    // prefixes match whole segments, and unrouted requests never reach the PDP
+    assert_eq!(get("other.test", "/wikis").await.0, 404);
+    assert_eq!(get("other.test", "/").await.0, 404);
+
+    std::fs::remove_file(&config).unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn balances_over_healthy_upstreams() {
+    let uds = "/tmp/appgate-test-pdp-pool.sock";
+    let _ = std::fs::remove_file(uds);
+    task::spawn(async move {
+        uds_server(PdpServer::new(AllowAll), uds).await.unwrap();
+    });
+
+    // two upstreams answering their name; `sick` fails health checks, `broken` fails requests
+    let mut flags = Vec::new();
+    for (name, port) in [("a", 38091), ("b", 38092)] {
+        let (sick, broken) = (Arc::new(AtomicBool::new(false)), Arc::new(AtomicBool::new(false)));
+        flags.push((sick.clone(), broken.clone()));
+        let upstream = Router::new()
+            .route("/healthz", get(move || async move {
+                match sick.load(Ordering::Relaxed) {
+                    true => (hyper::StatusCode::SERVICE_UNAVAILABLE, "sick"),
+                    false => (hyper::StatusCode::OK, "ok"),
+                }
+            }))
+            .fallback(move || async move {
+                match broken.load(Ordering::Relaxed) {
+                    true => (hyper::StatusCode::BAD_GATEWAY, "broken"),
+                    false => (hyper::StatusCode::OK, name),
+                }
+            });
+        let addr: SocketAddr = ([127, 0, 0, 1], port).into();
+        task::spawn(async move {
+            axum::serve(tokio::net::TcpListener::bind(addr).await.unwrap(), upstream).await.unwrap();
+        });
+    }
+    let [(_, a_broken), (b_sick, b_broken)] = &flags[..] else { unreachable!() };
+    assert!(!a_broken.load(Ordering::Relaxed));
+
+    let config = std::env::temp_dir().join(format!("appgate-mod-http-pool-{}.toml", std::process::id()));
+    std::fs::write(
+        &config,
+        r#"
+        [global]
+        run_dir = "/run/x"
+        log_level = "info"
+
+        [certs]
+        trust_store = "/etc/ca.pem"
+
+        [auth.oidc]
+        issuer = "https://kc/realms/main"
+        client_id = "x"
+        client_secret = "env:K"
+        redirect_uri = "https://app/oidc/callback"
+        cookie_name = "appg_sess"
+        cookie_domain = "example.com"
+        session_ttl_seconds = 3600
+
+        [modules.http]
+        metrics_bind = "127.0.0.1:38094"
+
+        [[modules.http.routes]]
+        name = "pool"
+        hosts = ["pool.test"]
+        upstreams = ["http://127.0.0.1:38091", "http://127.0.0.1:38092"]
+        health_check = { path = "/healthz", interval_seconds = 1, unhealthy_threshold = 1, healthy_threshold = 1 }
+        outlier_detection = { consecutive_failures = 2, ejection_seconds = 60 }
+
+        [[modules.http.routes]]
+        name = "sticky"
+        hosts = ["sticky.test"]
+        upstreams = ["http://127.0.0.1:38091", "http://127.0.0.1:38092"]
+        balance = "session_hash"
+
+        [[modules.http.routes]]
+        name = "gone"
+        hosts = ["gone.test"]
+        upstream = "http://127.0.0.1:38093"
+        health_check = { path = "/healthz", interval_seconds = 1, unhealthy_threshold = 1 }
+        "#,
+    )
+    .unwrap();
+
+    let http_bin = env!("CARGO_BIN_EXE_appgate-mod-http");
+    let _module = Module(
+        std::process::Command::new(http_bin)
+            .args(["--bind", "127.0.0.1:38095", "--pdp-uds", uds, "--config", config.to_str().unwrap()])
+            .spawn()
+            .expect("spawn http module"),
+    );
+    sleep(Duration::from_millis(1500)).await;
+
+    let client = client();
+    let fetch = |uri: String, host: &str, session: &str| {
+        let req = hyper::Request::builder()
+            .uri(uri)
+            .header("host", host)
+            .header("cookie", format!("appg_sess={session}"))
+            .body(Body::empty())
+            .unwrap();
+        let resp = client.request(req);
+        async move {
+            let resp = resp.await.unwrap();
+            let status = resp.status().as_u16();
+            let body = resp.into_body().collect().await.unwrap().to_bytes();
+            (status, String::from_utf8(body.to_vec()).unwrap())
+        }
+    };
+    let get = |host: &'static str, session: &'static str| fetch("http://127.0.0.1:38095/".into(), host, session);
+    let bodies = |n: usize, host: &'static str| async move {
+        let mut out = Vec::new();
+        for _ in 0..n {
+            out.push(get(host, "s").await.1);
+        }
+        out
+    };
+
+    // round robin alternates
+    let mut seen = bodies(4, "pool.test").await;
+    seen.sort();
+    assert_eq!(seen, ["a", "a", "b", "b"]);
+
+    // an upstream failing health checks gets nothing until it recovers
+    b_sick.store(true, Ordering::Relaxed);
+    sleep(Duration::from_millis(1500)).await;
+    assert_eq!(bodies(4, "pool.test").await, ["a"; 4]);
+    let metrics = fetch("http://127.0.0.1:38094/metrics".into(), "127.0.0.1", "").await.1;
+    assert!(metrics.contains(r#"appgate_http_upstream_up{endpoint="http://127.0.0.1:38092/",route="pool"} 0"#), "{metrics}");
+    b_sick.store(false, Ordering::Relaxed);
+    sleep(Duration::from_millis(1500)).await;
+    assert!(bodies(4, "pool.test").await.contains(&"b".to_string()));
+
+    // an upstream failing requests is ejected after two in a row
+    b_broken.store(true, Ordering::Relaxed);
+    let statuses: Vec<u16> = {
+        let mut out = Vec::new();
+        for _ in 0..8 {
+            out.push(get("pool.test", "s").await.0);
+        }
+        out
+    };
+    assert_eq!(statuses.iter().filter(|s| **s == 502).count(), 2, "{statuses:?}");
+    assert_eq!(&statuses[5..], [200; 3]);
+    let metrics = fetch("http://127.0.0.1:38094/metrics".into(), "127.0.0.1", "").await.1;
+    assert!(metrics.contains(r#"appgate_http_upstream_ejections_total{endpoint="http://127.0.0.1:38092/",route="pool"} 1"#), "{metrics}");
+    b_broken.store(false, Ordering::Relaxed);
+
+    // a session sticks to one upstream
+    for session in ["s1", "s2", "s3"] {
+        let first = get("sticky.test", session).await.1;
+        for _ in 0..3 {
+            assert_eq!(get("sticky.test", session).await.1, first);
+        }
+    }
+
+    // a route without a healthy upstream answers 503 and makes the module unready
+    assert_eq!(get("gone.test", "s").await.0, 503);
+    let (status, body) = fetch("http://127.0.0.1:38094/readyz".into(), "127.0.0.1", "").await;
+    assert_eq!((status, body.as_str()), (503, "no healthy upstream: gone"));
+
+    std::fs::remove_file(&config).unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn circuit_breaker_opens_on_upstream_errors() {
+    let uds = "/tmp/appgate-test-pdp-breaker.sock";
+    let _ = std::fs::remove_file(uds);
+    task::spawn(async move {
+        uds_server(PdpServer::new(AllowAll), uds).await.unwrap();
+    });
+
+    // upstream counting the requests it gets, failing with 500 while `failing` is set
+    let failing = Arc::new(AtomicBool::new(true));
+    let hits = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let upstream = Router::new().fallback({
+        let (failing, hits) = (failing.clone(), hits.clone());
+        move || async move {
+            hits.fetch_add(1, Ordering::Relaxed);
+            match failing.load(Ordering::Relaxed) {
+                true => (hyper::StatusCode::INTERNAL_SERVER_ERROR, "boom"),
+                false => (hyper::StatusCode::OK, "OK"),
+            }
+        }
+    });
+    let up_addr: SocketAddr = "127.0.0.1:38096".parse().unwrap();
+    task::spawn(async move {
+        axum::serve(tokio::net::TcpListener::bind(up_addr).await.unwrap(), upstream).await.unwrap();
+    });
+
+    let config = std::env::temp_dir().join(format!("appgate-mod-http-breaker-{}.toml", std::process::id()));
+    std::fs::write(
+        &config,
+        r#"
+        [global]
+        run_dir = "/run/x"
+        log_level = "info"
+
+        [certs]
+        trust_store = "/etc/ca.pem"
+
+        [auth.oidc]
+        issuer = "https://kc/realms/main"
+        client_id = "x"
+        client_secret = "env:K"
+        redirect_uri = "https://app/oidc/callback"
+        cookie_name = "appg_sess"
+        cookie_domain = "example.com"
+        session_ttl_seconds = 3600
+
+        [modules.http]
+        metrics_bind = "127.0.0.1:38098"
+
+        [[modules.http.routes]]
+        name = "fragile"
+        upstream = "http://127.0.0.1:38096"
+        circuit_breaker = { error_ratio = 0.5, window_seconds = 10, min_requests = 4, open_seconds = 1, probe_requests = 2 }
+        "#,
+    )
+    .unwrap();
+
+    let http_bin = env!("CARGO_BIN_EXE_appgate-mod-http");
+    let _module = Module(
+        std::process::Command::new(http_bin)
+            .args(["--bind", "127.0.0.1:38097", "--pdp-uds", uds, "--config", config.to_str().unwrap()])
+            .spawn()
+            .expect("spawn http module"),
+    );
+    sleep(Duration::from_millis(500)).await;
+
+    let client = client();
+    let status = |uri: &'static str| {
+        let resp = client.get(uri.parse().unwrap());
+        async move { resp.await.unwrap().status().as_u16() }
+    };
+    let get = || status("http://127.0.0.1:38097/");
+    let metrics = || async {
+        let resp = client.get("http://127.0.0.1:38098/metrics".parse().unwrap()).await.unwrap();
+        String::from_utf8(resp.into_body().collect().await.unwrap().to_bytes().to_vec()).unwrap()
+    };
+    let circuit = r#"appgate_http_upstream_circuit_state{endpoint="http://127.0.0.1:38096/",route="fragile"}"#;
+
+    // errors pass through until the breaker trips, then requests fail fast without reaching it
+    for _ in 0..4 {
+        assert_eq!(get().await, 500);
+    }
+    assert_eq!(get().await, 503);
+    assert_eq!(hits.load(Ordering::Relaxed), 4);
+    assert!(metrics().await.contains(&format!("{circuit} 1")));
+    // an open breaker alone does not make the module unready
+    assert_eq!(status("http://127.0.0.1:38098/readyz").await, 200);
+
+    // after open_seconds a failed probe reopens it
+    sleep(Duration::from_millis(1100)).await;
+    assert_eq!(get().await, 500);
+    assert_eq!(get().await, 503);
+    assert_eq!(hits.load(Ordering::Relaxed), 5);
+
+    // and successful probes close it
+    failing.store(false, Ordering::Relaxed);
+    sleep(Duration::from_millis(1100)).await;
+    for _ in 0..4 {
+        assert_eq!(get().await, 200);
+    }
+    let metrics = metrics().await;
+    assert!(metrics.contains(&format!("{circuit} 0")), "{metrics}");
+    for (to, n) in [("open", 2), ("half_open", 2), ("closed", 1)] {
+        let line = format!(r#"appgate_http_upstream_circuit_transitions_total{{endpoint="http://127.0.0.1:38096/",route="fragile",to="{to}"}} {n}"#);
+        assert!(metrics.contains(&line), "{line} in {metrics}");
+    }
+    assert_eq!(status("http://127.0.0.1:38098/readyz").await, 200);
+
+    std::fs::remove_file(&config).unwrap();
+}
@@ -0,0 +1,118 @@
+//! End-to-end authentication test: an OIDC login followed by a request through the HTTP module.
+//!
+//! The stand-in IdP and appgate-auth's browser endpoints come from the appgate-auth test helpers;
+//! appgate-auth's PDP serves the module over a Unix socket and the upstream echoes the request
+//! headers it receives, so the test sees exactly what the gateway forwarded.
+
+use appgate_auth::pdp::PdpSvc;
+use appgate_ipc::{pdp::pdp_server::PdpServer, uds_server};
+use arc_swap::ArcSwap;
+use axum::{http::HeaderMap, routing::any, Router};
+use reqwest::{header, redirect::Policy as RedirectPolicy, StatusCode};
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+use tokio::{task, time::sleep};
+
+#[path = "../../appgate-auth/tests/common/mod.rs"]
+mod common;
+
+/// Kills the spawned module when dropped, so a failed assertion doesn't leak it (and its port).
+struct Module(std::process::Child);
+
+impl Drop for Module {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+const POLICY: &str = r#"
+[[rules]]
+name = "foundry-players"
+protocol = "http"
+resource = "http://foundry/"
+require_groups = ["foundry-players"]
+
+[rules.inject]
+"X-User-Sub" = "{{claims.sub}}"
+"X-User-Email" = "{{claims.email}}"
+"X-User-Groups" = "{{groups|join(',')}}"
+"#;
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn e2e_oidc_login_and_reach_upstream() {
+    let idp = common::StandInIdp::start(&["foundry-players"]);
+    let gw = common::start_gateway(&idp, 300).await;
+
+    let policy: appgate_policy::Policy = toml::from_str(POLICY).unwrap();
+    let pdp = PdpSvc {
+        policy: Arc::new(ArcSwap::from_pointee(policy.into())),
+        sealer: gw.sealer.clone(),
+        store: gw.store.clone(),
+        login: Some(gw.links.clone()),
+    };
+    let uds = "/tmp/appgate-test-pdp-e2e.sock";
+    let _ = std::fs::remove_file(uds);
+    task::spawn(async move {
+        uds_server(PdpServer::new(pdp), uds).await.unwrap();
+    });
+
+    // upstream echoes the request headers it received, one `name: value` per line
+    async fn echo(headers: HeaderMap) -> String {
+        let mut lines: Vec<String> = headers.iter().map(|(k, v)| format!("{k}: {}", v.to_str().unwrap())).collect();
+        lines.sort();
+        lines.join("\n")
+    }
+    let upstream = Router::new().route("/*path", any(echo));
+    let up_addr: SocketAddr = "127.0.0.1:38099".parse().unwrap();
+    task::spawn(async move {
+        axum::serve(tokio::net::TcpListener::bind(up_addr).await.unwrap(), upstream).await.unwrap();
+    });
+
+    let http_bin = env!("CARGO_BIN_EXE_appgate-mod-http");
+    let _module = Module(
+        std::process::Command::new(http_bin)
+            .args(["--bind", "127.0.0.1:38100", "--pdp-uds", uds, "--upstream", "http://127.0.0.1:38099"])
+            .spawn()
+            .expect("spawn http module"),
+    );
+    sleep(Duration::from_millis(500)).await;
+
+    let http = reqwest::Client::builder().redirect(RedirectPolicy::none()).build().unwrap();
+    let game = "http://127.0.0.1:38100/game";
+
+    // without a session a browser is sent to log in
+    let resp = http.get(game).header(header::HOST, "foundry").header(header::ACCEPT, "text/html").send().await.unwrap();
+    assert_eq!(resp.status(), StatusCode::FOUND);
+    let location = resp.headers()[header::LOCATION].to_str().unwrap();
+    assert!(location.starts_with(&format!("{}/oidc/login?rt=", gw.base)), "{location}");
+
+    // after logging in the upstream sees the session's identity, not the client's claims to one
+    let cookie = common::login(&gw).await;
+    let resp = http
+        .get(game)
+        .header(header::HOST, "foundry")
+        .header(header::COOKIE, format!("theme=dark; appg_sess={cookie}"))
+        .header("X-User-Sub", "mallory")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let seen = resp.text().await.unwrap();
+    let seen: Vec<&str> = seen.lines().collect();
+    for expected in ["x-user-sub: alice-sub", "x-user-email: alice@example.com", "x-user-groups: foundry-players", "cookie: theme=dark"] {
+        assert!(seen.contains(&expected), "{expected:?} missing from {seen:?}");
+    }
+    assert!(!seen.iter().any(|l| l.contains("mallory") || l.contains("appg_sess")), "{seen:?}");
+
+    // a user outside the group is refused once logged in
+    idp.set_groups(&["wiki-editors"]);
+    let cookie = common::login(&gw).await;
+    let resp = http
+        .get(game)
+        .header(header::HOST, "foundry")
+        .header(header::COOKIE, format!("appg_sess={cookie}"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+}
@@ -0,0 +1,253 @@
+//! Cedar policies as a [`PolicyEngine`](crate::engine::PolicyEngine).
+//!
+//! Requests become Cedar entities in the `AppGate` namespace:
+//!
+//! * principal `AppGate::User::"<sub>"` (`"anonymous"` without a `sub` claim), a member of one
+//!   `AppGate::Group::"<name>"` per group, with the session's claims and a `groups` set as attributes
+//! * action `AppGate::Action::"<METHOD>"` (in `AppGate::Action::"http"`) for HTTP requests that
+//!   carry a method, otherwise `AppGate::Action::"<protocol>"`
+//! * resource `AppGate::Resource::"<normalised resource>"` with `scheme`, `host`, `port` and
+//!   `path` attributes for URLs
+//! * context: the module's request attributes (`context.method`, `context["header.accept"]`),
+//!   plus `protocol`, `now` (Unix seconds) and `peer` (an `ip`, when known)
+//!
+//! ```cedar
+//! @id("foundry-admin")
+//! @priority("10")
+//! @inject_X_Role("admin")
+//! permit (principal in AppGate::Group::"foundry-admin", action in AppGate::Action::"http", resource)
+//! when { resource.host == "foundry" };
+//! ```
+//!
+//! `@id` names the policy in decision reasons, `@inject_<header>` adds a header (underscores become
+//! dashes; values are [`template`]s) and `@priority` picks whose headers apply when several permit
+//! policies allow a request. Any satisfied `forbid` denies, and so does one that fails to evaluate.
+
+use anyhow::{anyhow, bail, Context as _, Result};
+use cedar_policy::{
+    Authorizer, Context as CedarContext, Effect as CedarEffect, Entities, Entity, EntityId, EntityTypeName, EntityUid,
+    PolicyId, PolicySet, Request, RestrictedExpression,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+};
+
+use crate::{
+    explain::{ConditionTrace, Explanation, RuleTrace},
+    resource::Resource,
+    template, Context, Decision, Effect,
+};
+
+/// One policy of the set, with what appgate reads from its annotations
+struct Entry {
+    id: PolicyId,
+    name: String,
+    effect: Effect,
+    priority: i32,
+    inject: HashMap<String, String>,
+    /// The policy alone, to tell whether it is satisfied when explaining
+    alone: PolicySet,
+}
+
+/// A parsed Cedar policy set
+pub struct CedarPolicy {
+    set: PolicySet,
+    /// In file order
+    entries: Vec<Entry>,
+    authorizer: Authorizer,
+}
+
+impl FromStr for CedarPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(src: &str) -> Result<Self> {
+        let set = PolicySet::from_str(src).map_err(|e| anyhow!("{e}"))?;
+        let mut entries = Vec::new();
+        for p in set.policies() {
+            let name = p.annotation("id").map_or_else(|| p.id().to_string(), str::to_string);
+            let priority = match p.annotation("priority") {
+                Some(v) => v.parse().with_context(|| format!("policy {name}: @priority must be an integer"))?,
+                None => 0,
+            };
+            let mut inject = HashMap::new();
+            for (key, value) in p.annotations() {
+                let Some(header) = key.strip_prefix("inject_") else { continue };
+                let header = header.replace('_', "-");
+                if !template::is_header_name(&header) {
+                    bail!("policy {name}: invalid header name {header:?}");
+                }
+                template::Template::parse(value).with_context(|| format!("policy {name}: header {header}"))?;
+                inject.insert(header, value.to_string());
+            }
+            let effect = match p.effect() {
+                CedarEffect::Permit => Effect::Allow,
+                CedarEffect::Forbid => Effect::Deny,
+            };
+            let mut alone = PolicySet::new();
+            alone.add(p.clone()).map_err(|e| anyhow!("{e}"))?;
+            entries.push(Entry { id: p.id().clone(), name, effect, priority, inject, alone });
+        }
+        // policies are numbered in file order
+        entries.sort_by_key(|e| e.id.to_string().trim_start_matches("policy").parse::<usize>().unwrap_or(usize::MAX));
+        let mut names = HashSet::new();
+        if let Some(dup) = entries.iter().find(|e| !names.insert(e.name.as_str())) {
+            bail!("policy {} is defined twice", dup.name);
+        }
+        Ok(Self { set, entries, authorizer: Authorizer::new() })
+    }
+}
+
+impl CedarPolicy {
+    pub fn load(path: &str) -> Result<Self> {
+        let src = std::fs::read_to_string(path).with_context(|| format!("reading {path}"))?;
+        src.parse().with_context(|| format!("parsing {path}"))
+    }
+
+    /// Policy names (`@id`, else Cedar's `policyN`) in file order
+    pub fn names(&self) -> Vec<String> {
+        self.entries.iter().map(|e| e.name.clone()).collect()
+    }
+
+    pub fn evaluate(&self, cx: &Context) -> Decision {
+        self.select(cx).0
+    }
+
+    /// [`CedarPolicy::evaluate`], with one trace per policy. Cedar does not say why a policy was
+    /// not satisfied, so those are reported as a failed `scope/when/unless`
+    pub fn explain(&self, cx: &Context) -> Explanation {
+        let (decision, decisive) = self.select(cx);
+        let rules = self
+            .entries
+            .iter()
+            .enumerate()
+            .map(|(i, e)| {
+                let satisfied = self
+                    .authorize(cx, &e.alone)
+                    .is_ok_and(|(reasons, _)| reasons.contains(&e.id));
+                let conditions = match satisfied {
+                    true => Vec::new(),
+                    false => vec![ConditionTrace { attribute: "scope/when/unless".into(), value: None, passed: false }],
+                };
+                RuleTrace {
+                    rule: e.name.clone(),
+                    effect: e.effect,
+                    priority: e.priority,
+                    protocol: true,
+                    resource: true,
+                    conditions,
+                    peer: true,
+                    schedule: true,
+                    groups: true,
+                    decisive: decisive == Some(i),
+                    source: None,
+                }
+            })
+            .collect();
+        Explanation { decision, rules }
+    }
+
+    fn select(&self, cx: &Context) -> (Decision, Option<usize>) {
+        let (reasons, errors) = match self.authorize(cx, &self.set) {
+            Ok(answer) => answer,
+            Err(e) => return (Decision::deny(format!("cedar: {e}")), None),
+        };
+        // Cedar skips policies that fail to evaluate: harmless for a permit, but a forbid that
+        // errors (e.g. on a missing attribute) must not let a permit through
+        if !errors.is_empty() {
+            for (i, e) in self.entries.iter().enumerate().filter(|(_, e)| e.effect == Effect::Deny) {
+                if let Ok((_, errors)) = self.authorize(cx, &e.alone) {
+                    if let Some(error) = errors.first() {
+                        return (Decision::deny(format!("cedar: {}: {error}", e.name)), Some(i));
+                    }
+                }
+            }
+        }
+        let satisfied = |effect| {
+            self.entries
+                .iter()
+                .enumerate()
+                .filter(|(_, e)| e.effect == effect && reasons.contains(&e.id))
+                // stable: file order among equal priorities
+                .max_by(|(ai, a), (bi, b)| a.priority.cmp(&b.priority).then(bi.cmp(ai)))
+        };
+        if let Some((i, e)) = satisfied(Effect::Deny) {
+            return (Decision::deny(format!("deny: {}", e.name)), Some(i));
+        }
+        match satisfied(Effect::Allow) {
+            Some((i, e)) => {
+                let inject = (!e.inject.is_empty()).then(|| e.inject.clone());
+                let d = Decision { allow: true, inject, reason: format!("policy: {}", e.name), until: None, source: None };
+                (d, Some(i))
+            }
+            None => (Decision::deny("default-deny"), None),
+        }
+    }
+
+    /// Ids of the satisfied policies of `set` that determined Cedar's answer, and the errors of
+    /// policies that could not be evaluated
+    fn authorize(&self, cx: &Context, set: &PolicySet) -> Result<(HashSet<PolicyId>, Vec<String>)> {
+        let (request, entities) = request(cx)?;
+        let response = self.authorizer.is_authorized(&request, set, &entities);
+        let diagnostics = response.diagnostics();
+        Ok((diagnostics.reason().cloned().collect(), diagnostics.errors().map(|e| e.to_string()).collect()))
+    }
+}
+
+fn uid(kind: &str, id: &str) -> EntityUid {
+    // the type names are fixed and entity ids are free-form, so neither parse can fail
+    let kind = EntityTypeName::from_str(kind).expect("valid entity type name");
+    EntityUid::from_type_name_and_id(kind, EntityId::from_str(id).expect("entity ids are free-form"))
+}
+
+fn string(s: &str) -> RestrictedExpression {
+    RestrictedExpression::new_string(s.to_string())
+}
+
+/// The Cedar request and entities for `cx`
+fn request(cx: &Context) -> Result<(Request, Entities)> {
+    let mut entities = Vec::new();
+
+    let groups: HashSet<EntityUid> = cx.groups.iter().map(|g| uid("AppGate::Group", g)).collect();
+    entities.extend(groups.iter().map(|g| Entity::new(g.clone(), HashMap::new(), HashSet::new())));
+    let principal = uid("AppGate::User", cx.claims.get("sub").map_or("anonymous", String::as_str));
+    let mut attrs: HashMap<String, RestrictedExpression> = cx.claims.iter().map(|(k, v)| (k.clone(), string(v))).collect();
+    attrs.insert("groups".into(), RestrictedExpression::new_set(cx.groups.iter().map(|g| string(g))));
+    entities.push(Entity::new(principal.clone(), attrs, groups));
+
+    let protocol = uid("AppGate::Action", cx.protocol);
+    let action = match cx.attributes.get("method") {
+        Some(m) if cx.protocol == "http" => {
+            let method = uid("AppGate::Action", &m.to_ascii_uppercase());
+            entities.push(Entity::new(method.clone(), HashMap::new(), HashSet::from([protocol.clone()])));
+            method
+        }
+        _ => protocol.clone(),
+    };
+    entities.push(Entity::new(protocol, HashMap::new(), HashSet::new()));
+
+    let parsed = Resource::parse(cx.resource);
+    let mut attrs = HashMap::new();
+    if let Resource::Url { scheme, host, port, path } = &parsed {
+        attrs.insert("scheme".into(), string(scheme));
+        attrs.insert("host".into(), string(host));
+        attrs.insert("path".into(), string(&format!("/{}", path.join("/"))));
+        if let Some(port) = port {
+            attrs.insert("port".into(), string(port));
+        }
+    }
+    let resource = uid("AppGate::Resource", &parsed.to_canonical());
+    entities.push(Entity::new(resource.clone(), attrs, HashSet::new()));
+
+    let mut context: HashMap<String, RestrictedExpression> = cx.attributes.iter().map(|(k, v)| (k.clone(), string(v))).collect();
+    context.insert("protocol".into(), string(cx.protocol));
+    context.insert("now".into(), RestrictedExpression::new_long(cx.now.timestamp()));
+    if let Some(peer) = cx.peer {
+        let ip = RestrictedExpression::from_str(&format!("ip(\"{}\")", peer.to_canonical())).map_err(|e| anyhow!("{e}"))?;
+        context.insert("peer".into(), ip);
+    }
+
+    let entities = Entities::from_entities(entities).map_err(|e| anyhow!("{e}"))?;
+    let request = Request::new(Some(principal), Some(action), Some(resource), CedarContext::from_pairs(context));
+    Ok((request, entities))
+}
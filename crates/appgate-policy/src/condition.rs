@@ -0,0 +1,58 @@
+//! Conditions over request attributes (`[rules.when]`).
+//!
+//! Modules describe each request with string attributes; the HTTP module sends `method`, `host`,
+//! `path`, `url`, `query.<key>` and `header.<name>` (lowercase). A condition names an attribute
+//! and what its value must look like:
+//!
+//! ```toml
+//! [rules.when]
+//! method = ["GET", "HEAD"]                    # any of these values
+//! "header.x-requested-with" = "XMLHttpRequest" # exactly this value
+//! "query.debug" = { present = false }          # attribute must be absent
+//! path = { glob = "/api/*/items" }             # `*`/`?` wildcards over the whole value
+//! ```
+
+use serde::Deserialize;
+use std::collections::HashMap;
+
+use crate::resource::wildcard;
+
+/// What an attribute's value must satisfy
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum Condition {
+    Equals(String),
+    AnyOf(Vec<String>),
+    Test(AttrTest),
+}
+
+/// Table form of a condition; every given field must hold
+#[derive(Debug, Deserialize, Clone, Default, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct AttrTest {
+    pub present: Option<bool>,
+    pub glob: Option<String>,
+    #[serde(default)]
+    pub not: Vec<String>,
+}
+
+impl Condition {
+    /// Whether `value` (absent when the module did not send the attribute) satisfies the condition
+    pub fn holds(&self, value: Option<&str>) -> bool {
+        match (self, value) {
+            (Self::Equals(want), Some(v)) => v == want,
+            (Self::AnyOf(want), Some(v)) => want.iter().any(|w| w == v),
+            (Self::Test(t), v) => {
+                t.present.is_none_or(|p| p == v.is_some())
+                    && t.glob.as_ref().is_none_or(|g| v.is_some_and(|v| wildcard(g, v)))
+                    && v.is_none_or(|v| !t.not.iter().any(|n| n == v))
+            }
+            (_, None) => false,
+        }
+    }
+}
+
+/// Every condition holds against `attributes`
+pub fn all_hold(when: &HashMap<String, Condition>, attributes: &HashMap<String, String>) -> bool {
+    when.iter().all(|(k, c)| c.holds(attributes.get(k).map(String::as_str)))
+}
@@ -0,0 +1,122 @@
+//! Policy engines: the TOML rules of this crate and [Cedar](crate::cedar), behind one trait.
+
+use anyhow::{bail, Result};
+use serde::Deserialize;
+use std::str::FromStr;
+
+use crate::{
+    cedar::CedarPolicy,
+    explain::Explanation,
+    lint::Finding,
+    Context, Decision, Policy,
+};
+
+/// Decides requests and explains its decisions
+pub trait PolicyEngine: Send + Sync {
+    fn evaluate(&self, cx: &Context) -> Decision;
+    fn explain(&self, cx: &Context) -> Explanation;
+}
+
+impl PolicyEngine for Policy {
+    fn evaluate(&self, cx: &Context) -> Decision {
+        Policy::evaluate(self, cx)
+    }
+
+    fn explain(&self, cx: &Context) -> Explanation {
+        Policy::explain(self, cx)
+    }
+}
+
+impl PolicyEngine for CedarPolicy {
+    fn evaluate(&self, cx: &Context) -> Decision {
+        CedarPolicy::evaluate(self, cx)
+    }
+
+    fn explain(&self, cx: &Context) -> Explanation {
+        CedarPolicy::explain(self, cx)
+    }
+}
+
+/// Which engine reads the policy file (`[policy] engine` in appgate.toml)
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EngineKind {
+    /// TOML rules (a file or directory, see [`crate::source`])
+    #[default]
+    Toml,
+    /// A Cedar policy set, see [`crate::cedar`]
+    Cedar,
+}
+
+impl FromStr for EngineKind {
+    type Err = anyhow::Error;
+
+    /// `toml` or `cedar`, as in appgate.toml
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "toml" => Ok(Self::Toml),
+            "cedar" => Ok(Self::Cedar),
+            other => bail!("unknown policy engine {other:?} (expected toml or cedar)"),
+        }
+    }
+}
+
+/// A loaded policy of either kind
+pub enum Engine {
+    Toml(Policy),
+    Cedar(Box<CedarPolicy>),
+}
+
+impl Engine {
+    /// Load `path` with the `kind` engine. TOML policies are linted: errors fail the load and
+    /// warnings are returned
+    pub fn load(kind: EngineKind, path: &str) -> Result<(Self, Vec<Finding>)> {
+        match kind {
+            EngineKind::Toml => Policy::load_checked(path).map(|(p, warnings)| (Self::Toml(p), warnings)),
+            EngineKind::Cedar => Ok((Self::Cedar(Box::new(CedarPolicy::load(path)?)), Vec::new())),
+        }
+    }
+
+    pub fn kind(&self) -> EngineKind {
+        match self {
+            Self::Toml(_) => EngineKind::Toml,
+            Self::Cedar(_) => EngineKind::Cedar,
+        }
+    }
+
+    /// Rule (or Cedar policy) names in file order
+    pub fn rule_names(&self) -> Vec<String> {
+        match self {
+            Self::Toml(p) => p.rules.iter().map(|r| r.name.clone()).collect(),
+            Self::Cedar(p) => p.names(),
+        }
+    }
+}
+
+impl From<Policy> for Engine {
+    fn from(p: Policy) -> Self {
+        Self::Toml(p)
+    }
+}
+
+impl From<CedarPolicy> for Engine {
+    fn from(p: CedarPolicy) -> Self {
+        Self::Cedar(Box::new(p))
+    }
+}
+
+impl PolicyEngine for Engine {
+    fn evaluate(&self, cx: &Context) -> Decision {
+        match self {
+            Self::Toml(p) => p.evaluate(cx),
+            Self::Cedar(p) => p.evaluate(cx),
+        }
+    }
+
+    fn explain(&self, cx: &Context) -> Explanation {
+        match self {
+            Self::Toml(p) => p.explain(cx),
+            Self::Cedar(p) => p.explain(cx),
+        }
+    }
+}
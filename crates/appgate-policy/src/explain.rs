@@ -0,0 +1,100 @@
+//! Decision traces: why a request was allowed or denied, rule by rule.
+
+use crate::{resource::Resource, source::Source, Context, Decision, Effect, Policy, Rule};
+
+/// One `[rules.when]` condition as checked against the request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConditionTrace {
+    pub attribute: String,
+    /// The request's value, if the module sent the attribute
+    pub value: Option<String>,
+    pub passed: bool,
+}
+
+/// How one rule fared against the request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleTrace {
+    pub rule: String,
+    pub effect: Effect,
+    pub priority: i32,
+    pub protocol: bool,
+    pub resource: bool,
+    /// Sorted by attribute name
+    pub conditions: Vec<ConditionTrace>,
+    pub peer: bool,
+    pub schedule: bool,
+    pub groups: bool,
+    /// This rule produced the decision
+    pub decisive: bool,
+    /// Where the rule is defined, when it was loaded from a file
+    pub source: Option<Source>,
+}
+
+impl RuleTrace {
+    /// Everything but the groups matched, so the rule took part in the decision
+    pub fn matched(&self) -> bool {
+        self.protocol && self.resource && self.conditions.iter().all(|c| c.passed) && self.peer && self.schedule
+    }
+
+    /// Short account of the first check that failed, or of the rule's part in the decision
+    pub fn summary(&self) -> String {
+        let failed: Vec<&str> = self.conditions.iter().filter(|c| !c.passed).map(|c| c.attribute.as_str()).collect();
+        match () {
+            _ if !self.protocol => "protocol differs".into(),
+            _ if !self.resource => "resource does not match".into(),
+            _ if !failed.is_empty() => format!("condition failed: {}", failed.join(", ")),
+            _ if !self.peer => "client address not admitted".into(),
+            _ if !self.schedule => "outside schedule".into(),
+            _ if !self.groups => "caller lacks required groups".into(),
+            _ if self.decisive => "decided".into(),
+            _ => "applies, but another rule decided".into(),
+        }
+    }
+}
+
+/// A decision with the trace of every rule, in file order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Explanation {
+    pub decision: Decision,
+    pub rules: Vec<RuleTrace>,
+}
+
+impl Policy {
+    /// [`Policy::evaluate`] with a per-rule account of what matched and what did not
+    pub fn explain(&self, cx: &Context) -> Explanation {
+        let (decision, decisive) = self.select(cx);
+        let resource = Resource::parse(cx.resource);
+        let rules = self
+            .rules
+            .iter()
+            .enumerate()
+            .map(|(i, r)| trace(r, cx, &resource, decisive == Some(i)))
+            .collect();
+        Explanation { decision, rules }
+    }
+}
+
+fn trace(r: &Rule, cx: &Context, resource: &Resource, decisive: bool) -> RuleTrace {
+    let mut conditions: Vec<ConditionTrace> = r
+        .when
+        .iter()
+        .map(|(attribute, c)| {
+            let value = cx.attributes.get(attribute).cloned();
+            ConditionTrace { attribute: attribute.clone(), passed: c.holds(value.as_deref()), value }
+        })
+        .collect();
+    conditions.sort_by(|a, b| a.attribute.cmp(&b.attribute));
+    RuleTrace {
+        rule: r.name.clone(),
+        effect: r.effect,
+        priority: r.priority,
+        protocol: r.protocol == cx.protocol,
+        resource: r.matches(resource),
+        conditions,
+        peer: r.admits_peer(cx.peer),
+        schedule: r.schedule.as_ref().is_none_or(|s| s.is_active(cx.now)),
+        groups: r.applies_to(cx.groups),
+        decisive,
+        source: r.source.clone(),
+    }
+}
@@ -1,12 +1,185 @@
+use chrono::{DateTime, Utc};
+use ipnet::IpNet;
 use serde::Deserialize;
+use std::{collections::HashMap, net::IpAddr, sync::OnceLock};
+
+pub mod cedar;
+pub mod condition;
+pub mod engine;
+pub mod explain;
+pub mod lint;
+pub mod resource;
+pub mod schedule;
+pub mod source;
+pub mod suite;
+pub mod template;
+
+use condition::Condition;
+use resource::Resource;
+use schedule::Schedule;
+use source::Source;
+
+/// A request as seen by the policy engine
+#[derive(Debug, Clone, Copy)]
+pub struct Context<'a> {
+    pub protocol: &'a str,
+    pub resource: &'a str,
+    pub groups: &'a [String],
+    /// Verified identity claims of the session (`sub`, `email`, ...), used by the Cedar engine
+    pub claims: &'a HashMap<String, String>,
+    /// Module-provided request attributes (`method`, `header.<name>`, ...)
+    pub attributes: &'a HashMap<String, String>,
+    /// Client address as reported by the module; `None` when unknown
+    pub peer: Option<IpAddr>,
+    /// Instant schedules are checked against
+    pub now: DateTime<Utc>,
+}
+
+/// Outcome of [`Policy::evaluate`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Decision {
+    pub allow: bool,
+    pub inject: Option<HashMap<String, String>>,
+    pub reason: String,
+    /// End of the allowing rule's schedule window; access must not outlive it
+    pub until: Option<DateTime<Utc>>,
+    /// Where the deciding rule is defined, when it was loaded from a file
+    pub source: Option<Source>,
+}
+
+impl Decision {
+    fn deny(reason: impl Into<String>) -> Self {
+        Self { allow: false, inject: None, reason: reason.into(), until: None, source: None }
+    }
+}
+
+/// How a rule's `resource` is compared with the requested resource
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchKind {
+    /// Same scheme, host and path after normalisation
+    Exact,
+    /// Same scheme and host; the rule's path is a whole-segment prefix (`/api` matches `/api/x`, not `/apix`)
+    #[default]
+    Prefix,
+    /// Host and path globs: `*.example.com`, `/users/*/profile`, `/api/**`
+    Glob,
+    /// Regular expression over the normalised `scheme://host[:port]/path`, anchored at both ends
+    Regex,
+}
+
+/// What a rule does when it applies
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Effect {
+    #[default]
+    Allow,
+    /// Overrides every allow rule that matches the same request
+    Deny,
+}
+
+/// How `require_groups` is checked against the caller's groups
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum GroupMatch {
+    /// The caller must be in every listed group
+    #[default]
+    All,
+    /// The caller must be in at least one listed group
+    Any,
+}
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct Rule {
     pub name: String,
     pub protocol: String,        // "http" | "tcp" | "udp"
-    pub resource: String,        // glob/prefix
+    pub resource: String,        // interpreted per `match`
+    #[serde(default, rename = "match")]
+    pub match_kind: MatchKind,
+    #[serde(default)]
+    pub effect: Effect,
+    /// Precedence among matching allow rules: higher first, then more specific. Deny rules win regardless
+    #[serde(default)]
+    pub priority: i32,
+    #[serde(default)]
     pub require_groups: Vec<String>,
+    #[serde(default)]
+    pub group_match: GroupMatch,
+    /// When non-empty, the rule only matches clients inside one of these ranges
+    #[serde(default)]
+    pub allow_cidrs: Vec<IpNet>,
+    /// The rule never matches clients inside these ranges
+    #[serde(default)]
+    pub deny_cidrs: Vec<IpNet>,
+    /// Headers for the module to add; values may interpolate identity, see [`template`]
     pub inject: Option<std::collections::HashMap<String,String>>,
+    /// Conditions over request attributes, all of which must hold for the rule to match
+    #[serde(default)]
+    pub when: HashMap<String, Condition>,
+    /// Days, times and dates during which the rule matches
+    pub schedule: Option<Schedule>,
+    /// Set by [`Policy::load`]
+    #[serde(skip)]
+    pub source: Option<Source>,
+    #[serde(skip)]
+    regex: OnceLock<Result<regex::Regex, regex::Error>>,
+}
+
+impl Rule {
+    fn regex(&self) -> &Result<regex::Regex, regex::Error> {
+        self.regex.get_or_init(|| regex::Regex::new(&format!("^(?:{})$", self.resource)))
+    }
+
+    /// Whether the caller's groups satisfy `require_groups`; an empty list applies to everyone
+    pub fn applies_to(&self, groups: &[String]) -> bool {
+        match self.group_match {
+            _ if self.require_groups.is_empty() => true,
+            GroupMatch::All => self.require_groups.iter().all(|g| groups.contains(g)),
+            GroupMatch::Any => self.require_groups.iter().any(|g| groups.contains(g)),
+        }
+    }
+
+    /// Whether the client address satisfies `allow_cidrs`/`deny_cidrs`. With an unknown peer,
+    /// address-restricted rules fail closed: allow rules never match and deny rules always do
+    pub fn admits_peer(&self, peer: Option<IpAddr>) -> bool {
+        if self.allow_cidrs.is_empty() && self.deny_cidrs.is_empty() {
+            return true;
+        }
+        // IPv4-mapped IPv6 addresses (dual-stack listeners) compare as IPv4
+        let Some(ip) = peer.map(|p| p.to_canonical()) else { return self.effect == Effect::Deny };
+        (self.allow_cidrs.is_empty() || self.allow_cidrs.iter().any(|n| n.contains(&ip)))
+            && !self.deny_cidrs.iter().any(|n| n.contains(&ip))
+    }
+
+    /// ` (file:line)` when the rule was loaded from a file, for messages
+    pub(crate) fn at(&self) -> String {
+        self.source.as_ref().map(|s| format!(" ({s})")).unwrap_or_default()
+    }
+
+    /// Ordering key among rules of equal priority: more literal characters first, then by match kind
+    pub fn specificity(&self) -> (usize, u8) {
+        let kind = match self.match_kind {
+            MatchKind::Exact => 3,
+            MatchKind::Prefix => 2,
+            MatchKind::Glob => 1,
+            MatchKind::Regex => 0,
+        };
+        let literal = match self.match_kind {
+            MatchKind::Regex => 0,
+            _ => self.resource.chars().filter(|c| !matches!(c, '*' | '?')).count(),
+        };
+        (literal, kind)
+    }
+
+    /// Whether this rule's resource matches the (already normalised) request resource
+    pub fn matches(&self, resource: &Resource) -> bool {
+        match self.match_kind {
+            MatchKind::Exact => resource::exact(&Resource::parse(&self.resource), resource),
+            MatchKind::Prefix => resource::prefix(&Resource::parse(&self.resource), resource),
+            MatchKind::Glob => resource::glob(&Resource::pattern(&self.resource), resource),
+            MatchKind::Regex => self.regex().as_ref().is_ok_and(|re| re.is_match(&resource.to_canonical())),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -15,24 +188,85 @@ pub struct Policy {
 }
 
 impl Policy {
+    /// Load a policy file or directory with its includes (see [`source`])
     pub fn load(path: &str) -> anyhow::Result<Self> {
-        let txt = std::fs::read_to_string(path)?;
-        Ok(toml::from_str(&txt)?)
+        let policy = Self { rules: source::load(std::path::Path::new(path))? };
+        policy.validate()?;
+        Ok(policy)
     }
 
+    /// Reject rules whose resource cannot be used with their match kind, and invalid injected headers
+    pub fn validate(&self) -> anyhow::Result<()> {
+        for r in &self.rules {
+            if r.match_kind == MatchKind::Regex {
+                if let Err(e) = r.regex() {
+                    anyhow::bail!("rule {}{}: invalid regex: {e}", r.name, r.at());
+                }
+            }
+            for (name, value) in r.inject.iter().flatten() {
+                if !template::is_header_name(name) {
+                    anyhow::bail!("rule {}{}: invalid header name {name:?}", r.name, r.at());
+                }
+                if let Err(e) = template::Template::parse(value) {
+                    anyhow::bail!("rule {}{}: header {name}: {e}", r.name, r.at());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// [`Policy::evaluate`] for a request without attributes or peer address, at the current time
     pub fn decide(
         &self,
         protocol: &str,
         resource: &str,
         groups: &[String],
     ) -> (bool, Option<std::collections::HashMap<String,String>>, String) {
-        for r in &self.rules {
-            if r.protocol == protocol && resource.starts_with(&r.resource) {
-                let ok = r.require_groups.iter().all(|g| groups.contains(g));
-                let reason = if ok { format!("policy: {}", r.name) } else { "missing group".into() };
-                return (ok, r.inject.clone(), reason);
+        let none = HashMap::new();
+        let d = self.evaluate(&Context { protocol, resource, groups, claims: &none, attributes: &none, peer: None, now: Utc::now() });
+        (d.allow, d.inject, d.reason)
+    }
+
+    /// Evaluate every rule matching the request's protocol, resource, attributes, peer and time.
+    ///
+    /// Any applicable deny rule wins. Otherwise the first applicable allow rule in precedence
+    /// order (priority, then specificity, then file order) allows and supplies `inject`.
+    pub fn evaluate(&self, cx: &Context) -> Decision {
+        self.select(cx).0
+    }
+
+    /// [`Policy::evaluate`], also returning the index of the rule that decided
+    fn select(&self, cx: &Context) -> (Decision, Option<usize>) {
+        let resource = Resource::parse(cx.resource);
+        let mut matching: Vec<(usize, &Rule)> = self
+            .rules
+            .iter()
+            .enumerate()
+            .filter(|(_, r)| r.protocol == cx.protocol && r.matches(&resource) && condition::all_hold(&r.when, cx.attributes))
+            .filter(|(_, r)| r.admits_peer(cx.peer) && r.schedule.as_ref().is_none_or(|s| s.is_active(cx.now)))
+            .collect();
+        if matching.is_empty() {
+            return (Decision::deny("default-deny"), None);
+        }
+        // stable sort keeps file order among equals
+        matching.sort_by(|(_, a), (_, b)| b.priority.cmp(&a.priority).then_with(|| b.specificity().cmp(&a.specificity())));
+
+        if let Some((i, r)) = matching.iter().find(|(_, r)| r.effect == Effect::Deny && r.applies_to(cx.groups)) {
+            let d = Decision { source: r.source.clone(), ..Decision::deny(format!("deny: {}", r.name)) };
+            return (d, Some(*i));
+        }
+        match matching.iter().find(|(_, r)| r.effect == Effect::Allow && r.applies_to(cx.groups)) {
+            Some((i, r)) => {
+                let d = Decision {
+                    allow: true,
+                    inject: r.inject.clone(),
+                    reason: format!("policy: {}", r.name),
+                    until: r.schedule.as_ref().and_then(|s| s.active_until(cx.now)).filter(|t| *t != DateTime::<Utc>::MAX_UTC),
+                    source: r.source.clone(),
+                };
+                (d, Some(*i))
             }
+            None => (Decision::deny("missing group"), None),
         }
-        (false, None, "default-deny".into())
     }
-}
\ No newline at end of file
+}
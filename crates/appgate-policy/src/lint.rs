@@ -0,0 +1,190 @@
+//! Static checks over a whole policy.
+//!
+//! Errors are policies that cannot be what was meant: rules that never decide because an
+//! earlier-precedence rule covers every request they would match (or a deny rule always overrides
+//! them), unknown protocols and invalid injected headers. Warnings are suspicious but possibly
+//! deliberate, such as sensitive paths open to every signed-in user. Duplicate rule names are
+//! already refused by the [loader](crate::source).
+//!
+//! Coverage is judged conservatively: a finding is only reported when one rule provably matches
+//! everything the other does, so overlapping-but-distinct rules are left alone.
+
+use std::fmt;
+
+use crate::{
+    condition::Condition,
+    resource::{self, Resource},
+    source::Source,
+    template, Effect, GroupMatch, MatchKind, Policy, Rule,
+};
+
+/// Protocols modules send
+pub const PROTOCOLS: [&str; 3] = ["http", "tcp", "udp"];
+
+/// Path segments that usually deserve a group requirement
+const SENSITIVE: [&str; 10] = ["admin", "setup", "config", "settings", "manage", "management", "internal", "debug", "metrics", "actuator"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+/// One problem with one rule
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub severity: Severity,
+    pub rule: String,
+    /// Where the rule is defined, when it was loaded from a file
+    pub source: Option<Source>,
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let level = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        if let Some(source) = &self.source {
+            write!(f, "{source}: ")?;
+        }
+        write!(f, "{level}: rule {}: {}", self.rule, self.message)
+    }
+}
+
+/// Every finding for `policy`, in rule order
+pub fn lint(policy: &Policy) -> Vec<Finding> {
+    let mut out = Vec::new();
+    for (i, r) in policy.rules.iter().enumerate() {
+        let mut report = |severity, message: String| out.push(Finding { severity, rule: r.name.clone(), source: r.source.clone(), message });
+        if !PROTOCOLS.contains(&r.protocol.as_str()) {
+            report(Severity::Error, format!("unknown protocol {:?} (expected one of {})", r.protocol, PROTOCOLS.join(", ")));
+        }
+        for (name, value) in r.inject.iter().flatten() {
+            if !template::is_header_name(name) {
+                report(Severity::Error, format!("invalid inject header name {name:?}"));
+            } else if let Err(e) = template::Template::parse(value) {
+                report(Severity::Error, format!("inject header {name}: {e}"));
+            }
+        }
+        if r.effect == Effect::Allow {
+            let others = || policy.rules.iter().enumerate().filter(move |(j, _)| *j != i);
+            if let Some((_, d)) = others().find(|(_, o)| o.effect == Effect::Deny && covers(o, r)) {
+                report(Severity::Error, format!("never allows: deny rule {}{} matches every request it does", d.name, d.at()));
+            } else if let Some((_, s)) = others().find(|(j, o)| o.effect == Effect::Allow && precedes(o, *j, r, i) && covers(o, r)) {
+                report(Severity::Error, format!("never decides: shadowed by rule {}{}, which matches every request it does first", s.name, s.at()));
+            }
+            if r.require_groups.is_empty() && r.allow_cidrs.is_empty() && sensitive(r) {
+                report(Severity::Warning, "sensitive path allowed without require_groups".into());
+            }
+        }
+    }
+    out
+}
+
+/// Whether `a` (at index `ai`) is tried before `b` (at `bi`) among allow rules
+fn precedes(a: &Rule, ai: usize, b: &Rule, bi: usize) -> bool {
+    (a.priority, a.specificity(), std::cmp::Reverse(ai)) > (b.priority, b.specificity(), std::cmp::Reverse(bi))
+}
+
+/// `a` matches every request `b` matches, for everyone `b` applies to
+fn covers(a: &Rule, b: &Rule) -> bool {
+    a.protocol == b.protocol
+        && covers_resource(a, b)
+        && a.when.iter().all(|(k, c)| b.when.get(k).is_some_and(|bc| implies(bc, c)))
+        && ((a.allow_cidrs.is_empty() && a.deny_cidrs.is_empty()) || (a.allow_cidrs == b.allow_cidrs && a.deny_cidrs == b.deny_cidrs))
+        && (a.schedule.is_none() || a.schedule == b.schedule)
+        && covers_groups(a, b)
+}
+
+/// Every value satisfying `b` satisfies `a`; only the obvious cases are recognised
+fn implies(b: &Condition, a: &Condition) -> bool {
+    match (b, a) {
+        _ if a == b => true,
+        (Condition::Equals(v), Condition::AnyOf(vs)) => vs.contains(v),
+        (Condition::AnyOf(bs), Condition::AnyOf(as_)) => bs.iter().all(|v| as_.contains(v)),
+        _ => false,
+    }
+}
+
+fn covers_resource(a: &Rule, b: &Rule) -> bool {
+    if a.match_kind == b.match_kind && a.resource == b.resource {
+        return true;
+    }
+    if let Some(tree) = subtree(a) {
+        return match b.match_kind {
+            MatchKind::Exact | MatchKind::Prefix => resource::prefix(&tree, &Resource::parse(&b.resource)),
+            // a glob with a literal host whose literal leading path lies inside the subtree
+            MatchKind::Glob => match (tree, Resource::pattern(&b.resource)) {
+                (Resource::Url { scheme, host, port, path }, Resource::Url { scheme: bs, host: bh, port: bp, path: bpath }) => {
+                    let literal: Vec<String> = bpath.iter().take_while(|s| !s.contains(['*', '?'])).cloned().collect();
+                    scheme == bs && host == bh && !has_wildcard(&bh) && (port.is_none() || port == bp) && literal.starts_with(&path)
+                }
+                _ => false,
+            },
+            MatchKind::Regex => false,
+        };
+    }
+    match (a.match_kind, b.match_kind) {
+        (MatchKind::Exact, MatchKind::Exact) => resource::exact(&Resource::parse(&a.resource), &Resource::parse(&b.resource)),
+        (MatchKind::Glob | MatchKind::Regex, MatchKind::Exact) => a.matches(&Resource::parse(&b.resource)),
+        _ => false,
+    }
+}
+
+/// The subtree a rule matches in full: a prefix rule's resource, or a glob with a literal scheme,
+/// host and port whose path is literal segments followed by `**`
+fn subtree(r: &Rule) -> Option<Resource> {
+    match r.match_kind {
+        MatchKind::Prefix => Some(Resource::parse(&r.resource)),
+        MatchKind::Glob => match Resource::pattern(&r.resource) {
+            Resource::Url { scheme, host, port, mut path } if path.last().is_some_and(|s| s == "**") => {
+                path.pop();
+                let literal = [&scheme, &host].into_iter().chain(port.as_ref()).chain(&path).all(|s| !has_wildcard(s));
+                literal.then_some(Resource::Url { scheme, host, port, path })
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Whether a pattern part contains wildcards
+fn has_wildcard(s: &str) -> bool {
+    s.contains(['*', '?'])
+}
+
+/// Everyone `b`'s group requirement admits, `a`'s admits too
+fn covers_groups(a: &Rule, b: &Rule) -> bool {
+    let subset = |x: &[String], y: &[String]| x.iter().all(|g| y.contains(g));
+    match (a.group_match, b.group_match) {
+        _ if a.require_groups.is_empty() => true,
+        _ if b.require_groups.is_empty() => false,
+        (GroupMatch::All, GroupMatch::All) => subset(&a.require_groups, &b.require_groups),
+        (GroupMatch::Any, GroupMatch::Any) => subset(&b.require_groups, &a.require_groups),
+        (GroupMatch::Any, GroupMatch::All) => b.require_groups.iter().any(|g| a.require_groups.contains(g)),
+        (GroupMatch::All, GroupMatch::Any) => b.require_groups.len() == 1 && subset(&a.require_groups, &b.require_groups),
+    }
+}
+
+/// An allow rule whose resource names a sensitive path segment
+fn sensitive(r: &Rule) -> bool {
+    match Resource::pattern(&r.resource) {
+        Resource::Url { path, .. } => path.iter().any(|s| SENSITIVE.contains(&s.to_ascii_lowercase().as_str())),
+        Resource::Opaque(_) => false,
+    }
+}
+
+impl Policy {
+    /// [`Policy::load`], then [`lint`]: lint errors fail the load, warnings are returned
+    pub fn load_checked(path: &str) -> anyhow::Result<(Self, Vec<Finding>)> {
+        let policy = Self::load(path)?;
+        let (errors, warnings): (Vec<_>, Vec<_>) = lint(&policy).into_iter().partition(|f| f.severity == Severity::Error);
+        if !errors.is_empty() {
+            let list: Vec<String> = errors.iter().map(ToString::to_string).collect();
+            anyhow::bail!("{path}: {}", list.join("; "));
+        }
+        Ok((policy, warnings))
+    }
+}
@@ -0,0 +1,66 @@
+use anyhow::Result;
+use appgate_policy::{
+    engine::{Engine, EngineKind},
+    lint::{lint, Severity},
+    suite::Suite,
+    Policy,
+};
+use clap::{Parser, Subcommand};
+use std::process::ExitCode;
+
+#[derive(Parser, Debug)]
+#[command(name = "appgate-policy")]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Run test suites against a policy; exits non-zero if any case fails
+    Test {
+        #[arg(long, default_value="config/policy/foundry.toml")]
+        policy: String,
+        /// `toml` or `cedar`
+        #[arg(long, default_value = "toml")]
+        engine: EngineKind,
+        /// Suite files (TOML, or YAML by extension)
+        #[arg(required = true)]
+        suites: Vec<String>,
+    },
+    /// Report unreachable, shadowed and suspicious rules; exits non-zero on errors
+    Lint {
+        #[arg(default_value="config/policy/foundry.toml")]
+        policy: String,
+    },
+}
+
+fn main() -> Result<ExitCode> {
+    let args = Args::parse();
+    match args.command {
+        Command::Test { policy, engine, suites } => {
+            let (policy, _) = Engine::load(engine, &policy)?;
+            let (mut cases, mut failed) = (0, 0);
+            for path in &suites {
+                let suite = Suite::load(path)?;
+                let failures = suite.run(&policy);
+                for f in &failures {
+                    print!("{path}: {f}");
+                }
+                cases += suite.cases.len();
+                failed += failures.len();
+            }
+            println!("{} of {cases} cases passed", cases - failed);
+            Ok(if failed == 0 { ExitCode::SUCCESS } else { ExitCode::FAILURE })
+        }
+        Command::Lint { policy } => {
+            let findings = lint(&Policy::load(&policy)?);
+            for f in &findings {
+                println!("{f}");
+            }
+            let errors = findings.iter().filter(|f| f.severity == Severity::Error).count();
+            println!("{errors} errors, {} warnings", findings.len() - errors);
+            Ok(if errors == 0 { ExitCode::SUCCESS } else { ExitCode::FAILURE })
+        }
+    }
+}
@@ -0,0 +1,165 @@
+//! Resource normalisation and host/path matching.
+//!
+//! URL-shaped resources (`scheme://host[:port]/path`) are split into parts and matched per part:
+//! hosts label by label, paths segment by segment. Anything else (`sni:host:port`, labels) is
+//! matched as a plain string.
+
+use percent_encoding::percent_decode_str;
+
+/// A resource split into comparable parts
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Resource {
+    Url { scheme: String, host: String, port: Option<String>, path: Vec<String> },
+    Opaque(String),
+}
+
+impl Resource {
+    /// Parse and normalise a requested resource: lowercase scheme and host, drop query and
+    /// fragment, percent-decode the path, and resolve `.`/`..`/empty segments
+    pub fn parse(s: &str) -> Self {
+        Self::split(s, true)
+    }
+
+    /// Parse a rule pattern: like [`Resource::parse`] but without percent-decoding, so escapes
+    /// in patterns stay literal
+    pub fn pattern(s: &str) -> Self {
+        Self::split(s, false)
+    }
+
+    fn split(s: &str, decode: bool) -> Self {
+        let Some((scheme, rest)) = s.split_once("://") else {
+            return Self::Opaque(s.to_string());
+        };
+        let end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+        let (authority, rest) = rest.split_at(end);
+        let path = &rest[..rest.find(['?', '#']).unwrap_or(rest.len())];
+        let authority = authority.rsplit_once('@').map_or(authority, |(_, h)| h);
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((h, p)) if !p.is_empty() && p.bytes().all(|b| b.is_ascii_digit()) => (h, Some(p.to_string())),
+            _ => (authority, None),
+        };
+        Self::Url {
+            scheme: scheme.to_ascii_lowercase(),
+            host: host.trim_end_matches('.').to_ascii_lowercase(),
+            port,
+            path: normalise_path(path, decode),
+        }
+    }
+
+    /// Canonical string form, as seen by regex rules
+    pub fn to_canonical(&self) -> String {
+        match self {
+            Self::Url { scheme, host, port, path } => {
+                let port = port.as_ref().map(|p| format!(":{p}")).unwrap_or_default();
+                format!("{scheme}://{host}{port}/{}", path.join("/"))
+            }
+            Self::Opaque(s) => s.clone(),
+        }
+    }
+}
+
+/// Path segments after decoding and dot-segment removal; `..` never climbs above the root
+fn normalise_path(path: &str, decode: bool) -> Vec<String> {
+    let decoded = if decode { percent_decode_str(path).decode_utf8_lossy().into_owned() } else { path.to_string() };
+    let mut out: Vec<String> = Vec::new();
+    // backslashes are separators to some upstreams; treat them as such so they cannot hide `..`
+    for seg in decoded.split(['/', '\\']) {
+        match seg {
+            "" | "." => {}
+            ".." => {
+                out.pop();
+            }
+            s => out.push(s.to_string()),
+        }
+    }
+    out
+}
+
+/// Ports only constrain the match when the rule names one
+fn port_matches(rule: &Option<String>, req: &Option<String>) -> bool {
+    rule.is_none() || rule == req
+}
+
+/// `resource` equals `rule` after normalisation
+pub fn exact(rule: &Resource, resource: &Resource) -> bool {
+    match (rule, resource) {
+        (
+            Resource::Url { scheme: rs, host: rh, port: rp, path: rpath },
+            Resource::Url { scheme, host, port, path },
+        ) => rs == scheme && rh == host && port_matches(rp, port) && rpath == path,
+        (Resource::Opaque(r), Resource::Opaque(s)) => r == s,
+        _ => false,
+    }
+}
+
+/// Same scheme and host, and `rule`'s path is a whole-segment prefix of `resource`'s
+pub fn prefix(rule: &Resource, resource: &Resource) -> bool {
+    match (rule, resource) {
+        (
+            Resource::Url { scheme: rs, host: rh, port: rp, path: rpath },
+            Resource::Url { scheme, host, port, path },
+        ) => rs == scheme && rh == host && port_matches(rp, port) && path.starts_with(rpath),
+        (Resource::Opaque(r), Resource::Opaque(s)) => s.starts_with(r.as_str()),
+        _ => false,
+    }
+}
+
+/// Host and path globs: `*` matches within one label/segment, `**` matches any number of them
+pub fn glob(rule: &Resource, resource: &Resource) -> bool {
+    match (rule, resource) {
+        (
+            Resource::Url { scheme: rs, host: rh, port: rp, path: rpath },
+            Resource::Url { scheme, host, port, path },
+        ) => {
+            let ports = match (rp, port) {
+                (None, _) => true,
+                (Some(rp), Some(p)) => wildcard(rp, p),
+                (Some(_), None) => false,
+            };
+            let rlabels: Vec<&str> = rh.split('.').collect();
+            let labels: Vec<&str> = host.split('.').collect();
+            let rsegs: Vec<&str> = rpath.iter().map(String::as_str).collect();
+            let segs: Vec<&str> = path.iter().map(String::as_str).collect();
+            wildcard(rs, scheme) && ports && segments(&rlabels, &labels) && segments(&rsegs, &segs)
+        }
+        (Resource::Opaque(r), Resource::Opaque(s)) => wildcard(&r.replace("**", "*"), s),
+        _ => false,
+    }
+}
+
+/// Match a sequence of segments where a `**` pattern segment spans zero or more segments
+fn segments(pat: &[&str], val: &[&str]) -> bool {
+    match pat.split_first() {
+        None => val.is_empty(),
+        Some((&"**", rest)) => (0..=val.len()).any(|i| segments(rest, &val[i..])),
+        Some((p, rest)) => val.split_first().is_some_and(|(v, vrest)| wildcard(p, v) && segments(rest, vrest)),
+    }
+}
+
+/// `*` matches any run of characters and `?` exactly one
+pub(crate) fn wildcard(pat: &str, val: &str) -> bool {
+    let (p, v): (Vec<char>, Vec<char>) = (pat.chars().collect(), val.chars().collect());
+    let (mut pi, mut vi) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while vi < v.len() {
+        match p.get(pi) {
+            Some('*') => {
+                backtrack = Some((pi, vi));
+                pi += 1;
+            }
+            Some(&c) if c == '?' || c == v[vi] => {
+                pi += 1;
+                vi += 1;
+            }
+            _ => match backtrack {
+                Some((bp, bv)) => {
+                    pi = bp + 1;
+                    vi = bv + 1;
+                    backtrack = Some((bp, bv + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    p[pi..].iter().all(|&c| c == '*')
+}
@@ -0,0 +1,122 @@
+//! Time-window conditions (`[rules.schedule]`).
+//!
+//! A schedule is active on the listed days (every day when empty) during any of the listed time
+//! ranges (all day when empty), in the schedule's IANA timezone, and between the optional
+//! absolute `not_before`/`not_after` instants:
+//!
+//! ```toml
+//! [rules.schedule]
+//! timezone = "Europe/Berlin"
+//! days = ["fri", "sat"]
+//! hours = ["19:00-23:30", "22:00-02:00"]   # an end at or before the start runs past midnight
+//! not_before = "2026-01-01T00:00:00Z"
+//! not_after = "2026-06-30T00:00:00Z"
+//! ```
+//!
+//! A range belongs to the day it starts on, so `sat` with `22:00-02:00` covers Sunday 01:00.
+
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc, Weekday};
+use chrono_tz::Tz;
+use serde::Deserialize;
+
+/// When a rule applies
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Schedule {
+    #[serde(default = "utc")]
+    pub timezone: Tz,
+    #[serde(default)]
+    pub days: Vec<Weekday>,
+    #[serde(default)]
+    pub hours: Vec<TimeRange>,
+    pub not_before: Option<DateTime<Utc>>,
+    pub not_after: Option<DateTime<Utc>>,
+}
+
+fn utc() -> Tz {
+    Tz::UTC
+}
+
+/// A daily `HH:MM-HH:MM` range; `24:00` is accepted as an end
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(try_from = "String")]
+pub struct TimeRange {
+    /// Minutes after midnight
+    pub start: u32,
+    /// Minutes after midnight; at or before `start` means the next day
+    pub end: u32,
+}
+
+impl TryFrom<String> for TimeRange {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        let minutes = |t: &str| -> Option<u32> {
+            let (h, m) = t.trim().split_once(':')?;
+            let (h, m): (u32, u32) = (h.parse().ok()?, m.parse().ok()?);
+            (m < 60 && (h < 24 || (h == 24 && m == 0))).then_some(h * 60 + m)
+        };
+        let parsed = s.split_once('-').and_then(|(a, b)| Some((minutes(a)?, minutes(b)?)));
+        match parsed {
+            Some((start, end)) if start < 24 * 60 => Ok(Self { start, end }),
+            _ => Err(format!("invalid time range {s:?}, expected HH:MM-HH:MM")),
+        }
+    }
+}
+
+impl Schedule {
+    /// Whether the schedule is active at `now`
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        self.active_until(now).is_some()
+    }
+
+    /// End of the window active at `now`, with back-to-back windows merged; `None` when inactive.
+    /// Schedules without days, hours or `not_after` never end: that is `DateTime::<Utc>::MAX_UTC`
+    pub fn active_until(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        if self.not_before.is_some_and(|t| now < t) || self.not_after.is_some_and(|t| now >= t) {
+            return None;
+        }
+        let cap = self.not_after.unwrap_or(DateTime::<Utc>::MAX_UTC);
+        if self.days.is_empty() && self.hours.is_empty() {
+            return Some(cap);
+        }
+        let local = now.with_timezone(&self.timezone).naive_local();
+        let mut end = self.window_end(local)?;
+        // a window that starts exactly where this one ends extends it; a week of them is the limit
+        for _ in 0..7 * self.hours.len().max(1) {
+            match self.window_end(end) {
+                Some(next) if next > end => end = next,
+                _ => break,
+            }
+        }
+        Some(self.to_utc(end).min(cap))
+    }
+
+    /// Latest end among the daily windows containing local time `t`
+    fn window_end(&self, t: NaiveDateTime) -> Option<NaiveDateTime> {
+        let all_day = [TimeRange { start: 0, end: 24 * 60 }];
+        let ranges = if self.hours.is_empty() { &all_day[..] } else { &self.hours[..] };
+        // windows started yesterday may still be open
+        [t.date() - Duration::days(1), t.date()]
+            .into_iter()
+            .filter(|d| self.days.is_empty() || self.days.contains(&d.weekday()))
+            .flat_map(|d| ranges.iter().map(move |r| window(d, r)))
+            .filter(|(start, end)| *start <= t && t < *end)
+            .map(|(_, end)| end)
+            .max()
+    }
+
+    /// Local times in a DST gap don't exist; use the first instant after the gap
+    fn to_utc(&self, t: NaiveDateTime) -> DateTime<Utc> {
+        (0..=4)
+            .find_map(|h| self.timezone.from_local_datetime(&(t + Duration::minutes(30 * h))).earliest())
+            .map_or_else(|| Utc.from_utc_datetime(&t), |d| d.with_timezone(&Utc))
+    }
+}
+
+/// The local start and end of `range` on day `d`
+fn window(d: NaiveDate, range: &TimeRange) -> (NaiveDateTime, NaiveDateTime) {
+    let at = |minutes: u32| d.and_time(NaiveTime::MIN) + Duration::minutes(minutes.into());
+    let end = if range.end > range.start { range.end } else { range.end + 24 * 60 };
+    (at(range.start), at(end))
+}
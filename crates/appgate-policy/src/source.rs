@@ -0,0 +1,111 @@
+//! Policy files: directories, includes and where each rule came from.
+//!
+//! [`Policy::load`](crate::Policy::load) accepts a file or a directory. A directory loads every
+//! `*.toml` file below it (per-team subdirectories included) in path order, skipping test suites
+//! (`*.tests.toml`). A file may pull in others, paths relative to itself:
+//!
+//! ```toml
+//! include = ["teams/"]   # files or directories
+//!
+//! [[rules]]
+//! ...
+//! ```
+//!
+//! A file's own rules come before those of its includes, which follow in list order. A file
+//! reached twice is loaded once. Rule names must be unique across everything loaded.
+
+use anyhow::{bail, Context as _, Result};
+use serde::Deserialize;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use crate::Rule;
+
+/// The file and line a rule was defined at
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Source {
+    pub file: String,
+    pub line: usize,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.file, self.line)
+    }
+}
+
+/// One policy file as written
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PolicyFile {
+    #[serde(default)]
+    include: Vec<String>,
+    #[serde(default)]
+    rules: Vec<toml::Spanned<Rule>>,
+}
+
+/// Every rule reachable from `path`, each with its [`Source`]
+pub(crate) fn load(path: &Path) -> Result<Vec<Rule>> {
+    let mut rules = Vec::new();
+    load_path(path, &mut HashSet::new(), &mut rules)?;
+    let mut names: HashMap<&str, &Rule> = HashMap::new();
+    for r in &rules {
+        if let Some(first) = names.insert(&r.name, r) {
+            bail!("rule {} is defined twice, at {} and {}", r.name, location(first), location(r));
+        }
+    }
+    Ok(rules)
+}
+
+fn location(r: &Rule) -> String {
+    r.source.as_ref().map_or_else(|| "?".into(), ToString::to_string)
+}
+
+fn load_path(path: &Path, loaded: &mut HashSet<PathBuf>, rules: &mut Vec<Rule>) -> Result<()> {
+    if path.is_dir() {
+        for file in policy_files(path)? {
+            load_path(&file, loaded, rules)?;
+        }
+        return Ok(());
+    }
+    let canonical = path.canonicalize().with_context(|| format!("reading {}", path.display()))?;
+    if !loaded.insert(canonical) {
+        return Ok(());
+    }
+    let txt = std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+    let parsed: PolicyFile = toml::from_str(&txt).with_context(|| format!("parsing {}", path.display()))?;
+    let file = path.display().to_string();
+    for spanned in parsed.rules {
+        let line = txt[..spanned.span().start].matches('\n').count() + 1;
+        let mut rule = spanned.into_inner();
+        rule.source = Some(Source { file: file.clone(), line });
+        rules.push(rule);
+    }
+    let dir = path.parent().unwrap_or(Path::new(""));
+    for include in &parsed.include {
+        load_path(&dir.join(include), loaded, rules).with_context(|| format!("included from {file}"))?;
+    }
+    Ok(())
+}
+
+/// `*.toml` files below `dir` other than test suites, sorted by path
+fn policy_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(d) = dirs.pop() {
+        for entry in std::fs::read_dir(&d).with_context(|| format!("reading {}", d.display()))? {
+            let path = entry?.path();
+            let name = path.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if name.ends_with(".toml") && !name.ends_with(".tests.toml") {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
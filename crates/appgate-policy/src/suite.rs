@@ -0,0 +1,144 @@
+//! Offline policy test suites: requests with the decisions a policy is expected to make.
+//!
+//! Suites are TOML (or YAML, by `.yaml`/`.yml` extension) files of cases:
+//!
+//! ```toml
+//! [[cases]]
+//! name = "admins get the admin role header"
+//! resource = "http://foundry/"           # protocol defaults to "http"
+//! groups = ["foundry-players", "foundry-admin"]
+//! attributes = { method = "GET" }
+//! expect = "allow"                        # or "deny"
+//! reason = "policy: foundry-admin"        # optional
+//! inject = { "X-Role" = "admin" }         # optional; compared after rendering with `claims`
+//! ```
+//!
+//! `peer` (an IP address) and `at` (RFC 3339, default now) cover address ranges and schedules.
+
+use anyhow::{Context as _, Result};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::{collections::HashMap, fmt, net::IpAddr, path::Path};
+
+use crate::{engine::PolicyEngine, explain::Explanation, template, Context};
+
+/// Expected allow/deny
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Verdict {
+    Allow,
+    Deny,
+}
+
+/// One request and the decision it should get
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Case {
+    pub name: String,
+    #[serde(default = "http")]
+    pub protocol: String,
+    pub resource: String,
+    #[serde(default)]
+    pub groups: Vec<String>,
+    #[serde(default)]
+    pub attributes: HashMap<String, String>,
+    #[serde(default)]
+    pub claims: HashMap<String, String>,
+    pub peer: Option<IpAddr>,
+    pub at: Option<DateTime<Utc>>,
+    pub expect: Verdict,
+    pub reason: Option<String>,
+    pub inject: Option<HashMap<String, String>>,
+}
+
+fn http() -> String {
+    "http".into()
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Suite {
+    pub cases: Vec<Case>,
+}
+
+/// A case whose decision differed from the expectation, with the trace to show why
+#[derive(Debug, Clone)]
+pub struct Failure {
+    pub case: String,
+    pub problems: Vec<String>,
+    pub explanation: Explanation,
+}
+
+impl Suite {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let txt = std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        let suite = match path.extension().and_then(|e| e.to_str()) {
+            Some("yaml" | "yml") => serde_yaml::from_str(&txt)?,
+            _ => toml::from_str(&txt)?,
+        };
+        Ok(suite)
+    }
+
+    /// Evaluate every case against `policy`; an empty result means the suite passed
+    pub fn run(&self, policy: &dyn PolicyEngine) -> Vec<Failure> {
+        self.cases.iter().filter_map(|c| c.check(policy)).collect()
+    }
+}
+
+impl Case {
+    fn check(&self, policy: &dyn PolicyEngine) -> Option<Failure> {
+        let cx = Context {
+            protocol: &self.protocol,
+            resource: &self.resource,
+            groups: &self.groups,
+            claims: &self.claims,
+            attributes: &self.attributes,
+            peer: self.peer,
+            now: self.at.unwrap_or_else(Utc::now),
+        };
+        let x = policy.explain(&cx);
+        let d = &x.decision;
+        let mut problems = Vec::new();
+        let got = if d.allow { Verdict::Allow } else { Verdict::Deny };
+        if got != self.expect {
+            problems.push(format!("expected {:?}, got {got:?} ({})", self.expect, d.reason));
+        }
+        if let Some(reason) = self.reason.as_ref().filter(|r| **r != d.reason) {
+            problems.push(format!("expected reason {reason:?}, got {:?}", d.reason));
+        }
+        if let Some(want) = &self.inject {
+            let rendered: HashMap<String, String> = d
+                .inject
+                .iter()
+                .flatten()
+                .map(|(k, v)| (k.clone(), template::render(v, &self.claims, &self.groups)))
+                .collect();
+            if *want != rendered {
+                problems.push(format!("expected inject {}, got {}", sorted(want), sorted(&rendered)));
+            }
+        }
+        (!problems.is_empty()).then(|| Failure { case: self.name.clone(), problems, explanation: x })
+    }
+}
+
+fn sorted(m: &HashMap<String, String>) -> String {
+    let mut kv: Vec<_> = m.iter().map(|(k, v)| format!("{k}={v:?}")).collect();
+    kv.sort();
+    format!("{{{}}}", kv.join(", "))
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "FAIL {}", self.case)?;
+        for p in &self.problems {
+            writeln!(f, "  {p}")?;
+        }
+        for r in &self.explanation.rules {
+            let mark = if r.decisive { '*' } else { ' ' };
+            let at = r.source.as_ref().map(|s| format!(" ({s})")).unwrap_or_default();
+            writeln!(f, "  {mark} {}{at}: {}", r.rule, r.summary())?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,172 @@
+//! Identity templates for injected header values.
+//!
+//! `{{ ... }}` interpolates verified identity data into an `inject` value; everything else is
+//! literal:
+//!
+//! ```toml
+//! [rules.inject]
+//! "X-User-Sub" = "{{claims.sub}}"
+//! "X-User-Email" = "{{ claims.email | default('unknown') }}"
+//! "X-User-Groups" = "{{groups|join(',')}}"
+//! ```
+//!
+//! Variables are `claims.<name>` (the claims the PDP projects for the session) and `groups`.
+//! Filters are `join('<sep>')`, `default('<value>')`, `lower` and `upper`. A missing claim renders
+//! empty so the header is still set, replacing anything the client sent. Interpolated values are
+//! percent-encoded where they would not be valid in a header (control characters, non-ASCII, `%`).
+
+use anyhow::{anyhow, bail, Result};
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+use std::collections::HashMap;
+
+/// Bytes escaped in interpolated values; non-ASCII is always escaped
+const UNSAFE: &AsciiSet = &CONTROLS.add(b'%');
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Var {
+    Claim(String),
+    Groups,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Filter {
+    Join(String),
+    Default(String),
+    Lower,
+    Upper,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    Expr(Var, Vec<Filter>),
+}
+
+/// A parsed `inject` value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template(Vec<Part>);
+
+impl Template {
+    pub fn parse(s: &str) -> Result<Self> {
+        let mut parts = Vec::new();
+        let mut rest = s;
+        while let Some(open) = rest.find("{{") {
+            literal(&rest[..open], &mut parts)?;
+            let close = rest[open..].find("}}").ok_or_else(|| anyhow!("unclosed {{{{ in {s:?}"))? + open;
+            parts.push(expr(&rest[open + 2..close])?);
+            rest = &rest[close + 2..];
+        }
+        literal(rest, &mut parts)?;
+        Ok(Self(parts))
+    }
+
+    /// Render against a session's claims and groups; the result is always a valid header value
+    pub fn render(&self, claims: &HashMap<String, String>, groups: &[String]) -> String {
+        let mut out = String::new();
+        for part in &self.0 {
+            match part {
+                Part::Literal(s) => out.push_str(s),
+                Part::Expr(var, filters) => {
+                    let mut list = match var {
+                        Var::Claim(name) => claims.get(name).into_iter().cloned().collect(),
+                        Var::Groups => groups.to_vec(),
+                    };
+                    let mut sep = ",".to_string();
+                    for f in filters {
+                        match f {
+                            Filter::Join(s) => sep = s.clone(),
+                            Filter::Default(d) if list.iter().all(String::is_empty) => list = vec![d.clone()],
+                            Filter::Default(_) => {}
+                            Filter::Lower => list.iter_mut().for_each(|v| *v = v.to_lowercase()),
+                            Filter::Upper => list.iter_mut().for_each(|v| *v = v.to_uppercase()),
+                        }
+                    }
+                    out.extend(utf8_percent_encode(&list.join(&sep), UNSAFE));
+                }
+            }
+        }
+        // surrounding whitespace is not part of a header value
+        out.trim_matches([' ', '\t']).to_string()
+    }
+}
+
+/// Render `template` for a session; values that fail to parse (rejected at load) render empty
+pub fn render(template: &str, claims: &HashMap<String, String>, groups: &[String]) -> String {
+    Template::parse(template).map(|t| t.render(claims, groups)).unwrap_or_default()
+}
+
+/// Whether `name` is a valid header name (an RFC 9110 token)
+pub fn is_header_name(name: &str) -> bool {
+    !name.is_empty() && name.bytes().all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+fn literal(s: &str, parts: &mut Vec<Part>) -> Result<()> {
+    if s.is_empty() {
+        return Ok(());
+    }
+    if let Some(c) = s.chars().find(|&c| !(c == ' ' || c == '\t' || c.is_ascii_graphic())) {
+        bail!("{c:?} is not allowed in a header value");
+    }
+    parts.push(Part::Literal(s.to_string()));
+    Ok(())
+}
+
+fn expr(s: &str) -> Result<Part> {
+    let mut segs = pipes(s).into_iter().map(str::trim);
+    let var = match segs.next().unwrap_or_default() {
+        "groups" => Var::Groups,
+        v => match v.strip_prefix("claims.") {
+            Some(name) if !name.is_empty() => Var::Claim(name.to_string()),
+            _ => bail!("unknown template variable {v:?}; expected claims.<name> or groups"),
+        },
+    };
+    let filters = segs.map(filter).collect::<Result<_>>()?;
+    Ok(Part::Expr(var, filters))
+}
+
+/// Split an expression at the `|`s between filters, leaving quoted arguments whole
+fn pipes(s: &str) -> Vec<&str> {
+    let mut segs = Vec::new();
+    let (mut start, mut quote) = (0, None);
+    for (i, c) in s.char_indices() {
+        match (quote, c) {
+            (None, '\'' | '"') => quote = Some(c),
+            (Some(q), c) if c == q => quote = None,
+            (None, '|') => {
+                segs.push(&s[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    segs.push(&s[start..]);
+    segs
+}
+
+fn filter(s: &str) -> Result<Filter> {
+    let (name, arg) = match s.split_once('(') {
+        Some((name, rest)) => {
+            let arg = rest.strip_suffix(')').map(str::trim).ok_or_else(|| anyhow!("unclosed ( in filter {s:?}"))?;
+            (name.trim(), Some(quoted(arg)?))
+        }
+        None => (s, None),
+    };
+    match (name, arg) {
+        ("join", Some(sep)) => Ok(Filter::Join(sep)),
+        ("default", Some(d)) => Ok(Filter::Default(d)),
+        ("lower", None) => Ok(Filter::Lower),
+        ("upper", None) => Ok(Filter::Upper),
+        _ => bail!("unknown template filter {s:?}"),
+    }
+}
+
+/// A `'...'` or `"..."` filter argument, which must itself be valid in a header value
+fn quoted(s: &str) -> Result<String> {
+    let inner = ['\'', '"']
+        .into_iter()
+        .find_map(|q| s.strip_prefix(q).and_then(|r| r.strip_suffix(q)))
+        .ok_or_else(|| anyhow!("filter argument {s:?} must be quoted"))?;
+    let mut check = Vec::new();
+    literal(inner, &mut check)?;
+    Ok(inner.to_string())
+}
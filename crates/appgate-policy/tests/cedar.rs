@@ -0,0 +1,137 @@
+//! Tests for the Cedar engine and the `PolicyEngine` abstraction.
+
+use appgate_policy::{
+    cedar::CedarPolicy,
+    engine::{Engine, EngineKind, PolicyEngine},
+    suite::Suite,
+    Context, Decision,
+};
+use std::collections::HashMap;
+
+const FOUNDRY: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../../config/policy/foundry.cedar");
+
+fn groups(gs: &[&str]) -> Vec<String> {
+    gs.iter().map(|g| g.to_string()).collect()
+}
+
+fn decide(p: &dyn PolicyEngine, resource: &str, groups: &[String], claims: &HashMap<String, String>, attributes: &HashMap<String, String>) -> Decision {
+    p.evaluate(&Context { protocol: "http", resource, groups, claims, attributes, peer: None, now: chrono::Utc::now() })
+}
+
+#[test]
+fn shipped_cedar_policy_matches_the_toml_one() {
+    let (cedar, findings) = Engine::load(EngineKind::Cedar, FOUNDRY).unwrap();
+    assert!(findings.is_empty());
+    assert_eq!(cedar.rule_names(), ["foundry-admin", "foundry-players"]);
+    let toml = Engine::load(EngineKind::Toml, concat!(env!("CARGO_MANIFEST_DIR"), "/../../config/policy/foundry.toml")).unwrap().0;
+    let none = HashMap::new();
+    for gs in [&[][..], &["foundry-players"], &["foundry-players", "foundry-admin"], &["foundry-admin"]] {
+        for resource in ["http://foundry/", "http://foundry/game", "http://other/"] {
+            let (c, t) = (decide(&cedar, resource, &groups(gs), &none, &none), decide(&toml, resource, &groups(gs), &none, &none));
+            assert_eq!(c.allow, t.allow, "{gs:?} {resource}");
+            assert_eq!(c.inject, t.inject, "{gs:?} {resource}");
+            if c.allow {
+                assert_eq!(c.reason, t.reason);
+            }
+        }
+    }
+
+    // the same suite passes under both engines
+    let suite = Suite::load(concat!(env!("CARGO_MANIFEST_DIR"), "/../../config/policy/foundry.tests.toml")).unwrap();
+    let failures = suite.run(&cedar);
+    assert!(failures.is_empty(), "{failures:?}");
+}
+
+#[test]
+fn maps_claims_methods_resources_and_context_into_cedar() {
+    let p: CedarPolicy = r#"
+        @id("alice-reads-reports")
+        @inject_x_user("{{claims.email}}")
+        permit (principal == AppGate::User::"alice", action == AppGate::Action::"GET", resource)
+        when { resource.host == "app" && resource.path like "/reports/*" && principal.email like "*@example.com" };
+
+        @id("staff-writes")
+        permit (principal, action in AppGate::Action::"http", resource)
+        when { principal.groups.contains("staff") && context has "header.x-csrf" };
+
+        @id("no-office-printer")
+        forbid (principal, action, resource == AppGate::Resource::"sni:printer.office:443");
+
+        @id("tcp-from-vpn")
+        permit (principal, action == AppGate::Action::"tcp", resource)
+        when { context.peer.isInRange(ip("10.8.0.0/16")) };
+    "#
+    .parse()
+    .unwrap();
+    let claims: HashMap<String, String> = [("sub", "alice"), ("email", "alice@example.com")].map(|(k, v)| (k.into(), v.into())).into();
+    let get: HashMap<String, String> = [("method".to_string(), "get".to_string())].into();
+
+    let d = decide(&p, "http://APP/reports/q3", &[], &claims, &get);
+    assert_eq!(d.reason, "policy: alice-reads-reports");
+    assert_eq!(d.inject.unwrap()["x-user"], "{{claims.email}}");
+    assert_eq!(decide(&p, "http://app/admin", &[], &claims, &get).reason, "default-deny");
+    let post: HashMap<String, String> = [("method".to_string(), "POST".to_string())].into();
+    assert!(!decide(&p, "http://app/reports/q3", &[], &claims, &post).allow);
+
+    let staff = groups(&["staff"]);
+    assert!(!decide(&p, "http://app/items", &staff, &HashMap::new(), &post).allow);
+    let csrf: HashMap<String, String> = [("method", "POST"), ("header.x-csrf", "1")].map(|(k, v)| (k.into(), v.into())).into();
+    assert_eq!(decide(&p, "http://app/items", &staff, &HashMap::new(), &csrf).reason, "policy: staff-writes");
+
+    let none = HashMap::new();
+    let tcp = |resource, peer: &str| {
+        let cx = Context { protocol: "tcp", resource, groups: &[], claims: &none, attributes: &none, peer: peer.parse().ok(), now: chrono::Utc::now() };
+        p.evaluate(&cx).reason
+    };
+    assert_eq!(tcp("sni:db:5432", "10.8.1.2"), "policy: tcp-from-vpn");
+    assert_eq!(tcp("sni:db:5432", "192.0.2.1"), "default-deny");
+    assert_eq!(tcp("sni:db:5432", "unknown"), "default-deny");
+    assert_eq!(tcp("sni:printer.office:443", "10.8.1.2"), "deny: no-office-printer");
+
+    let x = p.explain(&Context { protocol: "tcp", resource: "sni:printer.office:443", groups: &[], claims: &none, attributes: &none, peer: "10.8.1.2".parse().ok(), now: chrono::Utc::now() });
+    let summaries: Vec<(String, String)> = x.rules.iter().map(|r| (r.rule.clone(), r.summary())).collect();
+    assert_eq!(
+        summaries,
+        [
+            ("alice-reads-reports".to_string(), "condition failed: scope/when/unless".to_string()),
+            ("staff-writes".into(), "condition failed: scope/when/unless".into()),
+            ("no-office-printer".into(), "decided".into()),
+            ("tcp-from-vpn".into(), "applies, but another rule decided".into()),
+        ]
+    );
+}
+
+#[test]
+fn forbid_that_fails_to_evaluate_denies() {
+    let p: CedarPolicy = r#"
+        @id("everyone")
+        permit (principal, action, resource);
+
+        @id("no-contractors")
+        forbid (principal, action, resource)
+        when { principal.email like "*@contractor.example" };
+    "#
+    .parse()
+    .unwrap();
+    let none = HashMap::new();
+    let claims = |email: &str| -> HashMap<String, String> { [("sub", "alice"), ("email", email)].map(|(k, v)| (k.into(), v.into())).into() };
+
+    assert_eq!(decide(&p, "http://app/", &[], &claims("alice@example.com"), &none).reason, "policy: everyone");
+    assert_eq!(decide(&p, "http://app/", &[], &claims("bob@contractor.example"), &none).reason, "deny: no-contractors");
+    // without an email claim the forbid errors instead of being satisfied
+    let d = decide(&p, "http://app/", &[], &none, &none);
+    assert!(!d.allow);
+    assert!(d.reason.starts_with("cedar: no-contractors: "), "{}", d.reason);
+    let x = p.explain(&Context { protocol: "http", resource: "http://app/", groups: &[], claims: &none, attributes: &none, peer: None, now: chrono::Utc::now() });
+    assert!(x.rules[1].decisive);
+}
+
+#[test]
+fn rejects_invalid_cedar_policies() {
+    assert!("permit (principal, action, resource".parse::<CedarPolicy>().is_err());
+    let dup = r#"@id("a") permit (principal, action, resource); @id("a") forbid (principal, action, resource);"#;
+    assert!(dup.parse::<CedarPolicy>().err().unwrap().to_string().contains("defined twice"));
+    let header = r#"@inject_x_role("{{nope}}") permit (principal, action, resource);"#;
+    assert!(header.parse::<CedarPolicy>().is_err());
+    assert!(r#"@priority("high") permit (principal, action, resource);"#.parse::<CedarPolicy>().is_err());
+}
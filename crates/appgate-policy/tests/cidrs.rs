@@ -0,0 +1,86 @@
+//! Tests for `allow_cidrs` / `deny_cidrs` source-address restrictions.
+
+use appgate_policy::{Context, Policy};
+use std::{collections::HashMap, net::IpAddr};
+
+fn policy() -> Policy {
+    toml::from_str(
+        r#"
+        [[rules]]
+        name = "players"
+        protocol = "http"
+        resource = "http://foundry/"
+        require_groups = ["foundry-players"]
+
+        [[rules]]
+        name = "admin"
+        protocol = "http"
+        resource = "http://foundry/admin"
+        require_groups = ["foundry-admin"]
+        allow_cidrs = ["10.8.0.0/16", "fd00:8::/32"]
+
+        [[rules]]
+        name = "admin-off-vpn"
+        protocol = "http"
+        resource = "http://foundry/admin"
+        effect = "deny"
+        deny_cidrs = ["10.8.0.0/16", "fd00:8::/32"]
+        "#,
+    )
+    .unwrap()
+}
+
+fn eval(p: &Policy, resource: &str, peer: Option<&str>) -> String {
+    let groups = vec!["foundry-admin".to_string(), "foundry-players".to_string()];
+    let attributes = HashMap::new();
+    let peer = peer.map(|s| s.parse::<IpAddr>().unwrap());
+    p.evaluate(&Context { protocol: "http", resource, groups: &groups, claims: &HashMap::new(), attributes: &attributes, peer, now: chrono::Utc::now() }).reason
+}
+
+#[test]
+fn admin_routes_only_from_vpn_ranges() {
+    let p = policy();
+    assert_eq!(eval(&p, "http://foundry/admin/users", Some("10.8.3.4")), "policy: admin");
+    assert_eq!(eval(&p, "http://foundry/admin/users", Some("fd00:8::1")), "policy: admin");
+    // dual-stack listeners report IPv4 clients as mapped IPv6
+    assert_eq!(eval(&p, "http://foundry/admin/users", Some("::ffff:10.8.3.4")), "policy: admin");
+
+    assert_eq!(eval(&p, "http://foundry/admin/users", Some("203.0.113.9")), "deny: admin-off-vpn");
+    // an unknown address fails closed
+    assert_eq!(eval(&p, "http://foundry/admin/users", None), "deny: admin-off-vpn");
+}
+
+#[test]
+fn player_routes_stay_public() {
+    let p = policy();
+    for peer in [Some("203.0.113.9"), Some("10.8.3.4"), None] {
+        assert_eq!(eval(&p, "http://foundry/game", peer), "policy: players");
+    }
+}
+
+#[test]
+fn allow_cidrs_exclusions_and_malformed_ranges() {
+    let p: Policy = toml::from_str(
+        r#"
+        [[rules]]
+        name = "office"
+        protocol = "http"
+        resource = "http://app/"
+        allow_cidrs = ["192.168.0.0/16"]
+        deny_cidrs = ["192.168.99.0/24"]
+        "#,
+    )
+    .unwrap();
+    assert_eq!(eval(&p, "http://app/", Some("192.168.1.1")), "policy: office");
+    assert_eq!(eval(&p, "http://app/", Some("192.168.99.1")), "default-deny");
+    assert_eq!(eval(&p, "http://app/", None), "default-deny");
+
+    let bad = r#"
+        [[rules]]
+        name = "bad"
+        protocol = "http"
+        resource = "http://app/"
+        allow_cidrs = ["10.8.0.0/33"]
+    "#;
+    assert!(toml::from_str::<Policy>(bad).is_err());
+}
@@ -0,0 +1,92 @@
+//! Tests for `[rules.when]` conditions over request attributes.
+
+use appgate_policy::{Context, Policy};
+use std::collections::HashMap;
+
+fn groups(gs: &[&str]) -> Vec<String> {
+    gs.iter().map(|g| g.to_string()).collect()
+}
+
+fn attrs(kv: &[(&str, &str)]) -> HashMap<String, String> {
+    kv.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+}
+
+fn eval(p: &Policy, gs: &[&str], kv: &[(&str, &str)]) -> (bool, String) {
+    let (groups, attributes) = (groups(gs), attrs(kv));
+    let cx = Context { protocol: "http", resource: "http://foundry/api/items", groups: &groups, claims: &HashMap::new(), attributes: &attributes, peer: None, now: chrono::Utc::now() };
+    let d = p.evaluate(&cx);
+    (d.allow, d.reason)
+}
+
+#[test]
+fn read_only_players_and_admin_only_writes() {
+    let p: Policy = toml::from_str(
+        r#"
+        [[rules]]
+        name = "players-read"
+        protocol = "http"
+        resource = "http://foundry/"
+        require_groups = ["foundry-players"]
+        [rules.when]
+        method = ["GET", "HEAD"]
+
+        [[rules]]
+        name = "admin-write"
+        protocol = "http"
+        resource = "http://foundry/"
+        require_groups = ["foundry-admin"]
+        "#,
+    )
+    .unwrap();
+
+    assert_eq!(eval(&p, &["foundry-players"], &[("method", "GET")]), (true, "policy: players-read".into()));
+    assert!(eval(&p, &["foundry-players"], &[("method", "HEAD")]).0);
+    assert_eq!(eval(&p, &["foundry-players"], &[("method", "POST")]), (false, "missing group".into()));
+    assert_eq!(eval(&p, &["foundry-players", "foundry-admin"], &[("method", "DELETE")]), (true, "policy: admin-write".into()));
+    // a rule with conditions never matches a request that lacks the attributes
+    assert!(!p.decide("http", "http://foundry/", &groups(&["foundry-players"])).0);
+}
+
+#[test]
+fn equals_glob_presence_and_exclusion() {
+    let p: Policy = toml::from_str(
+        r#"
+        [[rules]]
+        name = "xhr-api"
+        protocol = "http"
+        resource = "http://foundry/api"
+        [rules.when]
+        "header.x-requested-with" = "XMLHttpRequest"
+        "query.debug" = { present = false }
+        path = { glob = "/api/*", not = ["/api/admin"] }
+        "#,
+    )
+    .unwrap();
+
+    let ok = [("header.x-requested-with", "XMLHttpRequest"), ("path", "/api/items")];
+    assert!(eval(&p, &[], &ok).0);
+
+    let mut debug = ok.to_vec();
+    debug.push(("query.debug", "1"));
+    assert!(!eval(&p, &[], &debug).0);
+
+    assert!(!eval(&p, &[], &[("header.x-requested-with", "fetch"), ("path", "/api/items")]).0);
+    assert!(!eval(&p, &[], &[("header.x-requested-with", "XMLHttpRequest"), ("path", "/api/admin")]).0);
+    assert!(!eval(&p, &[], &[("header.x-requested-with", "XMLHttpRequest"), ("path", "/other")]).0);
+    assert!(!eval(&p, &[], &[("path", "/api/items")]).0);
+}
+
+#[test]
+fn unknown_condition_fields_are_rejected() {
+    let err = toml::from_str::<Policy>(
+        r#"
+        [[rules]]
+        name = "typo"
+        protocol = "http"
+        resource = "http://foundry/"
+        [rules.when]
+        method = { globs = "G*" }
+        "#,
+    );
+    assert!(err.is_err());
+}
@@ -0,0 +1,85 @@
+//! Tests for decision traces from `Policy::explain`.
+
+use appgate_policy::{Context, Effect, Policy};
+use std::collections::HashMap;
+
+fn policy() -> Policy {
+    toml::from_str(
+        r#"
+        [[rules]]
+        name = "admin"
+        protocol = "http"
+        resource = "http://foundry/admin"
+        require_groups = ["foundry-admin"]
+
+        [[rules]]
+        name = "players-read"
+        protocol = "http"
+        resource = "http://foundry/"
+        require_groups = ["foundry-players"]
+        [rules.when]
+        method = ["GET", "HEAD"]
+
+        [[rules]]
+        name = "ssh"
+        protocol = "tcp"
+        resource = "sni:foundry:22"
+
+        [[rules]]
+        name = "no-debug"
+        protocol = "http"
+        resource = "http://foundry/"
+        effect = "deny"
+        [rules.when]
+        "query.debug" = { present = true }
+        "#,
+    )
+    .unwrap()
+}
+
+#[test]
+fn why_alice_was_denied_admin() {
+    let p = policy();
+    let groups = vec!["foundry-players".to_string()];
+    let attributes: HashMap<String, String> = [("method".to_string(), "POST".to_string())].into();
+    let cx = Context { protocol: "http", resource: "http://foundry/admin", groups: &groups, claims: &HashMap::new(), attributes: &attributes, peer: None, now: chrono::Utc::now() };
+    let x = p.explain(&cx);
+
+    assert_eq!(x.decision, p.evaluate(&cx));
+    assert_eq!(x.decision.reason, "missing group");
+    let names: Vec<&str> = x.rules.iter().map(|r| r.rule.as_str()).collect();
+    assert_eq!(names, ["admin", "players-read", "ssh", "no-debug"]);
+
+    let [admin, players, ssh, debug] = &x.rules[..] else { unreachable!() };
+    assert!(admin.matched() && !admin.groups);
+    assert_eq!(admin.summary(), "caller lacks required groups");
+
+    assert!(players.resource && players.groups && !players.matched());
+    assert_eq!(players.conditions[0].value.as_deref(), Some("POST"));
+    assert_eq!(players.summary(), "condition failed: method");
+
+    assert_eq!(ssh.summary(), "protocol differs");
+
+    assert_eq!(debug.effect, Effect::Deny);
+    assert!(debug.conditions[0].value.is_none() && !debug.conditions[0].passed);
+    assert!(x.rules.iter().all(|r| !r.decisive));
+}
+
+#[test]
+fn marks_the_deciding_rule() {
+    let p = policy();
+    let groups = vec!["foundry-admin".to_string(), "foundry-players".to_string()];
+    let get: HashMap<String, String> = [("method".to_string(), "GET".to_string())].into();
+    let now = chrono::Utc::now();
+
+    let x = p.explain(&Context { protocol: "http", resource: "http://foundry/admin/x", groups: &groups, claims: &HashMap::new(), attributes: &get, peer: None, now });
+    assert_eq!(x.decision.reason, "policy: admin");
+    assert!(x.rules[0].decisive && !x.rules[1].decisive);
+    assert_eq!(x.rules[0].summary(), "decided");
+    assert_eq!(x.rules[1].summary(), "applies, but another rule decided");
+
+    let debug: HashMap<String, String> = [("method".to_string(), "GET".to_string()), ("query.debug".to_string(), "1".to_string())].into();
+    let x = p.explain(&Context { protocol: "http", resource: "http://foundry/", groups: &groups, claims: &HashMap::new(), attributes: &debug, peer: None, now });
+    assert_eq!(x.decision.reason, "deny: no-debug");
+    assert!(x.rules[3].decisive);
+}
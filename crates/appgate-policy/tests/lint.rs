@@ -0,0 +1,146 @@
+//! Tests for the policy linter.
+
+use appgate_policy::{
+    lint::{lint, Severity},
+    Policy,
+};
+
+fn findings(src: &str) -> Vec<(Severity, String, String)> {
+    let p: Policy = toml::from_str(src).unwrap();
+    lint(&p).into_iter().map(|f| (f.severity, f.rule, f.message)).collect()
+}
+
+#[test]
+fn shipped_foundry_policy_is_clean() {
+    let p = Policy::load(concat!(env!("CARGO_MANIFEST_DIR"), "/../../config/policy/foundry.toml")).unwrap();
+    assert_eq!(lint(&p), vec![]);
+}
+
+#[test]
+fn flags_rules_shadowed_by_broader_earlier_matches() {
+    let f = findings(
+        r#"
+        [[rules]]
+        name = "everyone"
+        protocol = "http"
+        resource = "http://app/"
+        match = "prefix"
+        priority = 10
+
+        [[rules]]
+        name = "admins"
+        protocol = "http"
+        resource = "http://app/admin"
+        require_groups = ["admin"]
+        inject = { "X-Role" = "admin" }
+
+        [[rules]]
+        name = "blocked"
+        protocol = "http"
+        resource = "http://app/old/**"
+        match = "glob"
+        effect = "deny"
+
+        [[rules]]
+        name = "old-reports"
+        protocol = "http"
+        resource = "http://app/old/reports/*.pdf"
+        match = "glob"
+        require_groups = ["staff"]
+
+        [[rules]]
+        name = "api-when-get"
+        protocol = "http"
+        resource = "http://api/"
+        match = "prefix"
+        when = { method = "GET" }
+        priority = 10
+
+        [[rules]]
+        name = "api-read-only"
+        protocol = "http"
+        resource = "http://api/items"
+        when = { method = "GET", "header.accept" = "application/json" }
+        "#,
+    );
+    let errors: Vec<_> = f.iter().filter(|(s, ..)| *s == Severity::Error).map(|(_, r, m)| (r.as_str(), m.as_str())).collect();
+    assert_eq!(errors.len(), 3, "{f:?}");
+    assert_eq!(errors[0].0, "admins");
+    assert!(errors[0].1.contains("shadowed by rule everyone"), "{}", errors[0].1);
+    assert_eq!(errors[1].0, "old-reports");
+    assert!(errors[1].1.contains("deny rule blocked"), "{}", errors[1].1);
+    assert_eq!(errors[2].0, "api-read-only");
+}
+
+#[test]
+fn leaves_overlapping_but_distinct_rules_alone() {
+    let f = findings(
+        r#"
+        [[rules]]
+        name = "players"
+        protocol = "http"
+        resource = "http://foundry/"
+        require_groups = ["foundry-players"]
+
+        [[rules]]
+        name = "admin"
+        protocol = "http"
+        resource = "http://foundry/"
+        require_groups = ["foundry-admin"]
+
+        [[rules]]
+        name = "office-hours"
+        protocol = "http"
+        resource = "http://app/"
+        match = "prefix"
+        priority = 10
+        schedule = { days = ["mon", "tue"] }
+
+        [[rules]]
+        name = "app"
+        protocol = "http"
+        resource = "http://app/"
+        match = "prefix"
+        inject = { "X-After-Hours" = "1" }
+
+        [[rules]]
+        name = "deny-post"
+        protocol = "http"
+        resource = "http://app/"
+        match = "prefix"
+        effect = "deny"
+        when = { method = "POST" }
+        "#,
+    );
+    assert_eq!(f, vec![]);
+}
+
+#[test]
+fn flags_unknown_protocols_and_open_sensitive_paths() {
+    let f = findings(
+        r#"
+        [[rules]]
+        name = "web"
+        protocol = "http"
+        resource = "http://app/Admin/users"
+        require_groups = []
+
+        [[rules]]
+        name = "web-tls"
+        protocol = "https"
+        resource = "http://app/"
+
+        [[rules]]
+        name = "metrics-from-monitoring"
+        protocol = "http"
+        resource = "http://app/metrics"
+        allow_cidrs = ["10.9.0.0/16"]
+        "#,
+    );
+    assert_eq!(
+        f.iter().map(|(s, r, _)| (*s, r.as_str())).collect::<Vec<_>>(),
+        vec![(Severity::Warning, "web"), (Severity::Error, "web-tls")]
+    );
+    assert!(f[0].2.contains("sensitive path"));
+    assert!(f[1].2.contains("unknown protocol \"https\""));
+}
@@ -0,0 +1,126 @@
+//! Tests for resource normalisation and the exact/prefix/glob/regex match kinds.
+
+use appgate_policy::{resource::Resource, Policy};
+
+fn policy(rules: &str) -> Policy {
+    let p: Policy = toml::from_str(rules).unwrap();
+    p.validate().unwrap();
+    p
+}
+
+fn allowed(p: &Policy, resource: &str) -> bool {
+    p.decide("http", resource, &[]).0
+}
+
+#[test]
+fn normalises_paths_before_matching() {
+    let canon = |s: &str| Resource::parse(s).to_canonical();
+    assert_eq!(canon("HTTP://Foundry.Example.COM./a/./b/../c//d?x=1#f"), "http://foundry.example.com/a/c/d");
+    assert_eq!(canon("http://foundry/%2e%2e/admin"), "http://foundry/admin");
+    assert_eq!(canon("http://foundry/../../etc/passwd"), "http://foundry/etc/passwd");
+    assert_eq!(canon("http://foundry/a%2Fb\\..\\c"), "http://foundry/a/c");
+    assert_eq!(canon("http://user@foundry:8443/"), "http://foundry:8443/");
+    assert_eq!(canon("sni:db.internal:5432"), "sni:db.internal:5432");
+}
+
+#[test]
+fn prefix_matches_whole_host_and_path_segments() {
+    let p = policy(
+        r#"
+        [[rules]]
+        name = "api"
+        protocol = "http"
+        resource = "http://foundry/api"
+        require_groups = []
+        "#,
+    );
+    assert!(allowed(&p, "http://foundry/api"));
+    assert!(allowed(&p, "http://foundry/api/v1/items"));
+    assert!(allowed(&p, "http://FOUNDRY:8080/x/../api/v1"));
+    assert!(!allowed(&p, "http://foundry/apix"));
+    assert!(!allowed(&p, "http://foundryx/api"));
+    assert!(!allowed(&p, "http://foundry/api/../admin"));
+    assert!(!allowed(&p, "http://foundry/api/%2e%2e/admin"));
+    assert!(!allowed(&p, "https://foundry/api"));
+}
+
+#[test]
+fn exact_requires_the_same_path() {
+    let p = policy(
+        r#"
+        [[rules]]
+        name = "health"
+        protocol = "http"
+        resource = "http://foundry:8080/healthz"
+        match = "exact"
+        require_groups = []
+        "#,
+    );
+    assert!(allowed(&p, "http://foundry:8080/healthz"));
+    assert!(allowed(&p, "http://foundry:8080/./healthz?verbose"));
+    assert!(!allowed(&p, "http://foundry:8080/healthz/more"));
+    assert!(!allowed(&p, "http://foundry:9090/healthz"));
+    assert!(!allowed(&p, "http://foundry/healthz"));
+}
+
+#[test]
+fn globs_match_hosts_and_path_segments() {
+    let p = policy(
+        r#"
+        [[rules]]
+        name = "profiles"
+        protocol = "http"
+        resource = "http://*.example.com/users/*/profile"
+        match = "glob"
+        require_groups = []
+
+        [[rules]]
+        name = "api"
+        protocol = "http"
+        resource = "http://**.example.org/api/**"
+        match = "glob"
+        require_groups = []
+        "#,
+    );
+    assert!(allowed(&p, "http://app.example.com/users/42/profile"));
+    assert!(!allowed(&p, "http://example.com/users/42/profile"));
+    assert!(!allowed(&p, "http://a.b.example.com/users/42/profile"));
+    assert!(!allowed(&p, "http://app.example.com/users/42/7/profile"));
+    assert!(!allowed(&p, "http://app.example.com.evil.net/users/42/profile"));
+
+    assert!(allowed(&p, "http://example.org/api"));
+    assert!(allowed(&p, "http://eu.svc.example.org/api/v1/items/9"));
+    assert!(!allowed(&p, "http://example.org/apiv1"));
+    assert!(!allowed(&p, "http://example.org/api/../admin"));
+}
+
+#[test]
+fn regex_is_anchored_on_the_normalised_resource() {
+    let p = policy(
+        r#"
+        [[rules]]
+        name = "reports"
+        protocol = "http"
+        resource = 'http://reports/(daily|weekly)/\d{4}'
+        match = "regex"
+        require_groups = []
+        "#,
+    );
+    assert!(allowed(&p, "http://reports/daily/2024"));
+    assert!(allowed(&p, "http://REPORTS/x/../weekly/2024"));
+    assert!(!allowed(&p, "http://reports/daily/2024/extra"));
+    assert!(!allowed(&p, "http://evil/http://reports/daily/2024"));
+
+    let bad: Policy = toml::from_str(
+        r#"
+        [[rules]]
+        name = "broken"
+        protocol = "http"
+        resource = "http://x/("
+        match = "regex"
+        require_groups = []
+        "#,
+    )
+    .unwrap();
+    assert!(bad.validate().unwrap_err().to_string().contains("broken"));
+}
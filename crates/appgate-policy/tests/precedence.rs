@@ -0,0 +1,125 @@
+//! Tests for rule precedence, deny rules and any-of/all-of group requirements.
+
+use appgate_policy::Policy;
+
+fn groups(gs: &[&str]) -> Vec<String> {
+    gs.iter().map(|g| g.to_string()).collect()
+}
+
+#[test]
+fn shipped_foundry_policy_admits_players_and_admins() {
+    let p = Policy::load(concat!(env!("CARGO_MANIFEST_DIR"), "/../../config/policy/foundry.toml")).unwrap();
+
+    let (allow, inject, reason) = p.decide("http", "http://foundry/game", &groups(&["foundry-players"]));
+    assert!(allow, "{reason}");
+    assert_eq!(reason, "policy: foundry-players");
+    assert!(inject.is_none());
+
+    let (allow, inject, reason) = p.decide("http", "http://foundry/", &groups(&["foundry-players", "foundry-admin"]));
+    assert!(allow);
+    assert_eq!(reason, "policy: foundry-admin");
+    assert_eq!(inject.unwrap()["X-Role"], "admin");
+
+    assert_eq!(p.decide("http", "http://foundry/", &[]).2, "missing group");
+    assert_eq!(p.decide("http", "http://other/", &groups(&["foundry-admin"])).2, "default-deny");
+}
+
+#[test]
+fn more_specific_rules_take_precedence_unless_priority_says_otherwise() {
+    let p: Policy = toml::from_str(
+        r#"
+        [[rules]]
+        name = "site"
+        protocol = "http"
+        resource = "http://app/"
+        require_groups = ["staff"]
+        inject = { "X-Area" = "site" }
+
+        [[rules]]
+        name = "api"
+        protocol = "http"
+        resource = "http://app/api"
+        require_groups = ["staff"]
+        inject = { "X-Area" = "api" }
+
+        [[rules]]
+        name = "pinned"
+        protocol = "http"
+        resource = "http://app/**"
+        match = "glob"
+        priority = 5
+        require_groups = ["ops"]
+        inject = { "X-Area" = "ops" }
+        "#,
+    )
+    .unwrap();
+    let area = |res: &str, gs: &[&str]| p.decide("http", res, &groups(gs)).1.map(|i| i["X-Area"].clone());
+    assert_eq!(area("http://app/api/v1", &["staff"]).as_deref(), Some("api"));
+    assert_eq!(area("http://app/home", &["staff"]).as_deref(), Some("site"));
+    assert_eq!(area("http://app/api/v1", &["staff", "ops"]).as_deref(), Some("ops"));
+}
+
+#[test]
+fn deny_rules_override_allows() {
+    let p: Policy = toml::from_str(
+        r#"
+        [[rules]]
+        name = "everything"
+        protocol = "http"
+        resource = "http://app/"
+        priority = 100
+        require_groups = ["staff"]
+
+        [[rules]]
+        name = "no-admin-for-contractors"
+        protocol = "http"
+        resource = "http://app/admin"
+        effect = "deny"
+        require_groups = ["contractors"]
+
+        [[rules]]
+        name = "block-debug"
+        protocol = "http"
+        resource = "http://app/debug/**"
+        match = "glob"
+        effect = "deny"
+        "#,
+    )
+    .unwrap();
+    let decide = |res: &str, gs: &[&str]| {
+        let (allow, _, reason) = p.decide("http", res, &groups(gs));
+        (allow, reason)
+    };
+    assert_eq!(decide("http://app/admin/users", &["staff"]), (true, "policy: everything".into()));
+    assert_eq!(decide("http://app/admin/users", &["staff", "contractors"]), (false, "deny: no-admin-for-contractors".into()));
+    assert_eq!(decide("http://app/debug/pprof", &["staff"]), (false, "deny: block-debug".into()));
+    assert_eq!(decide("http://app/%64ebug/pprof", &["staff"]), (false, "deny: block-debug".into()));
+}
+
+#[test]
+fn group_requirements_can_be_any_or_all() {
+    let p: Policy = toml::from_str(
+        r#"
+        [[rules]]
+        name = "either"
+        protocol = "tcp"
+        resource = "sni:db.internal:5432"
+        match = "exact"
+        require_groups = ["dba", "oncall"]
+        group_match = "any"
+
+        [[rules]]
+        name = "both"
+        protocol = "tcp"
+        resource = "sni:vault.internal:8200"
+        match = "exact"
+        require_groups = ["ops", "security"]
+        "#,
+    )
+    .unwrap();
+    let allowed = |res: &str, gs: &[&str]| p.decide("tcp", res, &groups(gs)).0;
+    assert!(allowed("sni:db.internal:5432", &["oncall"]));
+    assert!(!allowed("sni:db.internal:5432", &["ops"]));
+    assert!(allowed("sni:vault.internal:8200", &["ops", "security"]));
+    assert!(!allowed("sni:vault.internal:8200", &["ops"]));
+}
@@ -0,0 +1,102 @@
+//! Tests for `[rules.schedule]` time windows and the expiry they impose.
+
+use appgate_policy::{Context, Decision, Policy};
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+
+fn at(s: &str) -> DateTime<Utc> {
+    DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+}
+
+fn policy(schedule: &str) -> Policy {
+    toml::from_str(&format!(
+        r#"
+        [[rules]]
+        name = "game-night"
+        protocol = "http"
+        resource = "http://foundry/"
+        [rules.schedule]
+        {schedule}
+        "#
+    ))
+    .unwrap()
+}
+
+fn eval(p: &Policy, now: &str) -> Decision {
+    let attributes = HashMap::new();
+    p.evaluate(&Context { protocol: "http", resource: "http://foundry/game", groups: &[], claims: &HashMap::new(), attributes: &attributes, peer: None, now: at(now) })
+}
+
+#[test]
+fn access_only_inside_the_window_and_expiry_ends_with_it() {
+    let p = policy(r#"timezone = "Europe/Berlin"
+        days = ["fri"]
+        hours = ["19:00-23:30"]"#);
+
+    // Friday 2026-10-16, Berlin is UTC+2
+    let d = eval(&p, "2026-10-16T18:00:00Z");
+    assert_eq!(d.reason, "policy: game-night");
+    assert_eq!(d.until, Some(at("2026-10-16T21:30:00Z")));
+
+    assert_eq!(eval(&p, "2026-10-16T16:59:59Z").reason, "default-deny");
+    assert_eq!(eval(&p, "2026-10-16T21:30:00Z").reason, "default-deny");
+    // same time on a Saturday
+    assert!(!eval(&p, "2026-10-17T18:00:00Z").allow);
+}
+
+#[test]
+fn ranges_run_past_midnight_and_adjacent_windows_merge() {
+    let p = policy(r#"timezone = "Europe/Berlin"
+        days = ["sat"]
+        hours = ["22:00-02:00"]"#);
+    // Sunday 01:00 local still belongs to Saturday's window
+    assert_eq!(eval(&p, "2026-10-17T23:00:00Z").until, Some(at("2026-10-18T00:00:00Z")));
+    // Sunday 22:00 local is not a Saturday window
+    assert!(!eval(&p, "2026-10-18T20:00:00Z").allow);
+
+    // whole days back to back end at the close of the last one
+    let p = policy(r#"timezone = "Europe/Berlin"
+        days = ["fri", "sat"]"#);
+    assert_eq!(eval(&p, "2026-10-16T10:00:00Z").until, Some(at("2026-10-17T22:00:00Z")));
+}
+
+#[test]
+fn window_end_follows_daylight_saving_changes() {
+    // clocks go back at 03:00 CEST on Sunday 2026-10-25, so 04:00 local is 03:00Z
+    let p = policy(r#"timezone = "Europe/Berlin"
+        days = ["sat"]
+        hours = ["22:00-04:00"]"#);
+    assert_eq!(eval(&p, "2026-10-24T21:00:00Z").until, Some(at("2026-10-25T03:00:00Z")));
+}
+
+#[test]
+fn absolute_dates_bound_the_schedule() {
+    let p = policy(r#"not_before = "2026-11-01T00:00:00Z"
+        not_after = "2026-12-01T00:00:00Z""#);
+    assert!(!eval(&p, "2026-10-31T23:59:59Z").allow);
+    assert_eq!(eval(&p, "2026-11-15T12:00:00Z").until, Some(at("2026-12-01T00:00:00Z")));
+    assert!(!eval(&p, "2026-12-01T00:00:00Z").allow);
+
+    // the window end is clamped to not_after
+    let p = policy(r#"hours = ["18:00-23:00"]
+        not_after = "2026-11-15T20:00:00Z""#);
+    assert_eq!(eval(&p, "2026-11-15T19:00:00Z").until, Some(at("2026-11-15T20:00:00Z")));
+
+    // no bounds at all: always active, no expiry of its own
+    assert_eq!(eval(&policy(""), "2026-11-15T19:00:00Z").until, None);
+}
+
+#[test]
+fn malformed_schedules_are_rejected() {
+    for schedule in [
+        r#"timezone = "Mars/Olympus""#,
+        r#"days = ["funday"]"#,
+        r#"hours = ["19:00"]"#,
+        r#"hours = ["25:00-26:00"]"#,
+        r#"hours = ["19:60-20:00"]"#,
+        r#"start = "19:00""#,
+    ] {
+        let txt = format!("[[rules]]\nname = \"x\"\nprotocol = \"http\"\nresource = \"http://foundry/\"\n[rules.schedule]\n{schedule}\n");
+        assert!(toml::from_str::<Policy>(&txt).is_err(), "{schedule}");
+    }
+}
@@ -0,0 +1,108 @@
+//! Tests for loading policies from directories and includes, and rule source locations.
+
+use appgate_policy::{lint::lint, Policy};
+use std::path::{Path, PathBuf};
+
+fn tree(name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("appgate-sources-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    for (path, txt) in files {
+        let path = dir.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, txt).unwrap();
+    }
+    dir
+}
+
+fn rule(name: &str, resource: &str) -> String {
+    format!("\n[[rules]]\nname = \"{name}\"\nprotocol = \"http\"\nresource = \"{resource}\"\n")
+}
+
+fn load(path: &Path) -> anyhow::Result<Policy> {
+    Policy::load(path.to_str().unwrap())
+}
+
+#[test]
+fn includes_and_directories_merge_in_order_with_sources() {
+    let main = format!("include = [\"teams/\", \"shared.toml\"]\n{}", rule("root", "http://root/"));
+    let dir = tree(
+        "merge",
+        &[
+            ("main.toml", &main),
+            ("shared.toml", &format!("# shared\n{}", rule("shared", "http://shared/"))),
+            ("teams/b.toml", &rule("team-b", "http://b/")),
+            ("teams/a/one.toml", &format!("{}{}", rule("team-a1", "http://a1/"), rule("team-a2", "http://a2/"))),
+            ("teams/a/one.tests.toml", "[[cases]]\nname = \"x\"\nresource = \"http://a1/\"\nexpect = \"allow\"\n"),
+            // reached through both the directory and main.toml's list; loaded once
+            ("teams/shared-link.toml", "include = [\"../shared.toml\"]\n"),
+        ],
+    );
+    let p = load(&dir.join("main.toml")).unwrap();
+    let names: Vec<&str> = p.rules.iter().map(|r| r.name.as_str()).collect();
+    assert_eq!(names, ["root", "team-a1", "team-a2", "team-b", "shared"]);
+
+    let at = |i: usize| p.rules[i].source.as_ref().unwrap().to_string();
+    assert!(at(0).ends_with("main.toml:3"), "{}", at(0));
+    assert!(at(1).ends_with("teams/a/one.toml:2"), "{}", at(1));
+    assert!(at(2).ends_with("teams/a/one.toml:7"), "{}", at(2));
+    assert!(at(4).ends_with("shared.toml:3"), "{}", at(4));
+
+    let (allow, _, reason) = p.decide("http", "http://a2/x", &[]);
+    assert!(allow);
+    assert_eq!(reason, "policy: team-a2");
+    let x = p.explain(&appgate_policy::Context {
+        protocol: "http",
+        resource: "http://a2/x",
+        groups: &[],
+        claims: &Default::default(),
+        attributes: &Default::default(),
+        peer: None,
+        now: chrono::Utc::now(),
+    });
+    assert_eq!(x.decision.source.unwrap().to_string(), at(2));
+
+    // a directory loads the same files, minus main.toml's own ordering
+    let p = load(&dir.join("teams")).unwrap();
+    let names: Vec<&str> = p.rules.iter().map(|r| r.name.as_str()).collect();
+    assert_eq!(names, ["team-a1", "team-a2", "team-b", "shared"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn name_collisions_across_files_are_rejected_with_both_locations() {
+    let dir = tree(
+        "collide",
+        &[
+            ("a.toml", &rule("web", "http://a/")),
+            ("b.toml", &format!("\n\n{}", rule("web", "http://b/"))),
+        ],
+    );
+    let err = format!("{:#}", load(&dir).unwrap_err());
+    assert!(err.contains("rule web is defined twice"), "{err}");
+    assert!(err.contains("a.toml:2 and ") && err.contains("b.toml:4"), "{err}");
+
+    std::fs::write(dir.join("b.toml"), "include = [\"missing.toml\"]\n").unwrap();
+    let err = format!("{:#}", load(&dir).unwrap_err());
+    assert!(err.contains("missing.toml") && err.contains("included from"), "{err}");
+    std::fs::write(dir.join("b.toml"), "rule = []\n").unwrap();
+    assert!(load(&dir).is_err(), "unknown top-level keys are typos");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn lint_findings_point_at_files_and_lines() {
+    let dir = tree(
+        "lint",
+        &[
+            ("a.toml", &format!("{}match = \"prefix\"\npriority = 5\n", rule("broad", "http://app/"))),
+            ("b.toml", &format!("# team b\n{}", rule("narrow", "http://app/reports"))),
+        ],
+    );
+    let p = load(&dir).unwrap();
+    let f = lint(&p);
+    assert_eq!(f.len(), 1, "{f:?}");
+    let msg = f[0].to_string();
+    assert!(msg.contains("b.toml:3: error: rule narrow: never decides: shadowed by rule broad ("), "{msg}");
+    assert!(msg.contains("a.toml:2)"), "{msg}");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
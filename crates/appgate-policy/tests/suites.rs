@@ -0,0 +1,112 @@
+//! Tests for offline policy test suites.
+
+use appgate_policy::{suite::Suite, Policy};
+
+const POLICY: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../../config/policy/foundry.toml");
+
+#[test]
+fn shipped_suite_passes_against_shipped_policy() {
+    let policy = Policy::load(POLICY).unwrap();
+    let suite = Suite::load(concat!(env!("CARGO_MANIFEST_DIR"), "/../../config/policy/foundry.tests.toml")).unwrap();
+    assert!(!suite.cases.is_empty());
+    let failures = suite.run(&policy);
+    assert!(failures.is_empty(), "{}", failures.iter().map(ToString::to_string).collect::<String>());
+}
+
+#[test]
+fn failures_carry_the_problem_and_the_trace() {
+    // the pre-priority foundry policy: file order let foundry-players shadow foundry-admin
+    let policy: Policy = toml::from_str(
+        r#"
+        [[rules]]
+        name = "foundry-players"
+        protocol = "http"
+        resource = "http://foundry/"
+        require_groups = ["foundry-players"]
+
+        [[rules]]
+        name = "foundry-admin"
+        protocol = "http"
+        resource = "http://foundry/"
+        require_groups = ["foundry-admin"]
+        inject = { "X-Role" = "admin", "X-User" = "{{claims.sub}}" }
+        "#,
+    )
+    .unwrap();
+    let suite: Suite = toml::from_str(
+        r#"
+        [[cases]]
+        name = "admins get the admin role header"
+        resource = "http://foundry/"
+        groups = ["foundry-players", "foundry-admin"]
+        claims = { sub = "alice" }
+        expect = "allow"
+        inject = { "X-Role" = "admin", "X-User" = "alice" }
+
+        [[cases]]
+        name = "admin-only users"
+        resource = "http://foundry/"
+        groups = ["foundry-admin"]
+        claims = { sub = "bob" }
+        expect = "allow"
+        reason = "policy: foundry-admin"
+        inject = { "X-Role" = "admin", "X-User" = "bob" }
+
+        [[cases]]
+        name = "strangers"
+        resource = "http://foundry/"
+        expect = "allow"
+        "#,
+    )
+    .unwrap();
+
+    let failures = suite.run(&policy);
+    let names: Vec<&str> = failures.iter().map(|f| f.case.as_str()).collect();
+    assert_eq!(names, ["admins get the admin role header", "strangers"]);
+
+    let report = failures[0].to_string();
+    assert!(report.starts_with("FAIL admins get the admin role header\n"), "{report}");
+    assert!(report.contains(r#"expected inject {X-Role="admin", X-User="alice"}, got {}"#), "{report}");
+    assert!(report.contains("* foundry-players: decided"), "{report}");
+    assert!(report.contains("  foundry-admin: applies, but another rule decided"), "{report}");
+    assert!(failures[1].problems[0].starts_with("expected Allow, got Deny (missing group)"));
+}
+
+#[test]
+fn yaml_suites_with_peers_and_times() {
+    let dir = std::env::temp_dir().join(format!("appgate-suite-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("night.yaml");
+    std::fs::write(
+        &path,
+        r#"
+cases:
+  - name: game night from the VPN
+    resource: http://foundry/
+    peer: 10.8.0.5
+    at: 2026-10-16T18:00:00Z
+    expect: allow
+  - name: daytime
+    resource: http://foundry/
+    peer: 10.8.0.5
+    at: 2026-10-16T10:00:00Z
+    expect: deny
+"#,
+    )
+    .unwrap();
+    let policy: Policy = toml::from_str(
+        r#"
+        [[rules]]
+        name = "night"
+        protocol = "http"
+        resource = "http://foundry/"
+        allow_cidrs = ["10.8.0.0/16"]
+        schedule = { hours = ["17:00-23:00"] }
+        "#,
+    )
+    .unwrap();
+    let suite = Suite::load(&path).unwrap();
+    assert_eq!(suite.cases.len(), 2);
+    assert!(suite.run(&policy).is_empty());
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,60 @@
+//! Tests for identity templates in `inject` values.
+
+use appgate_policy::{template::Template, Policy};
+use std::collections::HashMap;
+
+fn claims(kv: &[(&str, &str)]) -> HashMap<String, String> {
+    kv.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+}
+
+fn render(template: &str, claims: &HashMap<String, String>, groups: &[&str]) -> String {
+    let groups: Vec<String> = groups.iter().map(|g| g.to_string()).collect();
+    Template::parse(template).unwrap().render(claims, &groups)
+}
+
+#[test]
+fn interpolates_claims_and_groups() {
+    let c = claims(&[("sub", "alice-sub"), ("email", "Alice@Example.com")]);
+    assert_eq!(render("{{claims.sub}}", &c, &[]), "alice-sub");
+    assert_eq!(render("user={{ claims.email | lower }};", &c, &[]), "user=alice@example.com;");
+    assert_eq!(render("{{groups|join(',')}}", &c, &["players", "gms"]), "players,gms");
+    assert_eq!(render("{{groups | join(\"; \")}}", &c, &["players", "gms"]), "players; gms");
+    assert_eq!(render("admin", &c, &[]), "admin");
+
+    // missing values render empty unless defaulted
+    assert_eq!(render("{{claims.name}}", &c, &[]), "");
+    assert_eq!(render("{{claims.name|default('anonymous')}}", &c, &[]), "anonymous");
+    assert_eq!(render("{{groups|default('none')}}", &c, &[]), "none");
+}
+
+#[test]
+fn quoted_filter_arguments_may_contain_pipes() {
+    let c = claims(&[]);
+    assert_eq!(render("{{groups|join('|')}}", &c, &["players", "gms"]), "players|gms");
+    assert_eq!(render("{{ groups | join(\" | \") | upper }}", &c, &["players", "gms"]), "PLAYERS | GMS");
+    assert_eq!(render("{{claims.team|default('red|blue')}}", &c, &[]), "red|blue");
+}
+
+#[test]
+fn interpolated_values_are_always_valid_header_values() {
+    let c = claims(&[("name", "Zoë\r\nX-Admin: 1"), ("nick", "  100%  ")]);
+    assert_eq!(render("{{claims.name}}", &c, &[]), "Zo%C3%AB%0D%0AX-Admin: 1");
+    assert_eq!(render("{{claims.nick}}", &c, &[]), "100%25");
+}
+
+#[test]
+fn invalid_templates_and_header_names_are_rejected() {
+    for inject in [
+        r#""X-User" = "{{claims.sub""#,
+        r#""X-User" = "{{sub}}""#,
+        r#""X-User" = "{{claims.}}""#,
+        r#""X-User" = "{{groups|join(,)}}""#,
+        r#""X-User" = "{{groups|shout}}""#,
+        r#""X-User" = "a\nb""#,
+        r#""X User" = "{{claims.sub}}""#,
+    ] {
+        let txt = format!("[[rules]]\nname = \"x\"\nprotocol = \"http\"\nresource = \"http://foundry/\"\n[rules.inject]\n{inject}\n");
+        let p: Policy = toml::from_str(&txt).unwrap();
+        assert!(p.validate().is_err(), "{inject}");
+    }
+}
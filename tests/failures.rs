@@ -15,14 +15,16 @@ async fn network_connectivity_failure() {
 #[tokio::test]
 #[ignore]
 async fn authentication_failure() {
-    // TODO: Stub PDP returns allow=false for missing/invalid token; module should reply 403.
+    // TODO: Stub PDP returns outcome UNAUTHENTICATED for a missing/invalid token; module should
+    // redirect browsers to the login URL (302) and answer API and non-browser clients 401.
     unimplemented!();
 }
 
 #[tokio::test]
 #[ignore]
 async fn authorization_failure() {
-    // TODO: PDP returns allow=false due to missing group; module should reply 403 Forbidden.
+    // TODO: PDP returns outcome FORBIDDEN due to a missing group; module should reply 403 Forbidden
+    // (a valid session is never sent back to login).
     unimplemented!();
 }
 
@@ -40,13 +42,6 @@ async fn invalid_configuration_failure() {
     unimplemented!();
 }
 
-#[tokio::test]
-#[ignore]
-async fn downstream_dependency_failure() {
-    // TODO: Have the upstream return 500 repeatedly; module should trip circuit breaker after N failures.
-    unimplemented!();
-}
-
 #[tokio::test]
 #[ignore]
 async fn upstream_dependency_failure() {